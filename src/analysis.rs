@@ -0,0 +1,420 @@
+//! Static analysis of smart contract bytecode.
+//!
+//! `analyze_module` answers "would this bytecode be accepted?" without
+//! compiling it: the module is only parsed and validated with wasmparser,
+//! against the same features, condom limits and memory page limit as the
+//! runtime engines.
+
+use crate::middlewares::condom::{value_size, CondomViolation, ModuleShape};
+use crate::ExecutionConfig;
+use displaydoc::Display;
+use std::collections::BTreeSet;
+use thiserror::Error;
+use wasmer::sys::Features;
+use wasmer::wasmparser::{
+    BinaryReaderError, CompositeType, DataKind, ElementKind, ExternalKind, Name, NameSectionReader,
+    Parser, Payload, TypeRef, ValType, Validator, WasmFeatures,
+};
+use wasmer_types::Type;
+
+#[derive(Error, Display, Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    /// Too small bytecode
+    TooSmall,
+    /// Unsupported file format for SC: {0}
+    UnsupportedFormat(u8),
+    /// Could not parse the module: {0}
+    Parse(String),
+}
+
+impl From<BinaryReaderError> for AnalysisError {
+    fn from(e: BinaryReaderError) -> Self {
+        AnalysisError::Parse(e.to_string())
+    }
+}
+
+/// Runtime a bytecode is executed with, selected by its first byte
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModuleKind {
+    /// Legacy AssemblyScript module
+    AssemblyScript,
+    /// Agnostic WasmV1 module
+    WasmV1,
+}
+
+/// Kind of an imported or exported item
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExternKind {
+    Function,
+    Table,
+    Memory,
+    Global,
+    Tag,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportInfo {
+    pub module: String,
+    pub name: String,
+    pub kind: ExternKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportInfo {
+    pub name: String,
+    pub kind: ExternKind,
+}
+
+/// Limits of a linear memory, in pages of 64 KiB
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryInfo {
+    pub imported: bool,
+    pub initial_pages: u64,
+    pub maximum_pages: Option<u64>,
+    pub shared: bool,
+    pub memory64: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomSectionInfo {
+    pub name: String,
+    pub size: usize,
+}
+
+/// Result of `analyze_module`
+#[derive(Clone, Debug)]
+pub struct ModuleAnalysis {
+    pub kind: ModuleKind,
+    pub imports: Vec<ImportInfo>,
+    pub exports: Vec<ExportInfo>,
+    pub memories: Vec<MemoryInfo>,
+    pub custom_sections: Vec<CustomSectionInfo>,
+    /// Every condom limit the module breaches, whatever
+    /// `CondomLimits::collect_all_violations` is set to
+    pub condom_violations: Vec<CondomViolation>,
+    /// Validation error, for invalid modules or modules using a feature the
+    /// engines disable (SIMD, threads...)
+    pub validation_error: Option<String>,
+    /// Whether every memory fits in the runtime page limit
+    pub memory_within_limit: bool,
+    /// Whether every table fits in the runtime table element limit
+    pub tables_within_limit: bool,
+}
+
+impl ModuleAnalysis {
+    /// Whether the runtime would accept the module
+    pub fn is_accepted(&self) -> bool {
+        self.validation_error.is_none()
+            && self.condom_violations.is_empty()
+            && self.memory_within_limit
+            && self.tables_within_limit
+    }
+}
+
+impl From<ExternalKind> for ExternKind {
+    fn from(kind: ExternalKind) -> Self {
+        match kind {
+            ExternalKind::Func => ExternKind::Function,
+            ExternalKind::Table => ExternKind::Table,
+            ExternalKind::Memory => ExternKind::Memory,
+            ExternalKind::Global => ExternKind::Global,
+            ExternalKind::Tag => ExternKind::Tag,
+        }
+    }
+}
+
+/// wasmparser features matching the features enabled in the engines
+fn wasmparser_features(features: &Features) -> WasmFeatures {
+    WasmFeatures {
+        threads: features.threads,
+        reference_types: features.reference_types,
+        simd: features.simd,
+        bulk_memory: features.bulk_memory,
+        multi_value: features.multi_value,
+        tail_call: features.tail_call,
+        multi_memory: features.multi_memory,
+        memory64: features.memory64,
+        exceptions: features.exceptions,
+        relaxed_simd: features.relaxed_simd,
+        extended_const: features.extended_const,
+        mutable_global: true,
+        saturating_float_to_int: true,
+        sign_extension: true,
+        floats: true,
+        component_model: false,
+        ..Default::default()
+    }
+}
+
+/// Type of a global value as seen by the condom middleware
+fn global_type(ty: ValType) -> Type {
+    match ty {
+        ValType::I32 => Type::I32,
+        ValType::I64 => Type::I64,
+        ValType::F32 => Type::F32,
+        ValType::F64 => Type::F64,
+        ValType::V128 => Type::V128,
+        ValType::Ref(ty) if ty.is_func_ref() => Type::FuncRef,
+        ValType::Ref(_) => Type::ExternRef,
+    }
+}
+
+/// Parse and validate a smart contract bytecode without compiling it.
+///
+/// The first byte selects the runtime like in `RuntimeModule::new`.
+pub fn analyze_module(
+    bytecode: &[u8],
+    config: &ExecutionConfig,
+) -> Result<ModuleAnalysis, AnalysisError> {
+    if bytecode.len() <= 2 {
+        return Err(AnalysisError::TooSmall);
+    }
+    let (kind, wasm) = match bytecode[0] {
+        // the first byte is part of the `\0asm` magic
+        0 => (ModuleKind::AssemblyScript, bytecode),
+        1 => (ModuleKind::WasmV1, &bytecode[1..]),
+        id => return Err(AnalysisError::UnsupportedFormat(id)),
+    };
+
+    let mut analysis = ModuleAnalysis {
+        kind,
+        imports: Vec::new(),
+        exports: Vec::new(),
+        memories: Vec::new(),
+        custom_sections: Vec::new(),
+        condom_violations: Vec::new(),
+        validation_error: None,
+        memory_within_limit: true,
+        tables_within_limit: true,
+    };
+    let mut shape = ModuleShape::default();
+    let mut custom_section_names = BTreeSet::new();
+
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for rec_group in reader {
+                    for sub_type in rec_group?.types() {
+                        if let CompositeType::Func(func_type) = &sub_type.composite_type {
+                            shape
+                                .signature_lens
+                                .push(func_type.params().len() + func_type.results().len());
+                        }
+                    }
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = import?;
+                    let kind = match import.ty {
+                        TypeRef::Func(_) => {
+                            shape.functions += 1;
+                            ExternKind::Function
+                        }
+                        TypeRef::Table(table) => {
+                            shape.tables += 1;
+                            shape.table_sizes.push(table.initial as usize);
+                            ExternKind::Table
+                        }
+                        TypeRef::Memory(memory) => {
+                            shape.memories += 1;
+                            analysis.memories.push(MemoryInfo {
+                                imported: true,
+                                initial_pages: memory.initial,
+                                maximum_pages: memory.maximum,
+                                shared: memory.shared,
+                                memory64: memory.memory64,
+                            });
+                            ExternKind::Memory
+                        }
+                        TypeRef::Global(global) => {
+                            shape.globals += 1;
+                            shape.globals_data_len += value_size(global_type(global.content_type));
+                            ExternKind::Global
+                        }
+                        TypeRef::Tag(_) => ExternKind::Tag,
+                    };
+                    shape.imports += 1;
+                    analysis.imports.push(ImportInfo {
+                        module: import.module.to_string(),
+                        name: import.name.to_string(),
+                        kind,
+                    });
+                }
+            }
+            Payload::FunctionSection(reader) => shape.functions += reader.count() as usize,
+            Payload::TableSection(reader) => {
+                for table in reader {
+                    shape.tables += 1;
+                    shape.table_sizes.push(table?.ty.initial as usize);
+                }
+            }
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    let memory = memory?;
+                    shape.memories += 1;
+                    analysis.memories.push(MemoryInfo {
+                        imported: false,
+                        initial_pages: memory.initial,
+                        maximum_pages: memory.maximum,
+                        shared: memory.shared,
+                        memory64: memory.memory64,
+                    });
+                }
+            }
+            Payload::GlobalSection(reader) => {
+                for global in reader {
+                    shape.globals += 1;
+                    shape.global_initializers += 1;
+                    shape.globals_data_len += value_size(global_type(global?.ty.content_type));
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    shape.exports += 1;
+                    analysis.exports.push(ExportInfo {
+                        name: export.name.to_string(),
+                        kind: export.kind.into(),
+                    });
+                }
+            }
+            Payload::ElementSection(reader) => {
+                for element in reader {
+                    match element?.kind {
+                        ElementKind::Passive => shape.passive_elements += 1,
+                        ElementKind::Active { .. } => shape.table_initializers += 1,
+                        ElementKind::Declared => (),
+                    }
+                }
+            }
+            Payload::DataSection(reader) => {
+                for data in reader {
+                    if let DataKind::Passive = data?.kind {
+                        shape.passive_data += 1;
+                    }
+                }
+            }
+            Payload::CustomSection(reader) => {
+                if reader.name() == "name" {
+                    let names = NameSectionReader::new(reader.data(), reader.data_offset());
+                    for name in names {
+                        match name? {
+                            Name::Module { name, .. } => shape.name = Some(name.to_string()),
+                            Name::Function(names) => {
+                                for naming in names {
+                                    shape.function_names.push(naming?.name.to_string());
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                custom_section_names.insert(reader.name().to_string());
+                shape
+                    .custom_sections_data
+                    .push((reader.name().to_string(), reader.data().len()));
+                analysis.custom_sections.push(CustomSectionInfo {
+                    name: reader.name().to_string(),
+                    size: reader.data().len(),
+                });
+            }
+            _ => (),
+        }
+    }
+    shape.custom_sections = custom_section_names.len();
+
+    analysis.condom_violations = config.condom_limits().check(&shape);
+    analysis.validation_error =
+        Validator::new_with_features(wasmparser_features(config.features(kind)))
+            .validate_all(wasm)
+            .err()
+            .map(|e| e.to_string());
+    let page_limit = config.max_memory_pages() as u64;
+    analysis.memory_within_limit = analysis.memories.iter().all(|memory| {
+        memory.initial_pages <= page_limit
+            && memory
+                .maximum_pages
+                .map_or(true, |maximum| maximum <= page_limit)
+    });
+    let table_limit = config.max_table_elements() as usize;
+    analysis.tables_within_limit = shape.table_sizes.iter().all(|size| *size <= table_limit);
+
+    Ok(analysis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CondomLimits;
+
+    #[test]
+    fn test_analyze_as_module() {
+        let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
+        let analysis = analyze_module(bytecode, &ExecutionConfig::default()).unwrap();
+        assert_eq!(analysis.kind, ModuleKind::AssemblyScript);
+        assert!(analysis.is_accepted());
+        assert!(analysis
+            .exports
+            .iter()
+            .any(|export| export.name == "main" && export.kind == ExternKind::Function));
+        assert!(analysis
+            .imports
+            .iter()
+            .all(|import| import.module == "massa" || import.module == "env"));
+    }
+
+    #[test]
+    fn test_analyze_rejected_module() {
+        let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/simd.wasm"));
+        let config = ExecutionConfig::builder()
+            .condom_limits(CondomLimits {
+                max_exports: Some(0),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let analysis = analyze_module(bytecode, &config).unwrap();
+        assert!(!analysis.is_accepted());
+        assert!(analysis
+            .validation_error
+            .unwrap()
+            .contains("SIMD support is not enabled"));
+        assert_eq!(analysis.condom_violations.len(), 1);
+    }
+
+    #[test]
+    fn test_analyze_table_and_globals() {
+        let wasm = wasmer::wat2wasm(
+            br#"(module (table 100 funcref) (global i64 (i64.const 0)) (global i32 (i32.const 0)))"#,
+        )
+        .unwrap();
+        let mut bytecode = vec![1];
+        bytecode.extend_from_slice(&wasm);
+        let config = ExecutionConfig::builder()
+            .max_table_elements(10)
+            .condom_limits(CondomLimits {
+                max_globals_data_len: Some(8),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let analysis = analyze_module(&bytecode, &config).unwrap();
+        assert!(!analysis.tables_within_limit);
+        assert_eq!(analysis.condom_violations.len(), 1);
+        assert_eq!(analysis.condom_violations[0].observed, 12);
+        assert!(!analysis.is_accepted());
+    }
+
+    #[test]
+    fn test_analyze_invalid_bytecode() {
+        assert_eq!(
+            analyze_module(&[0], &ExecutionConfig::default()).unwrap_err(),
+            AnalysisError::TooSmall
+        );
+        assert_eq!(
+            analyze_module(&[2, 0, 0, 0], &ExecutionConfig::default()).unwrap_err(),
+            AnalysisError::UnsupportedFormat(2)
+        );
+    }
+}
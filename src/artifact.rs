@@ -0,0 +1,186 @@
+//! Framing of serialized runtime modules.
+//!
+//! A serialized `RuntimeModule` is laid out as:
+//!
+//! | field         | size     | content                                      |
+//! |---------------|----------|----------------------------------------------|
+//! | magic         | 4        | `MSCA`                                       |
+//! | format        | 2        | `ARTIFACT_FORMAT_VERSION`, little endian     |
+//! | version tag   | 1 + n    | length prefixed crate and wasmer versions    |
+//! | module id     | 1        | `RuntimeModuleId`                            |
+//! | compiler      | 1        | `Compiler` the module was compiled with      |
+//! | fingerprint   | 32       | SHA-256 of the compilation settings          |
+//! | checksum      | 32       | SHA-256 of the payload                       |
+//! | payload       | variable | wasmer serialized module                     |
+//!
+//! Wasmer deserialization is unsafe as it loads native code, the frame is
+//! verified beforehand so that corrupted or stale artifacts are rejected with
+//! an `ArtifactError` instead of being loaded.
+
+use crate::{Compiler, ExecutionConfig, ModuleKind};
+use displaydoc::Display;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use wasmer::sys::Features;
+
+const ARTIFACT_MAGIC: &[u8; 4] = b"MSCA";
+const ARTIFACT_FORMAT_VERSION: u16 = 2;
+
+pub(crate) type Fingerprint = [u8; 32];
+
+#[derive(Error, Display, Debug, Clone, PartialEq, Eq)]
+pub enum ArtifactError {
+    /// Artifact is truncated
+    Truncated,
+    /// Invalid artifact magic header
+    BadMagic,
+    /// Unsupported artifact format version: {0}
+    UnsupportedFormat(u16),
+    /// Artifact built by runtime {found}, expected {expected}
+    VersionMismatch { found: String, expected: String },
+    /// Unknown module id: {0}
+    UnknownModuleId(u8),
+    /// Unknown compiler id: {0}
+    UnknownCompilerId(u8),
+    /// Artifact checksum mismatch, the artifact is corrupted
+    ChecksumMismatch,
+    /// Artifact compiled with a different execution config
+    FingerprintMismatch,
+    /// Module deserialization error: {0}
+    Deserialization(String),
+}
+
+/// A verified artifact frame
+pub(crate) struct Artifact<'a> {
+    pub(crate) module_id: u8,
+    pub(crate) compiler: Compiler,
+    pub(crate) fingerprint: Fingerprint,
+    pub(crate) payload: &'a [u8],
+}
+
+fn compiler_id(compiler: &Compiler) -> u8 {
+    match compiler {
+        Compiler::CL => 0,
+        Compiler::SP => 1,
+    }
+}
+
+fn compiler_from_id(id: u8) -> Result<Compiler, ArtifactError> {
+    match id {
+        0 => Ok(Compiler::CL),
+        1 => Ok(Compiler::SP),
+        _ => Err(ArtifactError::UnknownCompilerId(id)),
+    }
+}
+
+/// Runtime and wasmer versions the artifacts are bound to
+fn version_tag() -> String {
+    format!("{}/{}", env!("CARGO_PKG_VERSION"), wasmer::VERSION)
+}
+
+/// Fingerprint of everything baked into a module at compile time
+pub(crate) fn settings_fingerprint(
+    limit: u64,
+    config: &ExecutionConfig,
+    kind: ModuleKind,
+) -> Fingerprint {
+    let mut hasher = Sha256::new();
+    hasher.update(limit.to_le_bytes());
+    config.hash_into(&mut hasher);
+    hash_features(&mut hasher, config.features(kind));
+    hasher.finalize().into()
+}
+
+/// Hash the enabled Wasm features
+pub(crate) fn hash_features(hasher: &mut Sha256, features: &Features) {
+    for enabled in [
+        features.threads,
+        features.reference_types,
+        features.simd,
+        features.bulk_memory,
+        features.multi_value,
+        features.tail_call,
+        features.module_linking,
+        features.multi_memory,
+        features.memory64,
+        features.exceptions,
+        features.relaxed_simd,
+        features.extended_const,
+    ] {
+        hasher.update([enabled as u8]);
+    }
+}
+
+/// Wrap a serialized module into an artifact frame
+pub(crate) fn frame(
+    module_id: u8,
+    compiler: &Compiler,
+    fingerprint: &Fingerprint,
+    payload: &[u8],
+) -> Vec<u8> {
+    let version_tag = version_tag();
+    let mut data = Vec::with_capacity(
+        ARTIFACT_MAGIC.len() + 2 + 1 + version_tag.len() + 2 + 32 + 32 + payload.len(),
+    );
+    data.extend_from_slice(ARTIFACT_MAGIC);
+    data.extend_from_slice(&ARTIFACT_FORMAT_VERSION.to_le_bytes());
+    // the tag is a couple of semver strings, it always fits
+    data.push(version_tag.len() as u8);
+    data.extend_from_slice(version_tag.as_bytes());
+    data.push(module_id);
+    data.push(compiler_id(compiler));
+    data.extend_from_slice(fingerprint);
+    data.extend_from_slice(&Sha256::digest(payload));
+    data.extend_from_slice(payload);
+    data
+}
+
+/// Split `data` at `len`, failing if it is too short
+fn take(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), ArtifactError> {
+    if data.len() < len {
+        return Err(ArtifactError::Truncated);
+    }
+    Ok(data.split_at(len))
+}
+
+/// Verify an artifact frame and return its content
+pub(crate) fn unframe(data: &[u8]) -> Result<Artifact<'_>, ArtifactError> {
+    let (magic, data) = take(data, ARTIFACT_MAGIC.len())?;
+    if magic != ARTIFACT_MAGIC {
+        return Err(ArtifactError::BadMagic);
+    }
+
+    let (format, data) = take(data, 2)?;
+    let format = u16::from_le_bytes([format[0], format[1]]);
+    if format != ARTIFACT_FORMAT_VERSION {
+        return Err(ArtifactError::UnsupportedFormat(format));
+    }
+
+    let (tag_len, data) = take(data, 1)?;
+    let (tag, data) = take(data, tag_len[0] as usize)?;
+    let expected = version_tag();
+    if tag != expected.as_bytes() {
+        return Err(ArtifactError::VersionMismatch {
+            found: String::from_utf8_lossy(tag).into_owned(),
+            expected,
+        });
+    }
+
+    let (module_id, data) = take(data, 1)?;
+    let (compiler, data) = take(data, 1)?;
+    let compiler = compiler_from_id(compiler[0])?;
+    let (fingerprint, data) = take(data, 32)?;
+    let (checksum, payload) = take(data, 32)?;
+    if Sha256::digest(payload).as_slice() != checksum {
+        return Err(ArtifactError::ChecksumMismatch);
+    }
+
+    Ok(Artifact {
+        module_id: module_id[0],
+        compiler,
+        fingerprint: fingerprint
+            .try_into()
+            .expect("fingerprint is 32 bytes long"),
+        payload,
+    })
+}
@@ -10,15 +10,18 @@ use function_name::named;
 use std::ops::Add;
 use wasmer::{AsStoreMut, AsStoreRef, FunctionEnvMut, Memory};
 
-use super::env::{get_remaining_points, sub_remaining_gas_abi, ASEnv};
-use crate::{as_execution::ABIError, settings};
+use super::env::{get_remaining_points, sub_remaining_gas_abi, ASEnv, Metered};
+use crate::as_execution::ABIError;
+use crate::error::AbortLocation;
 #[cfg(feature = "execution-trace")]
 use crate::{
     into_trace_value,
     types::{AbiTrace, AbiTraceType},
 };
 
-use super::common::{call_module, create_sc, function_exists, local_call};
+use super::common::{
+    call_module, create_sc, encode_try_call_outcome, function_exists, local_call, try_call_module,
+};
 use super::error::{abi_bail, ABIResult};
 
 macro_rules! get_memory {
@@ -30,6 +33,18 @@ macro_rules! get_memory {
     };
 }
 
+/// Records the trace of the ABI call started by `sub_remaining_gas_abi`.
+#[cfg(feature = "execution-trace")]
+fn push_trace(ctx: &mut FunctionEnvMut<ASEnv>, mut trace: AbiTrace) -> ABIResult<()> {
+    let (env, mut store) = ctx.data_and_store_mut();
+    if let Some(abi_call) = env.get_abi_call().lock().take() {
+        let gas_after = get_remaining_points(&*env, &mut store)?;
+        abi_call.finish(&mut trace, gas_after, env.get_config().call_depth(), None);
+    }
+    env.trace.push(trace);
+    Ok(())
+}
+
 /// Retrieves the AssemblyScript environment.
 ///
 /// Fails during instantiation to avoid gas manipulation in the WASM start
@@ -51,12 +66,16 @@ pub(crate) fn assembly_script_get_call_coins(mut ctx: FunctionEnvMut<ASEnv>) ->
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let res = env.get_interface().get_call_coins()? as i64;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res)
 }
 
@@ -70,6 +89,7 @@ pub(crate) fn assembly_script_transfer_coins(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     if raw_amount.is_negative() {
         abi_bail!("Negative raw amount.");
     }
@@ -92,16 +112,20 @@ pub(crate) fn assembly_script_transfer_coins(
             .cloned()
             .unwrap_or_else(|| "".to_string());
 
-        ctx.data_mut().trace.push(AbiTrace {
-            name: function_name!().to_string(),
-            params: vec![
-                into_trace_value!(from_address),
-                into_trace_value!(to_address),
-                (stringify!(raw_amount), raw_amount as u64).into(),
-            ],
-            return_value: AbiTraceType::None,
-            sub_calls: None,
-        });
+        push_trace(
+            &mut ctx,
+            AbiTrace {
+                name: function_name!().to_string(),
+                params: vec![
+                    into_trace_value!(from_address),
+                    into_trace_value!(to_address),
+                    (stringify!(raw_amount), raw_amount as u64).into(),
+                ],
+                return_value: AbiTraceType::None,
+                sub_calls: None,
+                ..Default::default()
+            },
+        )?;
     }
     Ok(())
 }
@@ -116,6 +140,7 @@ pub(crate) fn assembly_script_transfer_coins_for(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     if raw_amount.is_negative() {
         abi_bail!("Negative raw amount.");
     }
@@ -132,16 +157,20 @@ pub(crate) fn assembly_script_transfer_coins_for(
     env.get_interface()
         .transfer_coins_for(&from_address, &to_address, raw_amount as u64)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(from_address),
-            into_trace_value!(to_address),
-            (stringify!(raw_amount), raw_amount as u64).into(),
-        ],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(from_address),
+                into_trace_value!(to_address),
+                (stringify!(raw_amount), raw_amount as u64).into(),
+            ],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -151,12 +180,16 @@ pub(crate) fn assembly_script_get_balance(mut ctx: FunctionEnvMut<ASEnv>) -> ABI
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let res = env.get_interface().get_balance()? as i64;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res)
 }
 
@@ -176,12 +209,16 @@ pub(crate) fn assembly_script_get_balance_for(
     // }
     let res = env.get_interface().get_balance_for(&address)? as i64;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address)],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address)],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res)
 }
 
@@ -197,6 +234,10 @@ pub(crate) fn assembly_script_call(
 ) -> ABIResult<i32> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    // sending coins modifies the ledger
+    if call_coins != 0 {
+        env.get_config().check_writable(function_name!())?;
+    }
     let memory = get_memory!(env);
     let address = read_string(memory, &ctx, address)?;
     let function = read_string(memory, &ctx, function)?;
@@ -214,17 +255,21 @@ pub(crate) fn assembly_script_call(
 
     let response = call_module(&mut ctx, &address, &function, &param, call_coins)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            ("address", address.clone()).into(),
-            ("function", function.clone()).into(),
-            into_trace_value!(param),
-            into_trace_value!(call_coins),
-        ],
-        return_value: response.ret.clone().into(),
-        sub_calls: Some(response.trace),
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                ("address", address.clone()).into(),
+                ("function", function.clone()).into(),
+                into_trace_value!(param),
+                into_trace_value!(call_coins),
+            ],
+            return_value: response.ret.clone().into(),
+            sub_calls: Some(response.trace),
+            ..Default::default()
+        },
+    )?;
     match BufferPtr::alloc(&response.ret, env.get_ffi_env(), &mut ctx) {
         Ok(ret) => Ok(ret.offset() as i32),
         _ => abi_bail!(format!(
@@ -234,18 +279,76 @@ pub(crate) fn assembly_script_call(
     }
 }
 
+/// Same as `assembly_script_call` but the failure of the callee is returned
+/// instead of making the caller fail, see `encode_try_call_outcome` for the
+/// layout of the returned buffer. The callee gets at most `max_gas`.
+#[named]
+pub(crate) fn assembly_script_try_call(
+    mut ctx: FunctionEnvMut<ASEnv>,
+    address: i32,
+    function: i32,
+    param: i32,
+    call_coins: i64,
+    max_gas: i64,
+) -> ABIResult<i32> {
+    let env = get_env(&ctx)?;
+    sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    // sending coins modifies the ledger
+    if call_coins != 0 {
+        env.get_config().check_writable(function_name!())?;
+    }
+    let memory = get_memory!(env);
+    let address = read_string(memory, &ctx, address)?;
+    let function = read_string(memory, &ctx, function)?;
+    let param = read_buffer(memory, &ctx, param)?;
+
+    let outcome = try_call_module(&mut ctx, &address, &function, &param, call_coins, max_gas)?;
+    let ret = encode_try_call_outcome(&outcome);
+    #[cfg(feature = "execution-trace")]
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                ("address", address.clone()).into(),
+                ("function", function.clone()).into(),
+                into_trace_value!(param),
+                into_trace_value!(call_coins),
+                into_trace_value!(max_gas),
+            ],
+            return_value: ret.clone().into(),
+            sub_calls: Some(match outcome {
+                Ok(response) => response.trace,
+                Err(failure) => failure.trace,
+            }),
+            ..Default::default()
+        },
+    )?;
+    match BufferPtr::alloc(&ret, env.get_ffi_env(), &mut ctx) {
+        Ok(ret) => Ok(ret.offset() as i32),
+        _ => abi_bail!(format!(
+            "Cannot allocate response in try call {}::{}",
+            address, function
+        )),
+    }
+}
+
 #[named]
 pub(crate) fn assembly_script_get_remaining_gas(mut ctx: FunctionEnvMut<ASEnv>) -> ABIResult<i64> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let res = get_remaining_points(&env, &mut ctx)? as i64;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res)
 }
 
@@ -268,12 +371,16 @@ pub(crate) fn assembly_script_print(mut ctx: FunctionEnvMut<ASEnv>, arg: i32) ->
 
     env.get_interface().print(&message)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(message)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(message)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -285,17 +392,24 @@ pub(crate) fn assembly_script_get_op_keys(mut ctx: FunctionEnvMut<ASEnv>) -> ABI
     match env.get_interface().get_op_keys(None) {
         Err(err) => abi_bail!(err),
         Ok(keys) => {
-            let fmt_keys =
-                ser_bytearray_vec(&keys, keys.len(), settings::max_op_datastore_entry_count())?;
+            let fmt_keys = ser_bytearray_vec(
+                &keys,
+                keys.len(),
+                env.get_config().max_op_datastore_entry_count(),
+            )?;
             let ptr = pointer_from_bytearray(&env, &mut ctx, &fmt_keys)?.offset();
 
             #[cfg(feature = "execution-trace")]
-            ctx.data_mut().trace.push(AbiTrace {
-                name: function_name!().to_string(),
-                params: vec![],
-                return_value: fmt_keys.into(),
-                sub_calls: None,
-            });
+            push_trace(
+                &mut ctx,
+                AbiTrace {
+                    name: function_name!().to_string(),
+                    params: vec![],
+                    return_value: fmt_keys.into(),
+                    sub_calls: None,
+                    ..Default::default()
+                },
+            )?;
             Ok(ptr as i32)
         }
     }
@@ -319,17 +433,24 @@ pub(crate) fn assembly_script_get_op_keys_prefix(
     match env.get_interface().get_op_keys(prefix_opt) {
         Err(err) => abi_bail!(err),
         Ok(keys) => {
-            let fmt_keys =
-                ser_bytearray_vec(&keys, keys.len(), settings::max_op_datastore_entry_count())?;
+            let fmt_keys = ser_bytearray_vec(
+                &keys,
+                keys.len(),
+                env.get_config().max_op_datastore_entry_count(),
+            )?;
             let ptr = pointer_from_bytearray(&env, &mut ctx, &fmt_keys)?.offset();
 
             #[cfg(feature = "execution-trace")]
-            ctx.data_mut().trace.push(AbiTrace {
-                name: function_name!().to_string(),
-                params: vec![into_trace_value!(prefix)],
-                return_value: AbiTraceType::ByteArray(fmt_keys),
-                sub_calls: None,
-            });
+            push_trace(
+                &mut ctx,
+                AbiTrace {
+                    name: function_name!().to_string(),
+                    params: vec![into_trace_value!(prefix)],
+                    return_value: AbiTraceType::ByteArray(fmt_keys),
+                    sub_calls: None,
+                    ..Default::default()
+                },
+            )?;
             Ok(ptr as i32)
         }
     }
@@ -361,12 +482,16 @@ pub(crate) fn assembly_script_has_op_key(
             let a = pointer_from_bytearray(&env, &mut ctx, &b_vec)?.offset();
 
             #[cfg(feature = "execution-trace")]
-            ctx.data_mut().trace.push(AbiTrace {
-                name: function_name!().to_string(),
-                params: vec![into_trace_value!(key_bytes)],
-                return_value: b_vec.into(),
-                sub_calls: None,
-            });
+            push_trace(
+                &mut ctx,
+                AbiTrace {
+                    name: function_name!().to_string(),
+                    params: vec![into_trace_value!(key_bytes)],
+                    return_value: b_vec.into(),
+                    sub_calls: None,
+                    ..Default::default()
+                },
+            )?;
 
             Ok(a as i32)
         }
@@ -392,12 +517,16 @@ pub(crate) fn assembly_script_get_op_data(
     let ptr = pointer_from_bytearray(&env, &mut ctx, &data)?.offset() as i32;
 
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(key_bytes)],
-        return_value: data.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(key_bytes)],
+            return_value: data.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -410,6 +539,7 @@ pub(crate) fn assembly_script_create_sc(
 ) -> ABIResult<i32> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let bytecode: Vec<u8> = read_buffer(memory, &ctx, bytecode)?;
     // Do not remove this. It could be used for gas_calibration in future.
@@ -421,12 +551,16 @@ pub(crate) fn assembly_script_create_sc(
     let ptr = StringPtr::alloc(&address, env.get_ffi_env(), &mut ctx)?.offset() as i32;
 
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(bytecode)],
-        return_value: address.clone().into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(bytecode)],
+            return_value: address.clone().into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -445,12 +579,16 @@ pub(crate) fn assembly_script_hash(mut ctx: FunctionEnvMut<ASEnv>, value: i32) -
     let hash = env.get_interface().hash(&bytes)?.to_vec();
     let ptr = pointer_from_bytearray(&env, &mut ctx, &hash)?.offset();
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(bytes)],
-        return_value: hash.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(bytes)],
+            return_value: hash.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr as i32)
 }
 
@@ -468,12 +606,16 @@ pub(crate) fn assembly_script_keccak256_hash(
     let ptr = pointer_from_bytearray(&env, &mut ctx, &hash)?.offset();
 
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(bytes)],
-        return_value: hash.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(bytes)],
+            return_value: hash.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
 
     Ok(ptr as i32)
 }
@@ -494,16 +636,24 @@ pub(crate) fn assembly_script_get_keys(
         None
     };
     let keys = env.get_interface().get_keys(prefix_opt)?;
-    let fmt_keys = ser_bytearray_vec(&keys, keys.len(), settings::max_datastore_entry_count())?;
+    let fmt_keys = ser_bytearray_vec(
+        &keys,
+        keys.len(),
+        env.get_config().max_datastore_entry_count(),
+    )?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &fmt_keys)?.offset();
 
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(prefix)],
-        return_value: fmt_keys.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(prefix)],
+            return_value: fmt_keys.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr as i32)
 }
 
@@ -525,16 +675,24 @@ pub(crate) fn assembly_script_get_keys_for(
         None
     };
     let keys = env.get_interface().get_keys_for(&address, prefix_opt)?;
-    let fmt_keys = ser_bytearray_vec(&keys, keys.len(), settings::max_datastore_entry_count())?;
+    let fmt_keys = ser_bytearray_vec(
+        &keys,
+        keys.len(),
+        env.get_config().max_datastore_entry_count(),
+    )?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &fmt_keys)?.offset();
 
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address), into_trace_value!(prefix)],
-        return_value: AbiTraceType::ByteArrays(keys.iter().cloned().collect()),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address), into_trace_value!(prefix)],
+            return_value: AbiTraceType::ByteArrays(keys.iter().cloned().collect()),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr as i32)
 }
 
@@ -548,6 +706,7 @@ pub(crate) fn assembly_script_set_data(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let key = read_buffer(memory, &ctx, key)?;
     let value = read_buffer(memory, &ctx, value)?;
@@ -572,12 +731,16 @@ pub(crate) fn assembly_script_set_data(
 
     env.get_interface().raw_set_data(&key, &value)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(key), into_trace_value!(value)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(key), into_trace_value!(value)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -591,6 +754,7 @@ pub(crate) fn assembly_script_append_data(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let key = read_buffer(memory, &ctx, key)?;
     let value = read_buffer(memory, &ctx, value)?;
@@ -603,12 +767,16 @@ pub(crate) fn assembly_script_append_data(
     // }
     env.get_interface().raw_append_data(&key, &value)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(key), into_trace_value!(value)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(key), into_trace_value!(value)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -627,12 +795,16 @@ pub(crate) fn assembly_script_get_data(mut ctx: FunctionEnvMut<ASEnv>, key: i32)
     let data = env.get_interface().raw_get_data(&key)?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &data)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(key)],
-        return_value: data.clone().into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(key)],
+            return_value: data.clone().into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -650,12 +822,16 @@ pub(crate) fn assembly_script_has_data(mut ctx: FunctionEnvMut<ASEnv>, key: i32)
     // }
     let res = env.get_interface().has_data(&key)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(key)],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(key)],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i32)
 }
 
@@ -668,6 +844,7 @@ pub(crate) fn assembly_script_delete_data(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let key = read_buffer(memory, &ctx, key)?;
     // Do not remove this. It could be used for gas_calibration in future.
@@ -677,12 +854,16 @@ pub(crate) fn assembly_script_delete_data(
     // }
     env.get_interface().raw_delete_data(&key)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(key)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(key)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -697,6 +878,7 @@ pub(crate) fn assembly_script_set_data_for(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let key = read_buffer(memory, &ctx, key)?;
     let value = read_buffer(memory, &ctx, value)?;
@@ -713,16 +895,20 @@ pub(crate) fn assembly_script_set_data_for(
     env.get_interface()
         .raw_set_data_for(&address, &key, &value)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(address),
-            into_trace_value!(key),
-            into_trace_value!(value),
-        ],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(address),
+                into_trace_value!(key),
+                into_trace_value!(value),
+            ],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -737,6 +923,7 @@ pub(crate) fn assembly_script_append_data_for(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let key = read_buffer(memory, &ctx, key)?;
     let value = read_buffer(memory, &ctx, value)?;
@@ -753,16 +940,20 @@ pub(crate) fn assembly_script_append_data_for(
     env.get_interface()
         .raw_append_data_for(&address, &key, &value)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(address),
-            into_trace_value!(key),
-            into_trace_value!(value),
-        ],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(address),
+                into_trace_value!(key),
+                into_trace_value!(value),
+            ],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -790,12 +981,16 @@ pub(crate) fn assembly_script_get_data_for(
     let data = env.get_interface().raw_get_data_for(&address, &key)?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &data)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address), into_trace_value!(key)],
-        return_value: data.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address), into_trace_value!(key)],
+            return_value: data.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -809,6 +1004,7 @@ pub(crate) fn assembly_script_delete_data_for(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let address = read_string(memory, &ctx, address)?;
     let key = read_buffer(memory, &ctx, key)?;
@@ -821,12 +1017,16 @@ pub(crate) fn assembly_script_delete_data_for(
     // }
     env.get_interface().raw_delete_data_for(&address, &key)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address), into_trace_value!(key)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address), into_trace_value!(key)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -850,12 +1050,16 @@ pub(crate) fn assembly_script_has_data_for(
     // }
     let res = env.get_interface().has_data_for(&address, &key)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address), into_trace_value!(key)],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address), into_trace_value!(key)],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i32)
 }
 
@@ -870,12 +1074,16 @@ pub(crate) fn assembly_script_get_owned_addresses(
     #[allow(clippy::let_and_return)]
     let ptr = alloc_string_array(&mut ctx, &data);
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: data.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: data.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     ptr
 }
 
@@ -888,12 +1096,16 @@ pub(crate) fn assembly_script_get_call_stack(mut ctx: FunctionEnvMut<ASEnv>) ->
     #[allow(clippy::let_and_return)]
     let ptr = alloc_string_array(&mut ctx, &data);
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: data.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: data.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     ptr
 }
 
@@ -913,12 +1125,16 @@ pub(crate) fn assembly_script_generate_event(
     // }
     env.get_interface().generate_event(event.clone())?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(event)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(event)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -950,16 +1166,20 @@ pub(crate) fn assembly_script_signature_verify(
         .get_interface()
         .signature_verify(data.as_bytes(), &signature, &public_key)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(data.as_bytes().to_vec()),
-            into_trace_value!(signature),
-            into_trace_value!(public_key),
-        ],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(data.as_bytes().to_vec()),
+                into_trace_value!(signature),
+                into_trace_value!(public_key),
+            ],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i32)
 }
 
@@ -982,16 +1202,20 @@ pub(crate) fn assembly_script_evm_signature_verify(
         .get_interface()
         .evm_signature_verify(&data, &signature, &public_key)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(data),
-            into_trace_value!(signature),
-            into_trace_value!(public_key),
-        ],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(data),
+                into_trace_value!(signature),
+                into_trace_value!(public_key),
+            ],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i32)
 }
 
@@ -1010,12 +1234,16 @@ pub(crate) fn assembly_script_evm_get_address_from_pubkey(
         .evm_get_address_from_pubkey(&public_key)?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &address)?.offset();
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(public_key)],
-        return_value: address.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(public_key)],
+            return_value: address.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr as i32)
 }
 
@@ -1036,12 +1264,16 @@ pub(crate) fn assembly_script_evm_get_pubkey_from_signature(
         .evm_get_pubkey_from_signature(&data, &signature)?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &public_key)?.offset();
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(data), into_trace_value!(signature)],
-        return_value: public_key.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(data), into_trace_value!(signature)],
+            return_value: public_key.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr as i32)
 }
 
@@ -1057,12 +1289,16 @@ pub(crate) fn assembly_script_is_address_eoa(
     let address = read_string(memory, &ctx, address)?;
     let res = env.get_interface().is_address_eoa(&address)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address)],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address)],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i32)
 }
 
@@ -1084,12 +1320,16 @@ pub(crate) fn assembly_script_address_from_public_key(
     let addr = env.get_interface().address_from_public_key(&public_key)?;
     let ptr = pointer_from_string(&env, &mut ctx, &addr)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(public_key)],
-        return_value: addr.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(public_key)],
+            return_value: addr.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -1105,12 +1345,16 @@ pub(crate) fn assembly_script_validate_address(
     let address = read_string(memory, &ctx, address)?;
     let res = env.get_interface().validate_address(&address)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address)],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address)],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i32)
 }
 
@@ -1121,12 +1365,16 @@ pub(crate) fn assembly_script_unsafe_random(mut ctx: FunctionEnvMut<ASEnv>) -> A
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let res = env.get_interface().unsafe_random()?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res)
 }
 
@@ -1137,12 +1385,16 @@ pub(crate) fn assembly_script_get_time(mut ctx: FunctionEnvMut<ASEnv>) -> ABIRes
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let res = env.get_interface().get_time()?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: res.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: res.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(res as i64)
 }
 
@@ -1166,6 +1418,7 @@ pub(crate) fn assembly_script_send_message(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let validity_start: (u64, u8) = match (
         validity_start_period.try_into(),
         validity_start_thread.try_into(),
@@ -1225,25 +1478,29 @@ pub(crate) fn assembly_script_send_message(
     )?;
 
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(target_address),
-            into_trace_value!(target_handler),
-            into_trace_value!(validity_start_period),
-            into_trace_value!(validity_start_thread),
-            into_trace_value!(validity_end_period),
-            into_trace_value!(validity_end_thread),
-            into_trace_value!(max_gas as u64),
-            into_trace_value!(raw_fee as u64),
-            into_trace_value!(raw_coins as u64),
-            into_trace_value!(data),
-            into_trace_value!(filter_address_string),
-            into_trace_value!(key),
-        ],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(target_address),
+                into_trace_value!(target_handler),
+                into_trace_value!(validity_start_period),
+                into_trace_value!(validity_start_thread),
+                into_trace_value!(validity_end_period),
+                into_trace_value!(validity_end_thread),
+                into_trace_value!(max_gas as u64),
+                into_trace_value!(raw_fee as u64),
+                into_trace_value!(raw_coins as u64),
+                into_trace_value!(data),
+                into_trace_value!(filter_address_string),
+                into_trace_value!(key),
+            ],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
 
     Ok(())
 }
@@ -1261,12 +1518,16 @@ pub(crate) fn assembly_script_get_origin_operation_id(
         .unwrap_or_default();
     let ptr = pointer_from_string(&env, &mut ctx, &operation_id)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: operation_id.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: operation_id.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -1277,12 +1538,16 @@ pub(crate) fn assembly_script_get_current_period(mut ctx: FunctionEnvMut<ASEnv>)
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let current_period = env.get_interface().get_current_period()?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: current_period.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: current_period.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(current_period as i64)
 }
 
@@ -1293,12 +1558,16 @@ pub(crate) fn assembly_script_get_current_thread(mut ctx: FunctionEnvMut<ASEnv>)
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let current_thread = env.get_interface().get_current_thread()?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: current_thread.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: current_thread.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(current_thread as i32)
 }
 
@@ -1311,6 +1580,7 @@ pub(crate) fn assembly_script_set_bytecode_for(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let address = read_string(memory, &ctx, address)?;
     let bytecode_raw = read_buffer(memory, &ctx, bytecode)?;
@@ -1324,12 +1594,16 @@ pub(crate) fn assembly_script_set_bytecode_for(
     env.get_interface()
         .raw_set_bytecode_for(&address, &bytecode_raw)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address), into_trace_value!(bytecode_raw)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address), into_trace_value!(bytecode_raw)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -1341,6 +1615,7 @@ pub(crate) fn assembly_script_set_bytecode(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let bytecode_raw = read_buffer(memory, &ctx, bytecode)?;
     // Do not remove this. It could be used for gas_calibration in future.
@@ -1350,12 +1625,16 @@ pub(crate) fn assembly_script_set_bytecode(
     // }
     env.get_interface().raw_set_bytecode(&bytecode_raw)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(bytecode_raw)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(bytecode_raw)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -1367,12 +1646,16 @@ pub(crate) fn assembly_script_get_bytecode(mut ctx: FunctionEnvMut<ASEnv>) -> AB
     let data = env.get_interface().raw_get_bytecode()?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &data)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: data.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: data.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -1389,12 +1672,16 @@ pub(crate) fn assembly_script_get_bytecode_for(
     let data = env.get_interface().raw_get_bytecode_for(&address)?;
     let ptr = pointer_from_bytearray(&env, &mut ctx, &data)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address)],
-        return_value: data.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address)],
+            return_value: data.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -1422,16 +1709,20 @@ pub(crate) fn assembly_script_local_execution(
         )),
     };
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(bytecode),
-            into_trace_value!(function),
-            into_trace_value!(param),
-        ],
-        return_value: response.ret.clone().into(),
-        sub_calls: Some(response.trace),
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(bytecode),
+                into_trace_value!(function),
+                into_trace_value!(param),
+            ],
+            return_value: response.ret.clone().into(),
+            sub_calls: Some(response.trace),
+            ..Default::default()
+        },
+    )?;
     res
 }
 
@@ -1462,16 +1753,20 @@ pub(crate) fn assembly_script_local_call(
         )),
     };
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(bytecode),
-            into_trace_value!(function),
-            into_trace_value!(param),
-        ],
-        return_value: response.ret.clone().into(),
-        sub_calls: Some(response.trace),
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(bytecode),
+                into_trace_value!(function),
+                into_trace_value!(param),
+            ],
+            return_value: response.ret.clone().into(),
+            sub_calls: Some(response.trace),
+            ..Default::default()
+        },
+    )?;
 
     res
 }
@@ -1483,12 +1778,16 @@ pub fn assembly_script_caller_has_write_access(mut ctx: FunctionEnvMut<ASEnv>) -
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let has_write_access = env.get_interface().caller_has_write_access()?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: has_write_access.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: has_write_access.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(has_write_access as i32)
 }
 
@@ -1506,12 +1805,16 @@ pub fn assembly_script_function_exists(
     let function = read_string(memory, &ctx, function)?;
     let function_exists = function_exists(&mut ctx, &address, &function)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(address), into_trace_value!(function)],
-        return_value: function_exists.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(address), into_trace_value!(function)],
+            return_value: function_exists.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(function_exists as i32)
 }
 
@@ -1522,12 +1825,16 @@ pub(crate) fn assembly_script_chain_id(mut ctx: FunctionEnvMut<ASEnv>) -> ABIRes
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
     let chain_id = env.get_interface().chain_id()?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: chain_id.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: chain_id.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(chain_id as u64)
 }
 
@@ -1567,16 +1874,20 @@ pub(crate) fn assembly_script_get_deferred_call_quote(
         price = 0;
     }
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(deferred_call_period),
-            into_trace_value!(deferred_call_thread),
-            into_trace_value!(max_gas),
-        ],
-        return_value: price.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(deferred_call_period),
+                into_trace_value!(deferred_call_thread),
+                into_trace_value!(max_gas),
+            ],
+            return_value: price.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(price)
 }
 
@@ -1595,6 +1906,7 @@ pub(crate) fn assembly_script_deferred_call_register(
 ) -> ABIResult<i32> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let asc_target_slot: (u64, u8) = match (target_period.try_into(), target_thread.try_into()) {
         (Ok(p), Ok(t)) => (p, t),
         (Err(_), _) => abi_bail!("negative validity end period"),
@@ -1625,20 +1937,24 @@ pub(crate) fn assembly_script_deferred_call_register(
     )?;
     let ptr = pointer_from_string(&env, &mut ctx, &response)?.offset() as i32;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(target_address),
-            into_trace_value!(target_function),
-            into_trace_value!(target_period),
-            into_trace_value!(target_thread),
-            into_trace_value!(max_gas as u64),
-            into_trace_value!(raw_coins as u64),
-            into_trace_value!(params),
-        ],
-        return_value: response.to_owned().into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(target_address),
+                into_trace_value!(target_function),
+                into_trace_value!(target_period),
+                into_trace_value!(target_thread),
+                into_trace_value!(max_gas as u64),
+                into_trace_value!(raw_coins as u64),
+                into_trace_value!(params),
+            ],
+            return_value: response.to_owned().into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr)
 }
 
@@ -1654,12 +1970,16 @@ pub(crate) fn assembly_script_deferred_call_exists(
     let asc_id = read_string(memory, &ctx, deferred_id)?;
     let exists = env.get_interface().deferred_call_exists(&asc_id)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(asc_id)],
-        return_value: exists.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(asc_id)],
+            return_value: exists.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(exists as i32)
 }
 
@@ -1671,16 +1991,21 @@ pub(crate) fn assembly_script_deferred_call_cancel(
 ) -> ABIResult<()> {
     let env = get_env(&ctx)?;
     sub_remaining_gas_abi(&env, &mut ctx, function_name!())?;
+    env.get_config().check_writable(function_name!())?;
     let memory = get_memory!(env);
     let deferred_id = read_string(memory, &ctx, deferred_call_id)?;
     env.get_interface().deferred_call_cancel(&deferred_id)?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(deferred_id)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(deferred_id)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
 
     Ok(())
 }
@@ -1712,24 +2037,29 @@ pub fn assembly_script_abort(
         abi_bail!("aborting failed to load message or filename")
     }
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![
-            into_trace_value!(message_.clone().unwrap_or_default()),
-            into_trace_value!(filename_.clone().unwrap_or_default()),
-            into_trace_value!(line),
-            into_trace_value!(col),
-        ],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
-    abi_bail!(format!(
-        "error: {} at {}:{} col: {}",
-        message_.unwrap(),
-        filename_.unwrap(),
-        line,
-        col
-    ));
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![
+                into_trace_value!(message_.clone().unwrap_or_default()),
+                into_trace_value!(filename_.clone().unwrap_or_default()),
+                into_trace_value!(line),
+                into_trace_value!(col),
+            ],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
+    Err(ABIError::GuestAbort {
+        message: message_.unwrap(),
+        location: AbortLocation {
+            file: filename_.unwrap(),
+            line,
+            col,
+        },
+    })
 }
 
 /// Assembly script builtin `seed` function
@@ -1744,12 +2074,16 @@ pub fn assembly_script_seed(mut ctx: FunctionEnvMut<ASEnv>) -> ABIResult<f64> {
         _ => abi_bail!("failed to get random from interface"),
     };
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: seed.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: seed.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(seed)
 }
 
@@ -1766,12 +2100,16 @@ pub fn assembly_script_date_now(mut ctx: FunctionEnvMut<ASEnv>) -> ABIResult<f64
     };
     let ret = utime as f64;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![],
-        return_value: ret.into(),
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![],
+            return_value: ret.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ret)
 }
 
@@ -1867,12 +2205,16 @@ fn assembly_script_console(
 
     env.get_interface().generate_event(message.clone())?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(message)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(message)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -1922,12 +2264,16 @@ pub fn assembly_script_trace(
     env.get_interface()
         .generate_event(message_for_event.clone())?;
     #[cfg(feature = "execution-trace")]
-    ctx.data_mut().trace.push(AbiTrace {
-        name: function_name!().to_string(),
-        params: vec![into_trace_value!(message_for_event)],
-        return_value: AbiTraceType::None,
-        sub_calls: None,
-    });
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(message_for_event)],
+            return_value: AbiTraceType::None,
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(())
 }
 
@@ -2019,6 +2365,17 @@ pub(crate) fn assembly_script_hash_sha256(
     let bytes = read_buffer(memory, &ctx, bytes)?;
     let hash = env.get_interface().hash_sha256(&bytes)?.to_vec();
     let ptr = pointer_from_bytearray(&env, &mut ctx, &hash)?.offset();
+    #[cfg(feature = "execution-trace")]
+    push_trace(
+        &mut ctx,
+        AbiTrace {
+            name: function_name!().to_string(),
+            params: vec![into_trace_value!(bytes)],
+            return_value: hash.into(),
+            sub_calls: None,
+            ..Default::default()
+        },
+    )?;
     Ok(ptr as i32)
 }
 
@@ -2081,4 +2438,32 @@ mod tests {
         assert_eq!(vb_ser[vb_ser.len() - 2], 1);
         assert_eq!(vb_ser[vb_ser.len() - 1], 254);
     }
+
+    #[test]
+    fn test_ser_try_call_failure() {
+        use crate::as_execution::common::encode_try_call_outcome;
+        use crate::error::{ExecutionFailure, GasUsage};
+        use crate::VMError;
+
+        let failure = ExecutionFailure::from(VMError::OutOfGas {
+            function: "f".to_string(),
+            gas: GasUsage {
+                init_gas_cost: 10,
+                gas_used: 300,
+            },
+        });
+        let message = failure.error.to_string();
+        let ser = encode_try_call_outcome(&Err(failure));
+        // Expected:
+        // success: false
+        // kind: 8 as u32 (little endian), then "OutOfGas"
+        // message: its length as u32 (little endian), then the message
+        // gas used: 300 as u64 (little endian)
+        let mut expected = vec![0, 8, 0, 0, 0];
+        expected.extend(b"OutOfGas");
+        expected.extend((message.len() as u32).to_le_bytes());
+        expected.extend(message.as_bytes());
+        expected.extend(300u64.to_le_bytes());
+        assert_eq!(ser, expected);
+    }
 }
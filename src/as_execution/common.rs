@@ -7,8 +7,9 @@ use wasmer::FunctionEnvMut;
 
 use super::abi::get_env;
 use super::env::{get_remaining_points, set_remaining_points, ASEnv, Metered};
-use super::error::{abi_bail, ABIResult};
-use crate::Response;
+use super::error::{abi_bail, ABIError, ABIResult};
+use crate::{ExecutionFailure, Interface, Response, VMError};
+use std::sync::Arc;
 
 /// Calls an exported function in a WASM module at a given address
 pub(crate) fn call_module(
@@ -40,15 +41,24 @@ pub(crate) fn call_module(
 
     interface.increment_recursion_counter()?;
 
-    let resp = crate::execution::run_function(
-        &*interface,
-        module,
-        function,
-        param,
-        remaining_gas,
-        env.get_gas_costs(),
-        env.get_condom_limits(),
-    )?;
+    let resp = match with_checkpoint(&*interface, || {
+        crate::execution::run_sub_function(
+            &*interface,
+            module,
+            function,
+            param,
+            remaining_gas,
+            env.get_config(),
+            env.get_deadline(),
+        )
+    })? {
+        Ok(resp) => resp,
+        Err(failure) => return Err(sub_execution_failed(ctx, failure)),
+    };
+    #[cfg(feature = "profiling")]
+    env.get_profiler()
+        .lock()
+        .add_sub_execution(resp.profile.clone());
     if cfg!(not(feature = "gas_calibration")) {
         set_remaining_points(&env, ctx, resp.remaining_gas)?;
     }
@@ -67,7 +77,6 @@ pub(crate) fn local_call(
     tmp: bool,
 ) -> ABIResult<Response> {
     let env = get_env(ctx)?;
-    let gas_costs = env.get_gas_costs();
     let interface = env.get_interface();
     let remaining_gas = get_remaining_gas(&env, ctx)?;
 
@@ -79,15 +88,24 @@ pub(crate) fn local_call(
 
     interface.increment_recursion_counter()?;
 
-    let resp = crate::execution::run_function(
-        &*interface,
-        module,
-        function,
-        param,
-        remaining_gas,
-        gas_costs,
-        env.get_condom_limits(),
-    )?;
+    let resp = match with_checkpoint(&*interface, || {
+        crate::execution::run_sub_function(
+            &*interface,
+            module,
+            function,
+            param,
+            remaining_gas,
+            env.get_config(),
+            env.get_deadline(),
+        )
+    })? {
+        Ok(resp) => resp,
+        Err(failure) => return Err(sub_execution_failed(ctx, failure)),
+    };
+    #[cfg(feature = "profiling")]
+    env.get_profiler()
+        .lock()
+        .add_sub_execution(resp.profile.clone());
 
     interface.decrement_recursion_counter()?;
 
@@ -97,6 +115,100 @@ pub(crate) fn local_call(
     Ok(resp)
 }
 
+/// Same as `call_module`, but the failure of the sub-execution is returned to
+/// the caller instead of making it fail, and the changes made by the failed
+/// sub-execution are rolled back. The callee gets at most `max_gas`.
+/// A timeout still fails the caller, as the deadline is the one of the whole
+/// execution.
+pub(crate) fn try_call_module(
+    ctx: &mut FunctionEnvMut<ASEnv>,
+    address: &str,
+    function: &str,
+    param: &[u8],
+    raw_coins: i64,
+    max_gas: i64,
+) -> ABIResult<Result<Response, ExecutionFailure>> {
+    let (Ok(raw_coins), Ok(max_gas)) = (u64::try_from(raw_coins), u64::try_from(max_gas)) else {
+        abi_bail!("negative amount of coins or gas in TryCall")
+    };
+    let env = get_env(ctx)?;
+    let interface = env.get_interface();
+    let remaining_gas = get_remaining_gas(&env, ctx)?;
+    let limit = max_gas.min(remaining_gas);
+
+    let outcome = with_checkpoint(&*interface, || {
+        crate::execution::try_call(
+            &*interface,
+            |interface| interface.init_call(address, raw_coins),
+            function,
+            param,
+            limit,
+            env.get_config(),
+            env.get_deadline(),
+        )
+    })?;
+    let gas_used = match outcome {
+        Err(failure) if matches!(failure.error, VMError::Timeout { .. }) => {
+            return Err(sub_execution_failed(ctx, failure))
+        }
+        Ok(ref response) => limit.saturating_sub(response.remaining_gas),
+        Err(ref failure) => failure.gas_usage().gas_used,
+    };
+    #[cfg(feature = "profiling")]
+    if let Ok(response) = &outcome {
+        env.get_profiler()
+            .lock()
+            .add_sub_execution(response.profile.clone());
+    }
+    if cfg!(not(feature = "gas_calibration")) {
+        set_remaining_points(&env, ctx, remaining_gas.saturating_sub(gas_used))?;
+    }
+    Ok(outcome)
+}
+
+/// Serialize the outcome of a try call like the `Args` of the AssemblyScript
+/// SDK: `true` followed by the returned bytes, or `false` followed by the
+/// kind and message of the error and the gas used by the callee
+pub(crate) fn encode_try_call_outcome(outcome: &Result<Response, ExecutionFailure>) -> Vec<u8> {
+    fn push_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+        buffer.extend((bytes.len() as u32).to_le_bytes());
+        buffer.extend(bytes);
+    }
+    let mut buffer = Vec::new();
+    match outcome {
+        Ok(response) => {
+            buffer.push(1);
+            push_bytes(&mut buffer, &response.ret);
+        }
+        Err(failure) => {
+            let gas_used = failure.gas_usage().gas_used;
+            buffer.push(0);
+            push_bytes(&mut buffer, failure.error.kind().as_bytes());
+            push_bytes(&mut buffer, failure.error.to_string().as_bytes());
+            buffer.extend(gas_used.to_le_bytes());
+        }
+    }
+    buffer
+}
+
+/// Run a sub-execution between a checkpoint of the interface state, see
+/// `execution::with_checkpoint`
+fn with_checkpoint<T>(
+    interface: &dyn Interface,
+    sub_execution: impl FnOnce() -> Result<T, ExecutionFailure>,
+) -> ABIResult<Result<T, ExecutionFailure>> {
+    crate::execution::with_checkpoint(interface, sub_execution)
+        .map_err(|error| ABIError::Interface(Arc::new(error)))
+}
+
+/// Keep the failure of a sub-execution to report it along with the failure of
+/// the current execution
+fn sub_execution_failed(ctx: &mut FunctionEnvMut<ASEnv>, failure: ExecutionFailure) -> ABIError {
+    let error = failure.error.clone();
+    ctx.data_mut().sub_failure = Some(failure);
+    error.into()
+}
+
 /// Create a smart contract with the given `bytecode`
 pub(crate) fn create_sc(ctx: &mut FunctionEnvMut<ASEnv>, bytecode: &[u8]) -> ABIResult<String> {
     let env = ctx.data();
@@ -1,11 +1,15 @@
 use super::abi::*;
 use super::env::{get_remaining_points, set_remaining_points, ASEnv, Metered};
-use crate::error::{vm_bail, VMResult};
+use crate::backtrace::DebugLines;
+use crate::error::{vm_bail, GasUsage, VMResult};
+use crate::linker::check_imports;
+use crate::middlewares::watchdog::{self, Deadline};
 use crate::types::Response;
-use crate::{CondomLimits, GasCosts, Interface};
+use crate::{ExecutionConfig, Interface, ModuleKind, VMError};
 use as_ffi_bindings::{BufferPtr, Read as ASRead, Write as ASWrite};
 use wasmer::{
-    imports, Function, FunctionEnv, Imports, Instance, InstantiationError, Module, Store, Value,
+    imports, ExportError, Function, FunctionEnv, Imports, Instance, InstantiationError, Module,
+    Store, Value,
 };
 use wasmer_middlewares::metering::{self, MeteringPoints};
 use wasmer_types::TrapCode;
@@ -25,11 +29,11 @@ impl ASContext {
     pub(crate) fn new(
         interface: &dyn Interface,
         binary_module: Module,
-        gas_costs: GasCosts,
-        condom_limits: CondomLimits,
+        config: ExecutionConfig,
+        deadline: Option<Deadline>,
     ) -> Self {
         Self {
-            env: ASEnv::new(interface, gas_costs, condom_limits),
+            env: ASEnv::new(interface, config, deadline),
             module: binary_module,
         }
     }
@@ -40,9 +44,20 @@ impl ASContext {
         store: &mut Store,
     ) -> VMResult<(Instance, FunctionEnv<ASEnv>, u64)> {
         let (imports, mut fenv) = self.resolver(store);
+        check_imports(&self.module, &imports, &*store, ModuleKind::AssemblyScript).map_err(
+            |report| VMError::UnresolvedImports {
+                report,
+                gas: GasUsage::default(),
+            },
+        )?;
         match Instance::new(store, &self.module, &imports) {
             Ok(instance) => {
                 self.init_with_instance(store, &instance, &mut fenv)?;
+                // Interrupt the code that does not call any ABI once the
+                // deadline is passed
+                if let Some(deadline) = self.env.get_deadline() {
+                    watchdog::arm(store, &instance, deadline)?;
+                }
                 let post_init_points = if cfg!(not(feature = "gas_calibration")) {
                     if let MeteringPoints::Remaining(points) =
                         metering::get_remaining_points(store, &instance)
@@ -65,7 +80,10 @@ impl ASContext {
                 if let InstantiationError::Start(ref e) = err {
                     if let Some(trap) = e.clone().to_trap() {
                         if trap == TrapCode::UnreachableCodeReached && e.trace().is_empty() {
-                            vm_bail!("Not enough gas, limit reached at initialization");
+                            return Err(VMError::OutOfGas {
+                                function: String::new(),
+                                gas: GasUsage::default(),
+                            });
                         }
                     }
                 }
@@ -80,18 +98,31 @@ impl ASContext {
         instance: &Instance,
         function: &str,
         param: &[u8],
+        debug_lines: Option<&DebugLines>,
     ) -> VMResult<Response> {
         if cfg!(not(feature = "gas_calibration")) {
             // Sub initial metering cost
             let metering_initial_cost = self.env.get_gas_costs().launch_cost;
             let remaining_gas = get_remaining_points(&self.env, store)?;
             if metering_initial_cost > remaining_gas {
-                vm_bail!("Not enough gas to launch the virtual machine")
+                return Err(VMError::OutOfGas {
+                    function: function.to_string(),
+                    gas: GasUsage::default(),
+                });
             }
             set_remaining_points(&self.env, store, remaining_gas - metering_initial_cost)?;
         }
         // Now can exec
-        let wasm_func = instance.exports.get_function(function)?;
+        let wasm_func = match instance.exports.get_function(function) {
+            Ok(func) => func,
+            Err(ExportError::Missing(_)) => {
+                return Err(VMError::FunctionNotFound {
+                    function: function.to_string(),
+                    gas: GasUsage::default(),
+                })
+            }
+            Err(err) => return Err(err.into()),
+        };
         let argc = wasm_func.param_arity(store);
         let res = if argc == 0 {
             wasm_func.call(store, &[])
@@ -115,8 +146,12 @@ impl ASContext {
                         ret: Vec::new(), // main return empty vec
                         remaining_gas: remaining_gas?,
                         init_gas_cost: 0,
+                        max_memory_pages: self.env.get_config().max_memory_pages(),
+                        peak_memory_pages: memory_pages(instance, store),
                         #[cfg(feature = "execution-trace")]
                         trace: Default::default(),
+                        #[cfg(feature = "profiling")]
+                        profile: Default::default(),
                     });
                 }
                 let ret = if let Some(offset) = value.first() {
@@ -139,11 +174,15 @@ impl ASContext {
                     ret,
                     remaining_gas: remaining_gas?,
                     init_gas_cost: 0,
+                    max_memory_pages: self.env.get_config().max_memory_pages(),
+                    peak_memory_pages: memory_pages(instance, store),
                     #[cfg(feature = "execution-trace")]
                     trace: Default::default(),
+                    #[cfg(feature = "profiling")]
+                    profile: Default::default(),
                 })
             }
-            Err(error) => Err(error.into()),
+            Err(error) => Err(VMError::from_runtime_error(error, debug_lines)),
         }
     }
 
@@ -208,6 +247,10 @@ impl ASContext {
             self.env.exhausted_points = Some(g_2);
         }
 
+        // The profiler is shared with the function environment
+        #[cfg(feature = "profiling")]
+        self.env.get_profiler().lock().init_with_instance(instance);
+
         Ok(())
     }
 
@@ -231,6 +274,7 @@ impl ASContext {
             "massa" => {
                 "assembly_script_print" => Function::new_typed_with_env(store, &fenv, assembly_script_print),
                 "assembly_script_call" => Function::new_typed_with_env(store, &fenv, assembly_script_call),
+                "assembly_script_try_call" => Function::new_typed_with_env(store, &fenv, assembly_script_try_call),
                 "assembly_script_get_remaining_gas" => Function::new_typed_with_env(store, &fenv, assembly_script_get_remaining_gas),
                 "assembly_script_create_sc" => Function::new_typed_with_env(store, &fenv, assembly_script_create_sc),
                 "assembly_script_set_data" => Function::new_typed_with_env(store, &fenv, assembly_script_set_data),
@@ -292,3 +336,13 @@ impl ASContext {
         (imports, fenv)
     }
 }
+
+/// Current size of the instance memory, in pages. Memories never shrink so it
+/// is also the peak size reached during the execution.
+fn memory_pages(instance: &Instance, store: &Store) -> u32 {
+    instance
+        .exports
+        .get_memory("memory")
+        .map(|memory| memory.view(store).size().0)
+        .unwrap_or_default()
+}
@@ -1,8 +1,13 @@
-use super::{abi_bail, ABIResult};
-use crate::{types::Interface, CondomLimits};
+use super::{abi_bail, ABIError, ABIResult};
+use crate::middlewares::watchdog::Deadline;
+use crate::{types::Interface, ExecutionConfig, ExecutionFailure, VMError};
 
+#[cfg(feature = "profiling")]
+use crate::profiling::Profiler;
 #[cfg(feature = "execution-trace")]
-use crate::types::AbiTrace;
+use crate::{trace::AbiCall, types::AbiTrace};
+#[cfg(any(feature = "profiling", feature = "execution-trace"))]
+use parking_lot::Mutex;
 
 use crate::GasCosts;
 use std::{
@@ -30,34 +35,47 @@ pub struct ASEnv {
     pub remaining_points: Option<Global>,
     /// Cumulated exhausted points in the current execution context.
     pub exhausted_points: Option<Global>,
-    /// Gas costs of different execution operations.
-    gas_costs: GasCosts,
-    /// Maximum number of exports
-    condom_limits: CondomLimits,
+    /// Runtime settings, including the gas costs of the operations.
+    config: ExecutionConfig,
+    /// Deadline of the call, inherited by its nested calls.
+    deadline: Option<Deadline>,
     /// Initially added for gas calibration but unused at the moment.
     #[allow(dead_code)]
     param_size_map: HashMap<String, Option<Global>>,
     #[cfg(feature = "execution-trace")]
     pub trace: Vec<AbiTrace>,
+    /// Failure of the sub-execution that made an ABI call fail
+    pub(crate) sub_failure: Option<ExecutionFailure>,
+    /// ABI call in progress, shared by the clones of the environment
+    #[cfg(feature = "execution-trace")]
+    abi_call: Arc<Mutex<Option<AbiCall>>>,
+    /// Gas profile of the execution, shared by the clones of the environment
+    #[cfg(feature = "profiling")]
+    profiler: Arc<Mutex<Profiler>>,
 }
 
 impl ASEnv {
-    pub fn new(
+    pub(crate) fn new(
         interface: &dyn Interface,
-        gas_costs: GasCosts,
-        condom_limits: CondomLimits,
+        config: ExecutionConfig,
+        deadline: Option<Deadline>,
     ) -> Self {
         Self {
             ffi_env: Default::default(),
             abi_enabled: Arc::new(AtomicBool::new(false)),
-            gas_costs,
             interface: interface.clone_box(),
             remaining_points: None,
             exhausted_points: None,
             param_size_map: Default::default(),
-            condom_limits,
+            config,
+            deadline,
             #[cfg(feature = "execution-trace")]
             trace: Default::default(),
+            sub_failure: None,
+            #[cfg(feature = "execution-trace")]
+            abi_call: Default::default(),
+            #[cfg(feature = "profiling")]
+            profiler: Default::default(),
         }
     }
     pub fn get_interface(&self) -> Box<dyn Interface> {
@@ -69,6 +87,31 @@ impl ASEnv {
     pub fn get_ffi_env_as_mut(&mut self) -> &mut as_ffi_bindings::Env {
         &mut self.ffi_env
     }
+
+    /// Failure of the execution, with the trace recorded until then. The ABI
+    /// call in progress, if any, is the one that failed.
+    #[cfg_attr(not(feature = "execution-trace"), allow(unused_variables))]
+    pub(crate) fn failure(&mut self, error: VMError, remaining_gas: u64) -> ExecutionFailure {
+        let sub_failure = self.sub_failure.take();
+        #[cfg(feature = "execution-trace")]
+        let sub_trace = sub_failure.as_ref().map(|failure| failure.trace.clone());
+        #[allow(unused_mut)]
+        let mut failure = ExecutionFailure::from(error).with_sub_failure(sub_failure);
+        #[cfg(feature = "execution-trace")]
+        if self.config.abi_trace() {
+            if let Some(abi_call) = self.abi_call.lock().take() {
+                let mut trace = abi_call.failed(
+                    remaining_gas,
+                    self.config.call_depth(),
+                    failure.error.to_string(),
+                );
+                trace.sub_calls = sub_trace;
+                self.trace.push(trace);
+            }
+            failure.trace = std::mem::take(&mut self.trace);
+        }
+        failure
+    }
 }
 
 impl Metered for ASEnv {
@@ -82,10 +125,21 @@ impl Metered for ASEnv {
         self.param_size_map.get(name)?.as_ref()
     }
     fn get_gas_costs(&self) -> GasCosts {
-        self.gas_costs.clone()
+        self.config.gas_costs().clone()
+    }
+    fn get_config(&self) -> &ExecutionConfig {
+        &self.config
+    }
+    fn get_deadline(&self) -> Option<Deadline> {
+        self.deadline
     }
-    fn get_condom_limits(&self) -> CondomLimits {
-        self.condom_limits.clone()
+    #[cfg(feature = "execution-trace")]
+    fn get_abi_call(&self) -> &Arc<Mutex<Option<AbiCall>>> {
+        &self.abi_call
+    }
+    #[cfg(feature = "profiling")]
+    fn get_profiler(&self) -> &Arc<Mutex<Profiler>> {
+        &self.profiler
     }
 }
 
@@ -98,7 +152,12 @@ pub(crate) trait Metered {
     #[allow(dead_code)]
     fn get_gc_param(&self, name: &str) -> Option<&Global>;
     fn get_gas_costs(&self) -> GasCosts;
-    fn get_condom_limits(&self) -> CondomLimits;
+    fn get_config(&self) -> &ExecutionConfig;
+    fn get_deadline(&self) -> Option<Deadline>;
+    #[cfg(feature = "execution-trace")]
+    fn get_abi_call(&self) -> &Arc<Mutex<Option<AbiCall>>>;
+    #[cfg(feature = "profiling")]
+    fn get_profiler(&self) -> &Arc<Mutex<Profiler>>;
 }
 
 /// Get remaining metering points.
@@ -174,7 +233,7 @@ pub(crate) fn sub_remaining_gas(
     if let Some(remaining_gas) = remaining_gas.checked_sub(gas) {
         set_remaining_points(env, store, remaining_gas)?;
     } else {
-        abi_bail!("Out of gas")
+        return Err(ABIError::OutOfGas);
     }
     Ok(())
 }
@@ -184,6 +243,21 @@ pub(crate) fn sub_remaining_gas_abi(
     store: &mut impl AsStoreMut,
     abi_name: &str,
 ) -> ABIResult<()> {
+    #[cfg(feature = "execution-trace")]
+    {
+        let gas_before = get_remaining_points(env, store)?;
+        *env.get_abi_call().lock() = Some(AbiCall::start(abi_name, gas_before));
+    }
+    if let Some(deadline) = env.get_deadline() {
+        deadline.check()?;
+    }
+    #[cfg(feature = "profiling")]
+    if cfg!(not(feature = "gas_calibration")) {
+        let remaining_points = get_remaining_points(env, store)?;
+        env.get_profiler()
+            .lock()
+            .enter_abi(store, abi_name, remaining_points);
+    }
     sub_remaining_gas(
         env,
         store,
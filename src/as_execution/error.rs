@@ -1,5 +1,8 @@
+use crate::as_execution::ABIError;
+use crate::error::AbortLocation;
 use crate::{InterfaceError, VMError};
 use displaydoc::Display;
+use std::sync::Arc;
 use thiserror::Error;
 
 pub(crate) type ABIResult<T, E = ABIError> = core::result::Result<T, E>;
@@ -16,14 +19,27 @@ pub enum ABIError {
     VMError(String),
     /// {0}
     DepthError(String),
+    /// Out of gas
+    OutOfGas,
+    /// error: {message}{location}
+    GuestAbort {
+        message: String,
+        location: AbortLocation,
+    },
+    /// {0}
+    Interface(Arc<InterfaceError>),
+    /// {0}
+    Execution(Box<VMError>),
 }
 
 impl From<VMError> for ABIError {
     fn from(e: VMError) -> Self {
         match e {
-            VMError::InstanceError(e) => ABIError::VMError(e),
+            VMError::InstanceError { error, .. } => ABIError::VMError(error),
             VMError::ExecutionError { error, .. } => ABIError::VMError(error),
-            VMError::DepthError(e) => ABIError::DepthError(e),
+            VMError::DepthError { error, .. } => ABIError::DepthError(error),
+            // keep the typed errors of sub-executions
+            e => ABIError::Execution(Box::new(e)),
         }
     }
 }
@@ -33,11 +49,7 @@ impl From<InterfaceError> for ABIError {
         match e {
             InterfaceError::SerdeError(e) => ABIError::SerdeError(e.to_string()),
             InterfaceError::DepthError(e) => ABIError::DepthError(e),
-            InterfaceError::GasCalibrationError(e) | InterfaceError::GenericError(e) => {
-                ABIError::Error(anyhow::Error::msg(e))
-            }
-            InterfaceError::IoError(e) => ABIError::Error(e.into()),
-            InterfaceError::Utf8Error(e) => ABIError::Error(e.into()),
+            e => ABIError::Interface(Arc::new(e)),
         }
     }
 }
@@ -5,16 +5,19 @@ pub(crate) mod env;
 mod error;
 
 use crate::{
-    error::{exec_bail, VMResult},
+    artifact::{settings_fingerprint, ArtifactError, Fingerprint},
+    backtrace::DebugLines,
+    error::{ExecutionFailure, GasUsage, VMResult},
     execution::Compiler,
     middlewares::{
         condom::CondomMiddleware,
         dumper::Dumper,
+        dynamic_metering::DynamicMetering,
         gas_calibration::{get_gas_calibration_result, GasCalibration, GasCalibrationResult},
+        watchdog::{Deadline, Watchdog},
     },
-    settings::max_number_of_pages,
     tunable_memory::LimitingTunables,
-    CondomLimits, GasCosts, Interface, Response, VMError,
+    ExecutionConfig, Interface, ModuleKind, Response, VMError,
 };
 use std::sync::Arc;
 use wasmer::{
@@ -28,6 +31,11 @@ use wasmer_middlewares::{
     Metering,
 };
 
+#[cfg(feature = "profiling")]
+use crate::middlewares::profiling::Profiling;
+#[cfg(feature = "profiling")]
+use env::Metered;
+
 pub(crate) use context::*;
 pub(crate) use error::*;
 
@@ -37,6 +45,10 @@ pub struct ASModule {
     pub(crate) binary_module: Module,
     pub(crate) initial_limit: u64,
     pub compiler: Compiler,
+    // DWARF line info of the bytecode, not kept in serialized artifacts
+    pub(crate) debug_lines: Option<Arc<DebugLines>>,
+    // Compilation settings baked into the module
+    pub(crate) fingerprint: Fingerprint,
     // Compilation engine can not be dropped
     pub(crate) _engine: Engine,
 }
@@ -45,56 +57,68 @@ impl ASModule {
     pub(crate) fn new(
         bytecode: &[u8],
         limit: u64,
-        gas_costs: GasCosts,
         compiler: Compiler,
-        condom_limits: CondomLimits,
+        config: &ExecutionConfig,
     ) -> VMResult<Self> {
+        let fingerprint = settings_fingerprint(limit, config, ModuleKind::AssemblyScript);
+        let condom_middleware = Arc::new(CondomMiddleware::new(config.condom_limits().clone()));
         let engine = match compiler {
-            Compiler::CL => init_cl_engine(limit, gas_costs, condom_limits),
-            Compiler::SP => init_sp_engine(limit, gas_costs, condom_limits),
+            Compiler::CL => init_cl_engine(limit, config, condom_middleware.clone()),
+            Compiler::SP => init_sp_engine(limit, config, condom_middleware.clone()),
         };
+        let binary_module =
+            Module::new(&engine, bytecode).map_err(|e| match condom_middleware.take_report() {
+                Some(report) => VMError::ModuleRejected {
+                    report,
+                    gas: GasUsage::default(),
+                },
+                None => e.into(),
+            })?;
         Ok(Self {
-            binary_module: Module::new(&engine, bytecode)
-                .map_err(|e| VMError::InstanceError(e.to_string()))?,
+            binary_module,
             initial_limit: limit,
             compiler,
+            debug_lines: DebugLines::parse(bytecode).map(Arc::new),
+            fingerprint,
             _engine: engine,
         })
     }
 
     pub fn serialize(&self) -> VMResult<Vec<u8>> {
-        match self.compiler {
-            Compiler::CL => Ok(self
-                .binary_module
-                .serialize()
-                .map_err(|e| VMError::InstanceError(e.to_string()))?
-                .to_vec()),
-            Compiler::SP => {
-                panic!("cannot serialize a module compiled with Singlepass")
-            }
-        }
+        Ok(self
+            .binary_module
+            .serialize()
+            .map_err(VMError::instance_error)?
+            .to_vec())
     }
 
-    pub fn deserialize(
+    /// Deserialize the payload of a verified artifact
+    pub(crate) fn deserialize(
         ser_module: &[u8],
+        compiler: Compiler,
         limit: u64,
-        gas_costs: GasCosts,
-        condom_limits: CondomLimits,
-    ) -> VMResult<Self> {
-        // Deserialization is only meant for Cranelift modules
-        let engine = init_cl_engine(limit, gas_costs, condom_limits);
+        config: &ExecutionConfig,
+        fingerprint: Fingerprint,
+    ) -> Result<Self, ArtifactError> {
+        let condom_middleware = Arc::new(CondomMiddleware::new(config.condom_limits().clone()));
+        let engine = match compiler {
+            Compiler::CL => init_cl_engine(limit, config, condom_middleware),
+            Compiler::SP => init_sp_engine(limit, config, condom_middleware),
+        };
         let store = Store::new(engine.clone());
         // Unsafe because code injection is possible
-        // That's not an issue because we only deserialize modules we have
-        // serialized by ourselves before
+        // That's not an issue because we only deserialize artifacts whose
+        // frame, checksum and fingerprint have been verified beforehand
         let module = unsafe {
             Module::deserialize(&store, ser_module)
-                .map_err(|e| VMError::InstanceError(e.to_string()))?
+                .map_err(|e| ArtifactError::Deserialization(e.to_string()))?
         };
         Ok(ASModule {
             binary_module: module,
             initial_limit: limit,
-            compiler: Compiler::CL,
+            compiler,
+            debug_lines: None,
+            fingerprint,
             _engine: engine,
         })
     }
@@ -118,7 +142,8 @@ impl ASModule {
 // * https://github.com/WebAssembly/proposals
 //
 // TLDR: Turn off every feature except for `bulk_memory`.
-const FEATURES: Features = Features {
+// Default of `ExecutionConfig`.
+pub(crate) const FEATURES: Features = Features {
     threads: false,         // non-deterministic
     reference_types: false, // could be enabled but we have no need for it atm
     simd: false,            // non-deterministic
@@ -135,8 +160,8 @@ const FEATURES: Features = Features {
 
 pub(crate) fn init_sp_engine(
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
+    condom_middleware: Arc<CondomMiddleware>,
 ) -> Engine {
     // Singlepass is used to compile arbitrary bytecode.
     //
@@ -148,7 +173,7 @@ pub(crate) fn init_sp_engine(
     compiler_config.canonicalize_nans(true);
 
     // Add condom middleware
-    compiler_config.push_middleware(Arc::new(CondomMiddleware::new(condom_limits)));
+    compiler_config.push_middleware(condom_middleware);
 
     if cfg!(feature = "gas_calibration") {
         // Add gas calibration middleware
@@ -156,18 +181,40 @@ pub(crate) fn init_sp_engine(
         compiler_config.push_middleware(gas_calibration);
     } else {
         // Add metering middleware
-        let metering = Arc::new(Metering::new(limit, move |_: &Operator| -> u64 {
-            gas_costs.operator_cost
+        let gas_costs = config.gas_costs().clone();
+        let dynamic_metering = gas_costs
+            .has_memory_operator_costs()
+            .then(|| Arc::new(DynamicMetering::new(&gas_costs)));
+        let metering = Arc::new(Metering::new(limit, move |operator: &Operator| -> u64 {
+            gas_costs.get_operator_cost(operator)
         }));
         compiler_config.push_middleware(metering);
+        // Must come after the metering middleware, see `DynamicMetering`
+        if let Some(dynamic_metering) = dynamic_metering {
+            compiler_config.push_middleware(dynamic_metering);
+        }
+        // Must come after the metering middlewares so that its code is not
+        // metered, see `Profiling`
+        #[cfg(feature = "profiling")]
+        compiler_config.push_middleware(Arc::new(Profiling::new()));
+    }
+
+    // Only compiled in when a timeout is set, must come after the metering
+    // middlewares, see `Watchdog`
+    if config.timeout().is_some() {
+        compiler_config.push_middleware(Arc::new(Watchdog::new()));
     }
 
     let base = BaseTunables::for_target(&Target::default());
-    let tunables = LimitingTunables::new(base, Pages(max_number_of_pages()));
+    let tunables = LimitingTunables::new(
+        base,
+        Pages(config.max_memory_pages()),
+        config.max_table_elements(),
+    );
 
     let mut engine = Engine::from(
         EngineBuilder::new(compiler_config)
-            .set_features(Some(FEATURES))
+            .set_features(Some(config.features(ModuleKind::AssemblyScript).clone()))
             .engine(),
     );
     engine.set_tunables(tunables);
@@ -176,8 +223,8 @@ pub(crate) fn init_sp_engine(
 
 pub(crate) fn init_cl_engine(
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
+    condom_middleware: Arc<CondomMiddleware>,
 ) -> Engine {
     // Cranelift is used to compile bytecode that will be cached.
     //
@@ -189,7 +236,7 @@ pub(crate) fn init_cl_engine(
     compiler_config.canonicalize_nans(true);
 
     // Add condom middleware
-    compiler_config.push_middleware(Arc::new(CondomMiddleware::new(condom_limits)));
+    compiler_config.push_middleware(condom_middleware);
 
     if cfg!(feature = "gas_calibration") {
         // Add gas calibration middleware
@@ -201,18 +248,40 @@ pub(crate) fn init_cl_engine(
         compiler_config.push_middleware(dumper);
     } else {
         // Add metering middleware
-        let metering = Arc::new(Metering::new(limit, move |_: &Operator| -> u64 {
-            gas_costs.operator_cost
+        let gas_costs = config.gas_costs().clone();
+        let dynamic_metering = gas_costs
+            .has_memory_operator_costs()
+            .then(|| Arc::new(DynamicMetering::new(&gas_costs)));
+        let metering = Arc::new(Metering::new(limit, move |operator: &Operator| -> u64 {
+            gas_costs.get_operator_cost(operator)
         }));
         compiler_config.push_middleware(metering);
+        // Must come after the metering middleware, see `DynamicMetering`
+        if let Some(dynamic_metering) = dynamic_metering {
+            compiler_config.push_middleware(dynamic_metering);
+        }
+        // Must come after the metering middlewares so that its code is not
+        // metered, see `Profiling`
+        #[cfg(feature = "profiling")]
+        compiler_config.push_middleware(Arc::new(Profiling::new()));
+    }
+
+    // Only compiled in when a timeout is set, must come after the metering
+    // middlewares, see `Watchdog`
+    if config.timeout().is_some() {
+        compiler_config.push_middleware(Arc::new(Watchdog::new()));
     }
 
     let base = BaseTunables::for_target(&Target::default());
-    let tunables = LimitingTunables::new(base, Pages(max_number_of_pages()));
+    let tunables = LimitingTunables::new(
+        base,
+        Pages(config.max_memory_pages()),
+        config.max_table_elements(),
+    );
 
     let mut engine = Engine::from(
         EngineBuilder::new(compiler_config)
-            .set_features(Some(FEATURES))
+            .set_features(Some(config.features(ModuleKind::AssemblyScript).clone()))
             .engine(),
     );
     engine.set_tunables(tunables);
@@ -225,8 +294,10 @@ pub(crate) fn init_cl_engine(
 /// * `as_module`: Pre compiled AS module that will be instantiated and executed
 /// * `function`: Name of the function to call
 /// * `param`: Parameter passed to the function
-/// * `cache`: Cache of pre compiled modules
-/// * `gas_costs`: Cost in gas of every VM operation
+/// * `limit`: Gas limit of the execution
+/// * `config`: Runtime settings, including the cost in gas of every VM
+///   operation
+/// * `deadline`: Deadline of the call, see `Watchdog`
 ///
 /// Return:
 /// * Output of the executed function, remaininng gas after execution and the
@@ -238,34 +309,49 @@ pub(crate) fn exec_as_module(
     function: &str,
     param: &[u8],
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
-) -> VMResult<(Response, Option<GasCalibrationResult>)> {
+    config: &ExecutionConfig,
+    deadline: Option<Deadline>,
+) -> Result<(Response, Option<GasCalibrationResult>), ExecutionFailure> {
+    let condom_middleware = Arc::new(CondomMiddleware::new(config.condom_limits().clone()));
     let engine = match as_module.compiler {
-        Compiler::CL => init_cl_engine(limit, gas_costs.clone(), condom_limits.clone()),
-        Compiler::SP => init_sp_engine(limit, gas_costs.clone(), condom_limits.clone()),
+        Compiler::CL => init_cl_engine(limit, config, condom_middleware),
+        Compiler::SP => init_sp_engine(limit, config, condom_middleware),
     };
     let mut store = Store::new(engine);
-    let mut context = ASContext::new(
-        interface,
-        as_module.binary_module,
-        gas_costs,
-        condom_limits.clone(),
-    );
+    let mut context = ASContext::new(interface, as_module.binary_module, config.clone(), deadline);
 
     // save the gas remaining before sub-execution: used by readonly execution
     interface.save_gas_remaining_before_subexecution(limit);
 
-    let (instance, _fenv, init_rem_points) = context
+    let (instance, fenv, init_rem_points) = context
         .create_vm_instance_and_init_env(&mut store)
-        .map_err(|e| VMError::InstanceError(e.to_string()))?;
+        .map_err(|err| match err {
+            // the whole limit was consumed by the instance creation
+            VMError::OutOfGas { .. } => err.with_gas(
+                function,
+                GasUsage {
+                    init_gas_cost: limit,
+                    gas_used: limit,
+                },
+            ),
+            VMError::UnresolvedImports { .. } => err,
+            // the gas consumed by a failed instance creation is unknown
+            _ => VMError::InstanceError {
+                error: err.to_string(),
+                gas: GasUsage {
+                    init_gas_cost: limit,
+                    gas_used: limit,
+                },
+            },
+        })?;
     let init_cost = as_module.initial_limit.saturating_sub(init_rem_points);
 
     if cfg!(not(feature = "gas_calibration")) {
         metering::set_remaining_points(&mut store, &instance, limit.saturating_sub(init_cost));
     }
 
-    match context.execution(&mut store, &instance, function, param) {
+    let debug_lines = as_module.debug_lines.as_deref();
+    match context.execution(&mut store, &instance, function, param, debug_lines) {
         Ok(mut response) => {
             let gc_result = if cfg!(feature = "gas_calibration") {
                 Some(get_gas_calibration_result(&instance, &mut store))
@@ -275,37 +361,61 @@ pub(crate) fn exec_as_module(
             response.init_gas_cost = init_cost;
 
             #[cfg(feature = "execution-trace")]
+            if config.abi_trace() {
+                response.trace = fenv.as_ref(&store).trace.clone();
+            }
+
+            #[cfg(feature = "profiling")]
             {
-                response.trace = _fenv.as_ref(&store).trace.clone();
+                let profiler = context.env.get_profiler().clone();
+                response.profile = profiler.lock().report(&mut store, &instance, function);
             }
 
             Ok((response, gc_result))
         }
         Err(err) => {
-            if cfg!(feature = "gas_calibration") {
-                exec_bail!(err, init_cost)
+            let (error, remaining_gas) = if cfg!(feature = "gas_calibration") {
+                (
+                    VMError::ExecutionError {
+                        error: err.to_string(),
+                        gas: GasUsage {
+                            init_gas_cost: init_cost,
+                            gas_used: init_cost,
+                        },
+                    },
+                    0,
+                )
             } else {
+                // Because the last operation needed more than the remaining
+                // points, an exhausted metering means we ran out of gas
+                // whatever the error raised
+                let remaining_points = metering::get_remaining_points(&mut store, &instance);
+                let gas = GasUsage {
+                    init_gas_cost: init_cost,
+                    gas_used: match remaining_points {
+                        MeteringPoints::Remaining(remaining) => limit.saturating_sub(remaining),
+                        MeteringPoints::Exhausted => limit,
+                    },
+                };
                 // some error need to be handled carefully (depth error)
                 // hence we match on the error type to handle specific cases
-                match err {
-                    VMError::DepthError(e) => Err(VMError::DepthError(e)),
-                    _ => {
-                        // Because the last needed more than the remaining points, we
-                        // should have an error.
-                        match metering::get_remaining_points(&mut store, &instance) {
-                            MeteringPoints::Remaining(..) => {
-                                exec_bail!(err, init_cost)
-                            }
-                            MeteringPoints::Exhausted => {
-                                exec_bail!(
-                                    format!("Not enough gas, limit reached at: {function}"),
-                                    init_cost
-                                )
-                            }
+                let error = match (err, remaining_points) {
+                    (err @ VMError::DepthError { .. }, _) => err.with_gas(function, gas),
+                    (_, MeteringPoints::Exhausted) => VMError::OutOfGas {
+                        function: function.to_string(),
+                        gas,
+                    },
+                    (err @ VMError::InstanceError { .. }, MeteringPoints::Remaining(..)) => {
+                        VMError::ExecutionError {
+                            error: err.to_string(),
+                            gas,
                         }
                     }
-                }
-            }
+                    (err, MeteringPoints::Remaining(..)) => err.with_gas(function, gas),
+                };
+                (error, limit.saturating_sub(gas.gas_used))
+            };
+            Err(fenv.as_mut(&mut store).failure(error, remaining_gas))
         }
     }
 }
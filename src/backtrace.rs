@@ -0,0 +1,362 @@
+//! Symbolized wasm backtraces of the traps.
+//!
+//! Function names come from the `name` custom section of the module, as read
+//! by wasmer. Source locations come from the DWARF line info of the module,
+//! when it has any: it is read once when the module is compiled from its
+//! bytecode. Modules loaded from a serialized artifact only get the names.
+
+use std::collections::HashMap;
+use std::fmt;
+use wasmer::wasmparser::{Parser, Payload};
+use wasmer::FrameInfo;
+
+use gimli::{
+    ColumnType, Dwarf, EndianSlice, FileEntry, LineProgramHeader, LittleEndian, Reader, Unit,
+};
+
+/// Source location of a wasm instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    /// 0 when the line info gives no column
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if self.column > 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Frame of a wasm backtrace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmFrame {
+    pub func_index: u32,
+    /// Name of the function in the `name` custom section
+    pub function_name: Option<String>,
+    /// Offset of the instruction in the module
+    pub module_offset: usize,
+    /// Offset of the instruction in the function
+    pub func_offset: usize,
+    /// Source location of the instruction in the DWARF line info
+    pub location: Option<SourceLocation>,
+}
+
+impl fmt::Display for WasmFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function_name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "func{}", self.func_index)?,
+        }
+        match &self.location {
+            Some(location) => write!(f, " at {}", location),
+            None => write!(f, " at offset {:#x}", self.module_offset),
+        }
+    }
+}
+
+/// Wasm frames of a trap, innermost first
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WasmBacktrace {
+    pub frames: Vec<WasmFrame>,
+}
+
+impl WasmBacktrace {
+    pub(crate) fn new(frames: &[FrameInfo], debug_lines: Option<&DebugLines>) -> Self {
+        Self {
+            frames: frames
+                .iter()
+                .map(|frame| WasmFrame {
+                    func_index: frame.func_index(),
+                    function_name: frame.function_name().map(str::to_string),
+                    module_offset: frame.module_offset(),
+                    func_offset: frame.func_offset(),
+                    location: debug_lines.and_then(|lines| lines.locate(frame.module_offset())),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Innermost frame, the whole backtrace is in `frames`
+impl fmt::Display for WasmBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.frames.first() {
+            Some(frame) => write!(f, " in {}", frame),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LineRow {
+    /// Offset in the code section
+    address: u64,
+    /// Index in `DebugLines::files`, `None` when the line info refers to a
+    /// file it does not list
+    file: Option<usize>,
+    /// 0 when the instructions from `address` have no location
+    line: u32,
+    column: u32,
+}
+
+/// DWARF line table of a module
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DebugLines {
+    /// Offset of the code section content in the module, DWARF addresses
+    /// are relative to it
+    code_offset: u64,
+    files: Vec<String>,
+    /// Sorted by address
+    rows: Vec<LineRow>,
+}
+
+impl DebugLines {
+    /// Line table of a module, `None` if it has no usable DWARF line info
+    pub(crate) fn parse(bytecode: &[u8]) -> Option<Self> {
+        let mut code_offset = None;
+        let mut sections = HashMap::new();
+        for payload in Parser::new(0).parse_all(bytecode) {
+            match payload.ok()? {
+                Payload::CodeSectionStart { range, .. } => code_offset = Some(range.start as u64),
+                Payload::CustomSection(reader) if reader.name().starts_with(".debug_") => {
+                    sections.insert(reader.name(), reader.data());
+                }
+                _ => (),
+            }
+        }
+        if !sections.contains_key(".debug_line") {
+            return None;
+        }
+
+        let dwarf = Dwarf::load(|id| -> gimli::Result<_> {
+            let data = sections.get(id.name()).copied().unwrap_or(&[]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        })
+        .ok()?;
+        let mut lines = DebugLines {
+            code_offset: code_offset?,
+            ..Default::default()
+        };
+        lines.read_line_programs(&dwarf).ok()?;
+        // the end of a sequence comes before a sequence starting at the same
+        // address
+        lines.rows.sort_by_key(|row| (row.address, row.line > 0));
+        Some(lines)
+    }
+
+    fn read_line_programs<R: Reader>(&mut self, dwarf: &Dwarf<R>) -> gimli::Result<()> {
+        let mut file_indexes = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let line = match row.line() {
+                    Some(line) if !row.end_sequence() => line.get() as u32,
+                    _ => 0,
+                };
+                let file = match row.file(header) {
+                    Some(file) if line > 0 => {
+                        let path = file_path(dwarf, &unit, header, file)?;
+                        let next_index = self.files.len();
+                        Some(*file_indexes.entry(path.clone()).or_insert_with(|| {
+                            self.files.push(path);
+                            next_index
+                        }))
+                    }
+                    _ => None,
+                };
+                let column = match row.column() {
+                    ColumnType::LeftEdge => 0,
+                    ColumnType::Column(column) => column.get() as u32,
+                };
+                self.rows.push(LineRow {
+                    address: row.address(),
+                    file,
+                    line,
+                    column,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Source location of the instruction at `module_offset`
+    fn locate(&self, module_offset: usize) -> Option<SourceLocation> {
+        let address = (module_offset as u64).checked_sub(self.code_offset)?;
+        let index = self
+            .rows
+            .partition_point(|row| row.address <= address)
+            .checked_sub(1)?;
+        let row = &self.rows[index];
+        if row.line == 0 {
+            return None;
+        }
+        Some(SourceLocation {
+            file: self.files.get(row.file?)?.clone(),
+            line: row.line,
+            column: row.column,
+        })
+    }
+}
+
+fn file_path<R: Reader>(
+    dwarf: &Dwarf<R>,
+    unit: &Unit<R>,
+    header: &LineProgramHeader<R>,
+    file: &FileEntry<R>,
+) -> gimli::Result<String> {
+    let name = dwarf
+        .attr_string(unit, file.path_name())?
+        .to_string_lossy()?
+        .into_owned();
+    let directory = match file.directory(header) {
+        Some(directory) if !name.starts_with('/') => dwarf
+            .attr_string(unit, directory)?
+            .to_string_lossy()?
+            .into_owned(),
+        _ => String::new(),
+    };
+    Ok(match directory.is_empty() {
+        true => name,
+        false => format!("{}/{}", directory.trim_end_matches('/'), name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let lines = DebugLines {
+            code_offset: 100,
+            files: vec!["token.ts".to_string()],
+            rows: vec![
+                LineRow {
+                    address: 4,
+                    file: Some(0),
+                    line: 42,
+                    column: 5,
+                },
+                LineRow {
+                    address: 10,
+                    file: Some(0),
+                    line: 43,
+                    column: 0,
+                },
+                LineRow {
+                    address: 20,
+                    file: None,
+                    line: 0,
+                    column: 0,
+                },
+            ],
+        };
+        assert_eq!(lines.locate(50), None);
+        assert_eq!(lines.locate(103), None);
+        assert_eq!(lines.locate(108).unwrap().to_string(), "token.ts:42:5");
+        assert_eq!(lines.locate(115).unwrap().to_string(), "token.ts:43");
+        assert_eq!(lines.locate(125), None);
+        assert_eq!(
+            DebugLines::parse(&wasmer::wat2wasm(b"(module)").unwrap()),
+            None
+        );
+
+        let frame = WasmFrame {
+            func_index: 3,
+            function_name: Some("transfer".to_string()),
+            module_offset: 108,
+            func_offset: 2,
+            location: lines.locate(108),
+        };
+        let backtrace = WasmBacktrace {
+            frames: vec![frame],
+        };
+        assert_eq!(backtrace.to_string(), " in transfer at token.ts:42:5");
+        assert_eq!(WasmBacktrace::default().to_string(), "");
+    }
+
+    /// Custom section `name` of a module
+    fn custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut content = vec![name.len() as u8];
+        content.extend_from_slice(name.as_bytes());
+        content.extend_from_slice(data);
+        // the sections of the test fit in a single byte LEB128 size
+        assert!(content.len() < 0x80);
+        let mut section = vec![0, content.len() as u8];
+        section.extend(content);
+        section
+    }
+
+    #[test]
+    fn test_unlisted_file() {
+        // compile unit pointing to the line program at offset 0
+        #[rustfmt::skip]
+        let debug_abbrev = [
+            0x01,       // abbreviation code
+            0x11,       // DW_TAG_compile_unit
+            0x00,       // no children
+            0x10, 0x17, // DW_AT_stmt_list, DW_FORM_sec_offset
+            0x00, 0x00, // end of the attributes
+            0x00,       // end of the abbreviations
+        ];
+        #[rustfmt::skip]
+        let debug_info = [
+            12, 0, 0, 0, // unit length
+            4, 0,        // version
+            0, 0, 0, 0,  // abbreviation offset
+            4,           // address size
+            0x01,        // abbreviation code
+            0, 0, 0, 0,  // DW_AT_stmt_list
+        ];
+        // line program listing no file, with a row in the default file 1
+        #[rustfmt::skip]
+        let debug_line = [
+            34, 0, 0, 0,                         // unit length
+            4, 0,                                // version
+            20, 0, 0, 0,                         // header length
+            1,                                   // minimum instruction length
+            1,                                   // maximum operations per instruction
+            1,                                   // default is_stmt
+            0xfb,                                // line base
+            14,                                  // line range
+            13,                                  // opcode base
+            0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1,  // standard opcode lengths
+            0,                                   // no include directory
+            0,                                   // no file
+            0x03, 0x04,                          // DW_LNS_advance_line 4
+            0x01,                                // DW_LNS_copy
+            0x02, 0x10,                          // DW_LNS_advance_pc 16
+            0x00, 0x01, 0x01,                    // DW_LNE_end_sequence
+        ];
+        let mut bytecode = wasmer::wat2wasm(b"(module (func))").unwrap().into_owned();
+        bytecode.extend(custom_section(".debug_abbrev", &debug_abbrev));
+        bytecode.extend(custom_section(".debug_info", &debug_info));
+        bytecode.extend(custom_section(".debug_line", &debug_line));
+
+        let lines = DebugLines::parse(&bytecode).unwrap();
+        assert!(lines.files.is_empty());
+        assert_eq!(
+            lines.rows[0],
+            LineRow {
+                address: 0,
+                file: None,
+                line: 5,
+                column: 0,
+            }
+        );
+        let code_offset = lines.code_offset as usize;
+        assert_eq!(lines.locate(code_offset), None);
+        assert_eq!(lines.locate(code_offset + 8), None);
+    }
+}
@@ -0,0 +1,362 @@
+//! Settings of the runtime, shared by module compilation and execution.
+//!
+//! An `ExecutionConfig` is built once with `ExecutionConfig::builder()` and
+//! validated there, so that an invalid combination of settings is rejected
+//! before any module is compiled.
+
+use crate::settings::{
+    MAX_DATASTORE_ENTRY_COUNT, MAX_NUMBER_OF_PAGES, MAX_OP_DATASTORE_ENTRY_COUNT,
+    MAX_TABLE_ELEMENTS,
+};
+use crate::{
+    as_execution, wasmv1_execution, CondomLimits, GasCosts, GasUsage, ModuleKind, VMError,
+};
+use displaydoc::Display;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use thiserror::Error;
+use wasmer::sys::Features;
+use wasmer::Pages;
+
+#[derive(Error, Display, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Memory page limit must be between 1 and {max} pages, got {pages}
+    InvalidMemoryPages { pages: u32, max: u32 },
+    /// Table element limit must be at least 1
+    InvalidTableElements,
+    /// Execution timeout must not be zero
+    InvalidTimeout,
+    /// Operation datastore entry cap ({op}) exceeds the datastore entry cap ({max})
+    InvalidDatastoreEntryCount { op: usize, max: usize },
+    /// Wasm feature `{feature}` cannot be enabled for {kind:?} modules
+    UnsupportedFeature {
+        kind: ModuleKind,
+        feature: &'static str,
+    },
+}
+
+/// Settings of the runtime
+#[derive(Clone, Debug)]
+pub struct ExecutionConfig {
+    gas_costs: GasCosts,
+    condom_limits: CondomLimits,
+    max_memory_pages: u32,
+    max_table_elements: u32,
+    max_datastore_entry_count: usize,
+    max_op_datastore_entry_count: usize,
+    as_features: Features,
+    wasmv1_features: Features,
+    abi_trace: bool,
+    timeout: Option<Duration>,
+    read_only: bool,
+    /// Depth of the running call, 1 for the call started by the embedder
+    call_depth: u32,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            gas_costs: GasCosts::default(),
+            condom_limits: CondomLimits::default(),
+            max_memory_pages: MAX_NUMBER_OF_PAGES,
+            max_table_elements: MAX_TABLE_ELEMENTS,
+            max_datastore_entry_count: MAX_DATASTORE_ENTRY_COUNT,
+            max_op_datastore_entry_count: MAX_OP_DATASTORE_ENTRY_COUNT,
+            as_features: as_execution::FEATURES,
+            wasmv1_features: wasmv1_execution::FEATURES,
+            abi_trace: true,
+            timeout: None,
+            read_only: false,
+            call_depth: 0,
+        }
+    }
+}
+
+impl ExecutionConfig {
+    /// Start from the default settings
+    pub fn builder() -> ExecutionConfigBuilder {
+        ExecutionConfigBuilder::default()
+    }
+
+    /// Start from these settings, e.g. to raise the memory page limit of a
+    /// single call
+    pub fn to_builder(&self) -> ExecutionConfigBuilder {
+        ExecutionConfigBuilder {
+            config: self.clone(),
+        }
+    }
+
+    /// Cost in gas of every VM operation
+    pub fn gas_costs(&self) -> &GasCosts {
+        &self.gas_costs
+    }
+
+    /// Limits checked on modules at compilation
+    pub fn condom_limits(&self) -> &CondomLimits {
+        &self.condom_limits
+    }
+
+    /// Maximum number of 64 KiB pages of a module memory. Enforced when the
+    /// module is instantiated, it can differ from the config the module was
+    /// compiled with.
+    pub fn max_memory_pages(&self) -> u32 {
+        self.max_memory_pages
+    }
+
+    /// Maximum number of elements of a module table. Enforced when the module
+    /// is instantiated, like the memory page limit.
+    pub fn max_table_elements(&self) -> u32 {
+        self.max_table_elements
+    }
+
+    /// Maximum number of keys returned by a datastore listing
+    pub fn max_datastore_entry_count(&self) -> usize {
+        self.max_datastore_entry_count
+    }
+
+    /// Maximum number of keys returned by an operation datastore listing
+    pub fn max_op_datastore_entry_count(&self) -> usize {
+        self.max_op_datastore_entry_count
+    }
+
+    /// Wasm features enabled when compiling modules of the given kind
+    pub fn features(&self, kind: ModuleKind) -> &Features {
+        match kind {
+            ModuleKind::AssemblyScript => &self.as_features,
+            ModuleKind::WasmV1 => &self.wasmv1_features,
+        }
+    }
+
+    /// Whether ABI calls are recorded in `Response::trace`, only effective
+    /// with the `execution-trace` feature
+    pub fn abi_trace(&self) -> bool {
+        self.abi_trace
+    }
+
+    /// Wall-clock time budget of a call, nested calls included. Checked at
+    /// every ABI call and by the code that the `Watchdog` middleware injects
+    /// in the modules compiled with a timeout. It must stay disabled for
+    /// executions that are part of the consensus.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Whether the state-mutating ABIs are rejected by the runtime, before
+    /// they reach the interface. Inherited by the nested calls. Events are not
+    /// part of the state, and calls are only rejected when they send coins.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Settings of a starting call: count its depth
+    pub(crate) fn enter_call(&self) -> ExecutionConfig {
+        let mut config = self.clone();
+        config.call_depth += 1;
+        config
+    }
+
+    /// Depth of the running call, 1 for the call started by the embedder
+    #[cfg_attr(not(feature = "execution-trace"), allow(dead_code))]
+    pub(crate) fn call_depth(&self) -> u32 {
+        self.call_depth
+    }
+
+    /// Fail with `VMError::ReadOnly` when the state-mutating ABI `abi_name`
+    /// is called in a read-only call
+    pub(crate) fn check_writable(&self, abi_name: &str) -> Result<(), VMError> {
+        if self.read_only {
+            return Err(VMError::ReadOnly {
+                abi: abi_name.to_string(),
+                gas: GasUsage::default(),
+            });
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let max_pages = Pages::max_value().0;
+        if self.max_memory_pages == 0 || self.max_memory_pages > max_pages {
+            return Err(ConfigError::InvalidMemoryPages {
+                pages: self.max_memory_pages,
+                max: max_pages,
+            });
+        }
+        if self.max_table_elements == 0 {
+            return Err(ConfigError::InvalidTableElements);
+        }
+        if self.timeout == Some(Duration::ZERO) {
+            return Err(ConfigError::InvalidTimeout);
+        }
+        if self.max_op_datastore_entry_count > self.max_datastore_entry_count {
+            return Err(ConfigError::InvalidDatastoreEntryCount {
+                op: self.max_op_datastore_entry_count,
+                max: self.max_datastore_entry_count,
+            });
+        }
+        for kind in [ModuleKind::AssemblyScript, ModuleKind::WasmV1] {
+            let features = self.features(kind);
+            // non-deterministic, or not supported by the metering and the
+            // memory accesses of the ABIs
+            for (feature, enabled) in [
+                ("threads", features.threads),
+                ("simd", features.simd),
+                ("relaxed_simd", features.relaxed_simd),
+                ("multi_memory", features.multi_memory),
+                ("memory64", features.memory64),
+                ("module_linking", features.module_linking),
+                ("exceptions", features.exceptions),
+            ] {
+                if enabled {
+                    return Err(ConfigError::UnsupportedFeature { kind, feature });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hash the settings baked into compiled modules, see `artifact`. The
+    /// memory page and table element limits are only enforced when
+    /// instantiating a module, so a compiled module can be executed with any
+    /// limit. Only whether a timeout is set is hashed, it decides whether the
+    /// `Watchdog` middleware is compiled in.
+    pub(crate) fn hash_into(&self, hasher: &mut Sha256) {
+        self.gas_costs.hash_into(hasher);
+        self.condom_limits.hash_into(hasher);
+        hasher.update([self.timeout.is_some() as u8]);
+    }
+}
+
+/// Builder of an `ExecutionConfig`
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionConfigBuilder {
+    config: ExecutionConfig,
+}
+
+impl ExecutionConfigBuilder {
+    pub fn gas_costs(mut self, gas_costs: GasCosts) -> Self {
+        self.config.gas_costs = gas_costs;
+        self
+    }
+
+    pub fn condom_limits(mut self, condom_limits: CondomLimits) -> Self {
+        self.config.condom_limits = condom_limits;
+        self
+    }
+
+    pub fn max_memory_pages(mut self, pages: u32) -> Self {
+        self.config.max_memory_pages = pages;
+        self
+    }
+
+    pub fn max_table_elements(mut self, elements: u32) -> Self {
+        self.config.max_table_elements = elements;
+        self
+    }
+
+    pub fn max_datastore_entry_count(mut self, count: usize) -> Self {
+        self.config.max_datastore_entry_count = count;
+        self
+    }
+
+    pub fn max_op_datastore_entry_count(mut self, count: usize) -> Self {
+        self.config.max_op_datastore_entry_count = count;
+        self
+    }
+
+    /// Override the Wasm features of the given module kind. Features that
+    /// break determinism are rejected by `build`.
+    pub fn features(mut self, kind: ModuleKind, features: Features) -> Self {
+        match kind {
+            ModuleKind::AssemblyScript => self.config.as_features = features,
+            ModuleKind::WasmV1 => self.config.wasmv1_features = features,
+        }
+        self
+    }
+
+    pub fn abi_trace(mut self, enabled: bool) -> Self {
+        self.config.abi_trace = enabled;
+        self
+    }
+
+    /// Interrupt the calls running longer than `timeout`, see
+    /// `ExecutionConfig::timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Reject the state-mutating ABIs, see `ExecutionConfig::read_only`
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.config.read_only = read_only;
+        self
+    }
+
+    pub fn build(self) -> Result<ExecutionConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execution_config_validation() {
+        let config = ExecutionConfig::builder()
+            .max_memory_pages(32)
+            .abi_trace(false)
+            .build()
+            .unwrap();
+        assert_eq!(config.max_memory_pages(), 32);
+        assert!(!config.abi_trace());
+        assert!(!config.read_only());
+        assert!(matches!(
+            config
+                .to_builder()
+                .read_only(true)
+                .build()
+                .unwrap()
+                .check_writable("abi_set_ds_value"),
+            Err(VMError::ReadOnly { .. })
+        ));
+
+        assert_eq!(
+            ExecutionConfig::builder()
+                .max_memory_pages(0)
+                .build()
+                .unwrap_err(),
+            ConfigError::InvalidMemoryPages {
+                pages: 0,
+                max: 65536
+            }
+        );
+        assert_eq!(
+            ExecutionConfig::builder()
+                .max_table_elements(0)
+                .build()
+                .unwrap_err(),
+            ConfigError::InvalidTableElements
+        );
+        assert!(matches!(
+            ExecutionConfig::builder()
+                .max_datastore_entry_count(10)
+                .max_op_datastore_entry_count(11)
+                .build(),
+            Err(ConfigError::InvalidDatastoreEntryCount { .. })
+        ));
+
+        let mut features = wasmv1_execution::FEATURES;
+        features.simd = true;
+        assert_eq!(
+            ExecutionConfig::builder()
+                .features(ModuleKind::WasmV1, features)
+                .build()
+                .unwrap_err(),
+            ConfigError::UnsupportedFeature {
+                kind: ModuleKind::WasmV1,
+                feature: "simd"
+            }
+        );
+    }
+}
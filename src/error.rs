@@ -1,74 +1,343 @@
+use crate::backtrace::{DebugLines, WasmBacktrace};
+use crate::linker::ImportReport;
+use crate::middlewares::condom::CondomReport;
+#[cfg(feature = "execution-trace")]
+use crate::types::AbiTrace;
+use crate::types::InterfaceError;
 use displaydoc::Display;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+use wasmer_types::TrapCode;
 
 pub type VMResult<T> = Result<T, VMError>;
 
+/// Gas consumed by an execution, reported along with its errors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasUsage {
+    /// Gas cost of the instance creation
+    pub init_gas_cost: u64,
+    /// Total gas consumed, instance creation included
+    pub gas_used: u64,
+}
+
+/// Source location given by the guest when aborting
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AbortLocation {
+    pub file: String,
+    pub line: i32,
+    pub col: i32,
+}
+
+impl fmt::Display for AbortLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // WasmV1 guests do not provide any location
+        if self.file.is_empty() {
+            return Ok(());
+        }
+        write!(f, " at {}:{} col: {}", self.file, self.line, self.col)
+    }
+}
+
+#[non_exhaustive]
 #[derive(Clone, Error, Display, Debug)]
 pub enum VMError {
-    /// VM instance error: {0}
-    InstanceError(String),
+    /// VM instance error: {error}
+    InstanceError { error: String, gas: GasUsage },
     /// VM execution error: {error}
-    ExecutionError { error: String, init_gas_cost: u64 },
-    /// Depth error: {0}
-    DepthError(String),
+    ExecutionError { error: String, gas: GasUsage },
+    /// Depth error: {error}
+    DepthError { error: String, gas: GasUsage },
+    /// Not enough gas, limit reached at: {function}
+    OutOfGas { function: String, gas: GasUsage },
+    /// Wasm trap in {function}: {code}{backtrace}
+    Trap {
+        function: String,
+        code: TrapCode,
+        backtrace: WasmBacktrace,
+        gas: GasUsage,
+    },
+    /// Guest abort: {message}{location}
+    GuestAbort {
+        message: String,
+        location: AbortLocation,
+        gas: GasUsage,
+    },
+    /// Function not found: {function}
+    FunctionNotFound { function: String, gas: GasUsage },
+    /// Execution time budget of {budget:?} exceeded in: {function}
+    Timeout {
+        function: String,
+        budget: Duration,
+        gas: GasUsage,
+    },
+    /// Module rejected: {report}
+    ModuleRejected { report: CondomReport, gas: GasUsage },
+    /// Unresolved imports: {report}
+    UnresolvedImports { report: ImportReport, gas: GasUsage },
+    /// State-mutating ABI {abi} called in a read-only execution
+    ReadOnly { abi: String, gas: GasUsage },
+    /// Interface error: {error}
+    Interface {
+        error: Arc<InterfaceError>,
+        gas: GasUsage,
+    },
+}
+
+impl VMError {
+    /// `InstanceError` consuming no gas, its gas is set with `with_gas` when
+    /// it is raised during an execution
+    pub(crate) fn instance_error(error: impl ToString) -> Self {
+        VMError::InstanceError {
+            error: error.to_string(),
+            gas: GasUsage::default(),
+        }
+    }
+
+    /// Gas consumed by the failed execution, zero for errors raised before
+    /// any execution started
+    pub fn gas_usage(&self) -> GasUsage {
+        match self {
+            VMError::InstanceError { gas, .. }
+            | VMError::ExecutionError { gas, .. }
+            | VMError::DepthError { gas, .. }
+            | VMError::OutOfGas { gas, .. }
+            | VMError::Trap { gas, .. }
+            | VMError::GuestAbort { gas, .. }
+            | VMError::FunctionNotFound { gas, .. }
+            | VMError::Timeout { gas, .. }
+            | VMError::ModuleRejected { gas, .. }
+            | VMError::UnresolvedImports { gas, .. }
+            | VMError::ReadOnly { gas, .. }
+            | VMError::Interface { gas, .. } => *gas,
+        }
+    }
+
+    /// Name of the variant, reported to the contracts catching the failure of
+    /// a sub-execution
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VMError::InstanceError { .. } => "InstanceError",
+            VMError::ExecutionError { .. } => "ExecutionError",
+            VMError::DepthError { .. } => "DepthError",
+            VMError::OutOfGas { .. } => "OutOfGas",
+            VMError::Trap { .. } => "Trap",
+            VMError::GuestAbort { .. } => "GuestAbort",
+            VMError::FunctionNotFound { .. } => "FunctionNotFound",
+            VMError::Timeout { .. } => "Timeout",
+            VMError::ModuleRejected { .. } => "ModuleRejected",
+            VMError::UnresolvedImports { .. } => "UnresolvedImports",
+            VMError::ReadOnly { .. } => "ReadOnly",
+            VMError::Interface { .. } => "Interface",
+        }
+    }
+
+    /// Attach the failing function and the gas consumed to an error raised
+    /// while executing it. The function of an error bubbling up from a
+    /// sub-execution is kept as it is the innermost failing one.
+    pub(crate) fn with_gas(mut self, failing_function: &str, usage: GasUsage) -> Self {
+        match &mut self {
+            VMError::OutOfGas { function, gas }
+            | VMError::Trap { function, gas, .. }
+            | VMError::FunctionNotFound { function, gas }
+            | VMError::Timeout { function, gas, .. } => {
+                if function.is_empty() {
+                    *function = failing_function.to_string();
+                }
+                *gas = usage;
+            }
+            VMError::InstanceError { gas, .. }
+            | VMError::ExecutionError { gas, .. }
+            | VMError::DepthError { gas, .. }
+            | VMError::GuestAbort { gas, .. }
+            | VMError::ModuleRejected { gas, .. }
+            | VMError::UnresolvedImports { gas, .. }
+            | VMError::ReadOnly { gas, .. }
+            | VMError::Interface { gas, .. } => *gas = usage,
+        }
+        self
+    }
+
+    /// Convert an error raised by a guest call, symbolizing the backtrace of
+    /// its traps with the DWARF line info of the module
+    pub(crate) fn from_runtime_error(
+        e: wasmer::RuntimeError,
+        debug_lines: Option<&DebugLines>,
+    ) -> Self {
+        if let Some(err) = e.downcast_ref::<ABIError>() {
+            match err {
+                ABIError::OutOfGas => VMError::OutOfGas {
+                    function: String::new(),
+                    gas: GasUsage::default(),
+                },
+                ABIError::GuestAbort { message, location } => VMError::GuestAbort {
+                    message: message.clone(),
+                    location: location.clone(),
+                    gas: GasUsage::default(),
+                },
+                ABIError::Interface(error) => VMError::Interface {
+                    error: error.clone(),
+                    gas: GasUsage::default(),
+                },
+                ABIError::Execution(e) => *e.clone(),
+                ABIError::DepthError(error) => VMError::DepthError {
+                    error: error.clone(),
+                    gas: GasUsage::default(),
+                },
+                _ => VMError::ExecutionError {
+                    error: err.to_string(),
+                    gas: GasUsage::default(),
+                },
+            }
+        } else if let Some(err) = e.downcast_ref::<VMError>() {
+            // raised by the watchdog
+            err.clone()
+        } else if let Some(code) = e.clone().to_trap() {
+            VMError::Trap {
+                function: String::new(),
+                code,
+                backtrace: WasmBacktrace::new(e.trace(), debug_lines),
+                gas: GasUsage::default(),
+            }
+        } else {
+            VMError::instance_error(e)
+        }
+    }
+}
+
+/// Failed execution, with what it did until the failure
+#[derive(Clone, Debug)]
+pub struct ExecutionFailure {
+    pub error: VMError,
+    /// Function that failed, in the innermost failing call
+    pub function: String,
+    /// Depth of the innermost failing call, 1 for the call started by the
+    /// embedder
+    pub depth: u32,
+    /// ABI calls made until the failure, the failing one last
+    #[cfg(feature = "execution-trace")]
+    pub trace: Vec<AbiTrace>,
+}
+
+impl ExecutionFailure {
+    /// Gas consumed by the failed execution, see `VMError::gas_usage`
+    pub fn gas_usage(&self) -> GasUsage {
+        self.error.gas_usage()
+    }
+
+    /// Report the function and depth of the sub-execution that made the
+    /// execution fail
+    pub(crate) fn with_sub_failure(mut self, sub_failure: Option<ExecutionFailure>) -> Self {
+        if let Some(sub_failure) = sub_failure {
+            self.function = sub_failure.function;
+            self.depth = sub_failure.depth;
+        }
+        self
+    }
+
+    /// Attach the failing function and call depth, unless the failure comes
+    /// from a sub-execution that already set them
+    pub(crate) fn locate(mut self, function: &str, depth: u32) -> Self {
+        if self.depth == 0 {
+            self.function = function.to_string();
+            self.depth = depth;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ExecutionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for ExecutionFailure {}
+
+impl From<VMError> for ExecutionFailure {
+    fn from(error: VMError) -> Self {
+        Self {
+            error,
+            function: String::new(),
+            depth: 0,
+            #[cfg(feature = "execution-trace")]
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl From<ExecutionFailure> for VMError {
+    fn from(failure: ExecutionFailure) -> Self {
+        failure.error
+    }
 }
 
 impl From<ABIError> for VMError {
     fn from(e: ABIError) -> Self {
         match e {
-            ABIError::SerdeError(e) => VMError::InstanceError(e),
-            ABIError::VMError(e) => VMError::InstanceError(e),
-            ABIError::Error(e) => VMError::InstanceError(e.to_string()),
-            ABIError::RuntimeError(e) => VMError::InstanceError(e),
-            ABIError::DepthError(e) => VMError::DepthError(e),
+            ABIError::SerdeError(e) => VMError::instance_error(e),
+            ABIError::VMError(e) => VMError::instance_error(e),
+            ABIError::Error(e) => VMError::instance_error(e),
+            ABIError::RuntimeError(e) => VMError::instance_error(e),
+            ABIError::DepthError(error) => VMError::DepthError {
+                error,
+                gas: GasUsage::default(),
+            },
+            ABIError::OutOfGas => VMError::OutOfGas {
+                function: String::new(),
+                gas: GasUsage::default(),
+            },
+            ABIError::GuestAbort { message, location } => VMError::GuestAbort {
+                message,
+                location,
+                gas: GasUsage::default(),
+            },
+            ABIError::Interface(error) => VMError::Interface {
+                error,
+                gas: GasUsage::default(),
+            },
+            ABIError::Execution(e) => *e,
         }
     }
 }
 
 impl From<wasmer::RuntimeError> for VMError {
     fn from(e: wasmer::RuntimeError) -> Self {
-        if let Some(err) = e.downcast_ref::<ABIError>() {
-            VMError::DepthError(err.to_string())
-        } else {
-            VMError::InstanceError(e.to_string())
-        }
+        VMError::from_runtime_error(e, None)
     }
 }
 
 impl From<wasmer::ExportError> for VMError {
     fn from(e: wasmer::ExportError) -> Self {
-        VMError::InstanceError(e.to_string())
+        VMError::instance_error(e)
     }
 }
 
 impl From<wasmer::InstantiationError> for VMError {
     fn from(e: wasmer::InstantiationError) -> Self {
-        VMError::InstanceError(e.to_string())
+        VMError::instance_error(e)
     }
 }
 
-impl From<anyhow::Error> for VMError {
-    fn from(value: anyhow::Error) -> Self {
-        Self::InstanceError(value.to_string())
+impl From<wasmer::CompileError> for VMError {
+    fn from(e: wasmer::CompileError) -> Self {
+        VMError::instance_error(e)
     }
 }
 
-macro_rules! exec_bail {
-    ($err:expr, $init_gas_cost:expr) => {
-        return Err(crate::VMError::ExecutionError {
-            error: $err.to_string(),
-            init_gas_cost: $init_gas_cost,
-        })
-    };
+impl From<anyhow::Error> for VMError {
+    fn from(value: anyhow::Error) -> Self {
+        Self::instance_error(value)
+    }
 }
 
 macro_rules! vm_bail {
     ($err:expr) => {
-        return Err(crate::VMError::InstanceError($err.to_string()))
+        return Err(crate::VMError::instance_error($err))
     };
 }
 
-pub(crate) use exec_bail;
 pub(crate) use vm_bail;
 
 use crate::as_execution::ABIError;
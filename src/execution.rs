@@ -1,15 +1,18 @@
+use crate::artifact::{self, ArtifactError};
 use crate::as_execution::{exec_as_module, ASModule};
-use crate::error::VMResult;
+use crate::error::{ExecutionFailure, GasUsage, VMResult};
 use crate::middlewares::gas_calibration::GasCalibrationResult;
-use crate::types::{Interface, Response};
+use crate::middlewares::watchdog::Deadline;
+use crate::types::{Interface, InterfaceError, Response};
 use crate::wasmv1_execution::{exec_wasmv1_module, WasmV1Module};
-use crate::{settings, CondomLimits};
+use crate::{settings, ExecutionConfig, ModuleKind};
 use crate::{GasCosts, VMError};
 use anyhow::{anyhow, Result};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::sync::Arc;
 
 /// Enum listing the available compilers
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Compiler {
     CL,
     SP,
@@ -35,12 +38,7 @@ impl RuntimeModule {
     /// * (0): legacy AssemblyScript module
     /// * (1): new agnostic module
     /// * (_): unsupported module
-    pub fn new(
-        bytecode: &[u8],
-        gas_costs: GasCosts,
-        compiler: Compiler,
-        condom_limits: CondomLimits,
-    ) -> Result<Self> {
+    pub fn new(bytecode: &[u8], compiler: Compiler, config: &ExecutionConfig) -> Result<Self> {
         if bytecode.len() <= 2 {
             return Err(anyhow!("Too small bytecode"));
         }
@@ -56,22 +54,25 @@ impl RuntimeModule {
         match module_id {
             RuntimeModuleId::ASModuleId => Ok(Self::ASModule(ASModule::new(
                 bytecode,
-                gas_costs.max_instance_cost,
-                gas_costs,
+                config.gas_costs().max_instance_cost,
                 compiler,
-                condom_limits,
+                config,
             )?)),
             RuntimeModuleId::WasmV1ModuleId => {
                 // Safe to use [1..] as we checked the bytecode length
                 // TODO: ensure that the WasmV1 VM can be refilled with gas after launching a pre-compiled module
                 let res = WasmV1Module::compile(
                     &bytecode[1..],
-                    gas_costs.max_instance_cost,
-                    gas_costs,
+                    config.gas_costs().max_instance_cost,
                     compiler,
-                    condom_limits,
+                    config,
                 )
-                .map_err(|err| anyhow!("Failed to compile WasmV1 module: {}", err))?;
+                .map_err(|err| match err {
+                    VMError::InstanceError { .. } => {
+                        anyhow!("Failed to compile WasmV1 module: {}", err)
+                    }
+                    err => err.into(),
+                })?;
                 Ok(Self::WasmV1Module(res))
             }
         }
@@ -85,43 +86,73 @@ impl RuntimeModule {
         }
     }
 
-    /// Serialize a RuntimeModule, prepending its byte id
+    /// Serialize a RuntimeModule into a framed artifact, see `artifact`
     pub fn serialize(&self) -> Result<Vec<u8>> {
-        let (mut ser, id) = match self {
-            RuntimeModule::ASModule(module) => (module.serialize()?, RuntimeModuleId::ASModuleId),
-            RuntimeModule::WasmV1Module(module) => {
-                (module.serialize(), RuntimeModuleId::WasmV1ModuleId)
-            }
+        let (ser, id, fingerprint) = match self {
+            RuntimeModule::ASModule(module) => (
+                module.serialize()?,
+                RuntimeModuleId::ASModuleId,
+                &module.fingerprint,
+            ),
+            RuntimeModule::WasmV1Module(module) => (
+                module.serialize()?,
+                RuntimeModuleId::WasmV1ModuleId,
+                &module.fingerprint,
+            ),
         };
 
-        // CHECK: as we know the size before hand, maybe we can put the id at
-        // the end of the vector, this would prevent a memmove
-        ser.insert(0, id as u8);
-
-        Ok(ser)
+        Ok(artifact::frame(
+            id.into(),
+            &self.compiler(),
+            fingerprint,
+            &ser,
+        ))
     }
 
     /// Deserialize a RuntimeModule
+    ///
+    /// The artifact frame is verified before the module is loaded: the
+    /// artifact must have been produced by the same runtime and wasmer
+    /// versions, be intact, and have been compiled with the given gas limit
+    /// and execution config.
     pub fn deserialize(
         ser_module: &[u8],
         limit: u64,
-        gas_costs: GasCosts,
-        condom_limits: CondomLimits,
-    ) -> Result<Self> {
-        let module_id = ser_module
-            .first()
-            .map(|&id| RuntimeModuleId::try_from(id))
-            .transpose()?;
+        config: &ExecutionConfig,
+    ) -> Result<Self, ArtifactError> {
+        let artifact = artifact::unframe(ser_module)?;
+        let module_id = RuntimeModuleId::try_from(artifact.module_id)
+            .map_err(|_| ArtifactError::UnknownModuleId(artifact.module_id))?;
 
-        match module_id {
-            Some(RuntimeModuleId::ASModuleId) => Ok(RuntimeModule::ASModule(
-                ASModule::deserialize(&ser_module[1..], limit, gas_costs, condom_limits)?,
-            )),
-            Some(RuntimeModuleId::WasmV1ModuleId) => Ok(RuntimeModule::WasmV1Module(
-                WasmV1Module::deserialize(&ser_module[1..], limit, gas_costs, condom_limits)?,
-            )),
-            None => Err(anyhow!("Empty bytecode")),
+        // Check the compilation settings before loading any native code
+        let kind = match module_id {
+            RuntimeModuleId::ASModuleId => ModuleKind::AssemblyScript,
+            RuntimeModuleId::WasmV1ModuleId => ModuleKind::WasmV1,
+        };
+        let fingerprint = artifact::settings_fingerprint(limit, config, kind);
+        if fingerprint != artifact.fingerprint {
+            return Err(ArtifactError::FingerprintMismatch);
         }
+
+        let module = match module_id {
+            RuntimeModuleId::ASModuleId => RuntimeModule::ASModule(ASModule::deserialize(
+                artifact.payload,
+                artifact.compiler,
+                limit,
+                config,
+                fingerprint,
+            )?),
+            RuntimeModuleId::WasmV1ModuleId => {
+                RuntimeModule::WasmV1Module(WasmV1Module::deserialize(
+                    artifact.payload,
+                    artifact.compiler,
+                    limit,
+                    config,
+                    fingerprint,
+                )?)
+            }
+        };
+        Ok(module)
     }
 
     /// Check the exports of a compiled module to see if it contains the given
@@ -134,40 +165,39 @@ impl RuntimeModule {
     }
 }
 
-/// Select and launch the adequate execution function
+/// Select and launch the adequate execution function. The call starts its
+/// timeout, unless it is nested in a call that gives its `deadline`.
 pub(crate) fn exec(
     interface: &dyn Interface,
     rt_module: RuntimeModule,
     function: &str,
     param: &[u8],
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
-) -> VMResult<(Response, Option<GasCalibrationResult>)> {
+    config: &ExecutionConfig,
+    deadline: Option<Deadline>,
+) -> Result<(Response, Option<GasCalibrationResult>), ExecutionFailure> {
+    let config = &config.enter_call();
+    let deadline = deadline.or_else(|| config.timeout().map(Deadline::start));
     let response = match rt_module {
-        RuntimeModule::ASModule(module) => exec_as_module(
-            interface,
-            module,
-            function,
-            param,
-            limit,
-            gas_costs,
-            condom_limits,
-        )?,
+        RuntimeModule::ASModule(module) => {
+            exec_as_module(interface, module, function, param, limit, config, deadline)
+        }
         RuntimeModule::WasmV1Module(module) => exec_wasmv1_module(
-            interface,
-            module,
-            function,
-            param,
-            limit,
-            gas_costs,
-            condom_limits,
+            interface, module, function, param, limit, config, deadline,
         )
-        .map_err(|err| {
-            VMError::InstanceError(format!("Failed to execute WasmV1 module: {}", err))
-        })?,
+        .map_err(|mut failure| {
+            if let VMError::InstanceError { gas, .. } | VMError::ExecutionError { gas, .. } =
+                failure.error
+            {
+                failure.error = VMError::InstanceError {
+                    error: format!("Failed to execute WasmV1 module: {}", failure.error),
+                    gas,
+                };
+            }
+            failure
+        }),
     };
-    Ok(response)
+    response.map_err(|failure| failure.locate(function, config.call_depth()))
 }
 
 /// Library Input, take a `module` wasm built with the massa environment,
@@ -187,19 +217,9 @@ pub fn run_main(
     interface: &dyn Interface,
     rt_module: RuntimeModule,
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
 ) -> VMResult<Response> {
-    Ok(exec(
-        interface,
-        rt_module,
-        settings::MAIN,
-        b"",
-        limit,
-        gas_costs,
-        condom_limits,
-    )?
-    .0)
+    run_main_detailed(interface, rt_module, limit, config).map_err(|failure| failure.error)
 }
 
 /// Library Input, take a `module` wasm built with the massa environment,
@@ -219,21 +239,128 @@ pub fn run_function(
     function: &str,
     param: &[u8],
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
 ) -> VMResult<Response> {
+    run_function_detailed(interface, rt_module, function, param, limit, config)
+        .map_err(|failure| failure.error)
+}
+
+/// Same as run_main but a failure also reports the function and call depth
+/// where it happened, and the trace of the ABI calls made until then
+pub fn run_main_detailed(
+    interface: &dyn Interface,
+    rt_module: RuntimeModule,
+    limit: u64,
+    config: &ExecutionConfig,
+) -> Result<Response, ExecutionFailure> {
     Ok(exec(
         interface,
         rt_module,
-        function,
-        param,
+        settings::MAIN,
+        b"",
         limit,
-        gas_costs,
-        condom_limits,
+        config,
+        None,
+    )?
+    .0)
+}
+
+/// Same as run_function but a failure also reports the function and call
+/// depth where it happened, and the trace of the ABI calls made until then
+pub fn run_function_detailed(
+    interface: &dyn Interface,
+    rt_module: RuntimeModule,
+    function: &str,
+    param: &[u8],
+    limit: u64,
+    config: &ExecutionConfig,
+) -> Result<Response, ExecutionFailure> {
+    Ok(exec(interface, rt_module, function, param, limit, config, None)?.0)
+}
+
+/// Run `function` of `rt_module` as a sub-execution of the call with the
+/// given `deadline`, see `run_function_detailed`
+pub(crate) fn run_sub_function(
+    interface: &dyn Interface,
+    rt_module: RuntimeModule,
+    function: &str,
+    param: &[u8],
+    limit: u64,
+    config: &ExecutionConfig,
+    deadline: Option<Deadline>,
+) -> Result<Response, ExecutionFailure> {
+    Ok(exec(
+        interface, rt_module, function, param, limit, config, deadline,
     )?
     .0)
 }
 
+/// Run a sub-execution between a checkpoint of the interface state, rolled
+/// back if it fails and committed otherwise
+pub(crate) fn with_checkpoint<T>(
+    interface: &dyn Interface,
+    sub_execution: impl FnOnce() -> Result<T, ExecutionFailure>,
+) -> Result<Result<T, ExecutionFailure>, InterfaceError> {
+    let checkpoint = interface.checkpoint()?;
+    let outcome = sub_execution();
+    match outcome {
+        Ok(_) => interface.commit(checkpoint)?,
+        Err(_) => interface.rollback(checkpoint)?,
+    }
+    Ok(outcome)
+}
+
+/// Run `function` of the module returned by `init_call` as the sub-execution
+/// of a try call ABI. Unlike the call ABIs, the interface errors are returned
+/// as failures of the sub-execution, to be reported to the calling contract.
+/// A failure that does not know the gas it consumed, like the ones raised
+/// before the callee starts, uses the whole `limit`.
+pub(crate) fn try_call(
+    interface: &dyn Interface,
+    init_call: impl FnOnce(&dyn Interface) -> crate::types::Result<Vec<u8>>,
+    function: &str,
+    param: &[u8],
+    limit: u64,
+    config: &ExecutionConfig,
+    deadline: Option<Deadline>,
+) -> Result<Response, ExecutionFailure> {
+    let unknown_gas = GasUsage {
+        init_gas_cost: 0,
+        gas_used: limit,
+    };
+    let interface_failure = |error: InterfaceError| {
+        ExecutionFailure::from(VMError::Interface {
+            error: Arc::new(error),
+            gas: unknown_gas,
+        })
+        .locate(function, config.call_depth() + 1)
+    };
+    let bytecode = init_call(interface).map_err(interface_failure)?;
+    let response = interface
+        .get_module(&bytecode, limit)
+        .map_err(interface_failure)
+        .and_then(|module| {
+            interface
+                .increment_recursion_counter()
+                .map_err(interface_failure)?;
+            let response =
+                run_sub_function(interface, module, function, param, limit, config, deadline)
+                    .map_err(|mut failure| {
+                        // raised before the execution of the callee started
+                        if failure.gas_usage() == GasUsage::default() {
+                            failure.error = failure.error.with_gas(function, unknown_gas);
+                        }
+                        failure
+                    });
+            interface
+                .decrement_recursion_counter()
+                .map_err(interface_failure)?;
+            response
+        });
+    interface.finish_call().map_err(interface_failure)?;
+    response
+}
+
 /// Same as run_main but return a GasCalibrationResult
 #[cfg(feature = "gas_calibration")]
 pub fn run_main_gc(
@@ -241,8 +368,7 @@ pub fn run_main_gc(
     rt_module: RuntimeModule,
     param: &[u8],
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
 ) -> VMResult<GasCalibrationResult> {
     Ok(exec(
         interface,
@@ -250,8 +376,8 @@ pub fn run_main_gc(
         settings::MAIN,
         param,
         limit,
-        gas_costs,
-        condom_limits,
+        config,
+        None,
     )?
     .1
     .unwrap())
@@ -265,31 +391,19 @@ fn test_serialize_deserialize() {
     // ASModule
     {
         let module = RuntimeModule::ASModule(
-            ASModule::new(
-                bytecode,
-                0,
-                GasCosts::default(),
-                Compiler::CL,
-                CondomLimits::default(),
-            )
-            .unwrap(),
+            ASModule::new(bytecode, 0, Compiler::CL, &ExecutionConfig::default()).unwrap(),
         );
 
         let serialized = module.serialize().unwrap();
         assert_eq!(
-            *serialized.first().unwrap(),
+            artifact::unframe(&serialized).unwrap().module_id,
             RuntimeModuleId::ASModuleId as u8
         );
 
-        let serialized2 = RuntimeModule::deserialize(
-            &serialized,
-            0,
-            GasCosts::default(),
-            CondomLimits::default(),
-        )
-        .unwrap()
-        .serialize()
-        .unwrap();
+        let serialized2 = RuntimeModule::deserialize(&serialized, 0, &ExecutionConfig::default())
+            .unwrap()
+            .serialize()
+            .unwrap();
 
         assert_eq!(serialized, serialized2);
     }
@@ -297,32 +411,91 @@ fn test_serialize_deserialize() {
     // WasmV1Module
     {
         let module = RuntimeModule::WasmV1Module(
-            WasmV1Module::compile(
-                bytecode,
-                0,
-                GasCosts::default(),
-                Compiler::CL,
-                CondomLimits::default(),
-            )
-            .unwrap(),
+            WasmV1Module::compile(bytecode, 0, Compiler::CL, &ExecutionConfig::default()).unwrap(),
         );
 
         let serialized = module.serialize().unwrap();
         assert_eq!(
-            *serialized.first().unwrap(),
+            artifact::unframe(&serialized).unwrap().module_id,
             RuntimeModuleId::WasmV1ModuleId as u8
         );
 
-        let serialized2 = RuntimeModule::deserialize(
-            &serialized,
-            0,
-            GasCosts::default(),
-            CondomLimits::default(),
-        )
-        .unwrap()
-        .serialize()
-        .unwrap();
+        let serialized2 = RuntimeModule::deserialize(&serialized, 0, &ExecutionConfig::default())
+            .unwrap()
+            .serialize()
+            .unwrap();
 
         assert_eq!(serialized, serialized2);
     }
 }
+
+#[test]
+fn test_deserialize_invalid_artifacts() {
+    let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm"));
+    let module = RuntimeModule::new(bytecode, Compiler::CL, &ExecutionConfig::default()).unwrap();
+    let limit = GasCosts::default().max_instance_cost;
+    let serialized = module.serialize().unwrap();
+    let deserialize = |ser_module: &[u8], gas_costs: GasCosts| {
+        let config = ExecutionConfig::builder()
+            .gas_costs(gas_costs)
+            .build()
+            .unwrap();
+        RuntimeModule::deserialize(ser_module, limit, &config).err()
+    };
+
+    assert!(deserialize(&serialized, GasCosts::default()).is_none());
+
+    assert_eq!(
+        deserialize(&serialized[..10], GasCosts::default()),
+        Some(ArtifactError::Truncated)
+    );
+    assert_eq!(
+        deserialize(b"not an artifact at all", GasCosts::default()),
+        Some(ArtifactError::BadMagic)
+    );
+
+    let mut corrupted = serialized.clone();
+    *corrupted.last_mut().unwrap() ^= 0xff;
+    assert_eq!(
+        deserialize(&corrupted, GasCosts::default()),
+        Some(ArtifactError::ChecksumMismatch)
+    );
+
+    let mut gas_costs = GasCosts::default();
+    gas_costs.operator_cost += 1;
+    assert_eq!(
+        deserialize(&serialized, gas_costs),
+        Some(ArtifactError::FingerprintMismatch)
+    );
+
+    // a module compiled without a timeout has no watchdog
+    let config = ExecutionConfig::builder()
+        .timeout(std::time::Duration::from_secs(60))
+        .build()
+        .unwrap();
+    assert_eq!(
+        RuntimeModule::deserialize(&serialized, limit, &config).err(),
+        Some(ArtifactError::FingerprintMismatch)
+    );
+}
+
+#[test]
+fn test_serialize_deserialize_singlepass() {
+    let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm"));
+    let module = RuntimeModule::new(bytecode, Compiler::SP, &ExecutionConfig::default()).unwrap();
+
+    let serialized = module.serialize().unwrap();
+    assert_eq!(
+        artifact::unframe(&serialized).unwrap().compiler,
+        Compiler::SP
+    );
+
+    let deserialized = RuntimeModule::deserialize(
+        &serialized,
+        GasCosts::default().max_instance_cost,
+        &ExecutionConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(deserialized.compiler(), Compiler::SP);
+    assert!(deserialized.function_exists("main"));
+}
@@ -1,21 +1,50 @@
+mod analysis;
+mod artifact;
 mod as_execution;
+mod backtrace;
+mod config;
 mod error;
 mod execution;
+mod linker;
 mod middlewares;
+mod module_cache;
+#[cfg(feature = "profiling")]
+mod profiling;
+mod recording;
 mod settings;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "execution-trace")]
+mod trace;
 mod tunable_memory;
 mod types;
 mod wasmv1_execution;
 
-pub use error::VMError;
-pub use execution::{run_function, run_main};
+pub use analysis::{
+    analyze_module, AnalysisError, CustomSectionInfo, ExportInfo, ExternKind, ImportInfo,
+    MemoryInfo, ModuleAnalysis, ModuleKind,
+};
+pub use artifact::ArtifactError;
+pub use backtrace::{SourceLocation, WasmBacktrace, WasmFrame};
+pub use config::{ConfigError, ExecutionConfig, ExecutionConfigBuilder};
+pub use error::{AbortLocation, ExecutionFailure, GasUsage, VMError};
+pub use execution::{run_function, run_function_detailed, run_main, run_main_detailed};
 pub use execution::{Compiler, RuntimeModule};
+pub use linker::{ImportIssue, ImportReport};
+pub use middlewares::condom::{CondomLimit, CondomReport, CondomViolation};
+pub use module_cache::{ModuleCache, ModuleCacheConfig, ModuleCacheKey};
+pub use recording::{RecordedCall, Recording, RecordingInterface, ReplayError, ReplayInterface};
 pub use types::*;
+pub use wasmer_types::TrapCode;
 
 #[cfg(feature = "gas_calibration")]
 pub use execution::run_main_gc;
 #[cfg(feature = "gas_calibration")]
 pub use middlewares::gas_calibration::GasCalibrationResult;
+#[cfg(feature = "profiling")]
+pub use profiling::ProfileNode;
+#[cfg(feature = "execution-trace")]
+pub use trace::{decode_traces, encode_traces, TraceDecodeError};
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,245 @@
+//! Pre-instantiation check of the module imports.
+//!
+//! Wasmer reports a missing or mistyped import as an opaque instantiation
+//! error. The imports of a module are checked against the ABIs registered by
+//! the runtime beforehand, reporting every unresolved import, signature
+//! mismatches, the closest known ABI name and whether the import belongs to
+//! the other runtime.
+
+use crate::ModuleKind;
+use std::fmt;
+use wasmer::{AsStoreRef, ExternType, Imports, Module};
+
+/// An import the runtime cannot provide
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportIssue {
+    /// No ABI is registered under this name
+    Unresolved {
+        module: String,
+        name: String,
+        /// Closest registered ABI name
+        suggestion: Option<String>,
+        /// Runtime the import belongs to, if not the one the module targets
+        other_runtime: Option<ModuleKind>,
+    },
+    /// The ABI is registered with another signature
+    SignatureMismatch {
+        module: String,
+        name: String,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for ImportIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportIssue::Unresolved {
+                module,
+                name,
+                suggestion,
+                other_runtime,
+            } => {
+                write!(
+                    f,
+                    "Error while importing \"{}\".\"{}\": unknown import",
+                    module, name
+                )?;
+                if let Some(other_runtime) = other_runtime {
+                    write!(
+                        f,
+                        ", it is an ABI of the {:?} runtime but the module targets the other one",
+                        other_runtime
+                    )?;
+                } else if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean \"{}\"?", suggestion)?;
+                }
+                Ok(())
+            }
+            ImportIssue::SignatureMismatch {
+                module,
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Error while importing \"{}\".\"{}\": incompatible signature, expected {} but found {}",
+                module, name, expected, found
+            ),
+        }
+    }
+}
+
+/// Every import issue found in a module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportReport {
+    pub issues: Vec<ImportIssue>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ImportReport {}
+
+/// Guess the runtime an import belongs to from the ABI naming conventions
+fn import_runtime(module: &str, name: &str) -> Option<ModuleKind> {
+    match module {
+        "massa" if name.starts_with("abi_") => Some(ModuleKind::WasmV1),
+        "massa" if name.starts_with("assembly_script_") => Some(ModuleKind::AssemblyScript),
+        "env" => Some(ModuleKind::AssemblyScript),
+        _ => None,
+    }
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Closest known name, if close enough to be a likely typo
+fn closest_name<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (name.len() / 3).max(2);
+    known
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Check that every import of `module` is provided by `imports`
+pub(crate) fn check_imports(
+    module: &Module,
+    imports: &Imports,
+    store: &impl AsStoreRef,
+    kind: ModuleKind,
+) -> Result<(), ImportReport> {
+    let mut issues = Vec::new();
+    for import in module.imports() {
+        match imports.get_export(import.module(), import.name()) {
+            Some(export) => {
+                let expected = export.ty(store);
+                let found = import.ty();
+                let mismatch = match (&expected, found) {
+                    (ExternType::Function(expected), ExternType::Function(found)) => {
+                        (expected != found).then(|| (expected.to_string(), found.to_string()))
+                    }
+                    (expected, found) => (std::mem::discriminant(expected)
+                        != std::mem::discriminant(found))
+                    .then(|| (format!("{:?}", expected), format!("{:?}", found))),
+                };
+                if let Some((expected, found)) = mismatch {
+                    issues.push(ImportIssue::SignatureMismatch {
+                        module: import.module().to_string(),
+                        name: import.name().to_string(),
+                        expected,
+                        found,
+                    });
+                }
+            }
+            None => {
+                let other_runtime = import_runtime(import.module(), import.name())
+                    .filter(|import_kind| *import_kind != kind);
+                let suggestion = closest_name(
+                    import.name(),
+                    imports
+                        .into_iter()
+                        .filter(|((module, _), _)| module == import.module())
+                        .map(|((_, name), _)| name)
+                        .collect::<Vec<_>>()
+                        .iter()
+                        .map(String::as_str),
+                );
+                issues.push(ImportIssue::Unresolved {
+                    module: import.module().to_string(),
+                    name: import.name().to_string(),
+                    suggestion,
+                    other_runtime,
+                });
+            }
+        }
+    }
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ImportReport { issues })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer::{imports, sys::EngineBuilder, wat2wasm, Cranelift, Function, Store};
+
+    fn module(store: &Store, wat: &str) -> Module {
+        Module::new(store, wat2wasm(wat.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_check_imports() {
+        let mut store = Store::new(EngineBuilder::new(Cranelift::default()));
+        let imports = imports! {
+            "massa" => {
+                "abi_transfer_coins" => Function::new_typed(&mut store, |_: i32| -> i32 { 0 }),
+            }
+        };
+
+        let valid = module(
+            &store,
+            r#"(module (import "massa" "abi_transfer_coins" (func (param i32) (result i32))))"#,
+        );
+        assert!(check_imports(&valid, &imports, &store, ModuleKind::WasmV1).is_ok());
+
+        let invalid = module(
+            &store,
+            r#"(module
+                (import "massa" "abi_transfer_coin" (func (param i32) (result i32)))
+                (import "massa" "abi_transfer_coins" (func (param i64) (result i32)))
+                (import "massa" "assembly_script_print" (func (param i32))))"#,
+        );
+        let report = check_imports(&invalid, &imports, &store, ModuleKind::WasmV1).unwrap_err();
+        assert_eq!(report.issues.len(), 3);
+        assert!(matches!(
+            &report.issues[0],
+            ImportIssue::Unresolved { suggestion: Some(suggestion), other_runtime: None, .. }
+                if suggestion == "abi_transfer_coins"
+        ));
+        assert!(matches!(
+            &report.issues[1],
+            ImportIssue::SignatureMismatch { .. }
+        ));
+        assert!(matches!(
+            &report.issues[2],
+            ImportIssue::Unresolved {
+                other_runtime: Some(ModuleKind::AssemblyScript),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("abi_call", "abi_call"), 0);
+        assert_eq!(edit_distance("abi_cal", "abi_call"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
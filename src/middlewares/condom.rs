@@ -1,227 +1,419 @@
 /// An entry-point protection middleware that prevents malicious WASM files from reaching the compilation step.
 use std::fmt;
+use std::sync::Mutex;
 use wasmer::{
     FunctionMiddleware, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
     ModuleMiddleware,
 };
-use wasmer_types::ModuleInfo;
+use wasmer_types::{ModuleInfo, Type};
 
 use crate::CondomLimits;
 
-/// The module-level export limit middleware, named `CondomMiddleware`.
-pub struct CondomMiddleware {
-    /// Maximum allowed number of exports.
-    limits: CondomLimits,
+/// A `CondomLimits` field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CondomLimit {
+    MaxExports,
+    MaxFunctions,
+    MaxSignatureLen,
+    MaxNameLen,
+    MaxImportsLen,
+    MaxTableInitializersLen,
+    MaxPassiveElementsLen,
+    MaxPassiveDataLen,
+    MaxGlobalInitializersLen,
+    MaxFunctionNamesLen,
+    MaxTablesCount,
+    MaxMemoriesLen,
+    MaxGlobalsLen,
+    MaxCustomSectionsLen,
+    MaxCustomSectionsDataLen,
+    MaxTableSize,
+    MaxGlobalsDataLen,
 }
 
-impl CondomMiddleware {
-    /// Creates a new `CondomMiddleware`.
-    pub fn new(limits: CondomLimits) -> Self {
-        Self { limits }
+impl CondomLimit {
+    /// Name of the `CondomLimits` field
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            CondomLimit::MaxExports => "max_exports",
+            CondomLimit::MaxFunctions => "max_functions",
+            CondomLimit::MaxSignatureLen => "max_signature_len",
+            CondomLimit::MaxNameLen => "max_name_len",
+            CondomLimit::MaxImportsLen => "max_imports_len",
+            CondomLimit::MaxTableInitializersLen => "max_table_initializers_len",
+            CondomLimit::MaxPassiveElementsLen => "max_passive_elements_len",
+            CondomLimit::MaxPassiveDataLen => "max_passive_data_len",
+            CondomLimit::MaxGlobalInitializersLen => "max_global_initializers_len",
+            CondomLimit::MaxFunctionNamesLen => "max_function_names_len",
+            CondomLimit::MaxTablesCount => "max_tables_count",
+            CondomLimit::MaxMemoriesLen => "max_memories_len",
+            CondomLimit::MaxGlobalsLen => "max_globals_len",
+            CondomLimit::MaxCustomSectionsLen => "max_custom_sections_len",
+            CondomLimit::MaxCustomSectionsDataLen => "max_custom_sections_data_len",
+            CondomLimit::MaxTableSize => "max_table_size",
+            CondomLimit::MaxGlobalsDataLen => "max_globals_data_len",
+        }
     }
 }
 
-impl fmt::Debug for CondomMiddleware {
+impl fmt::Display for CondomLimit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("CondomMiddleware")
-            .field("limits", &self.limits)
-            .finish()
+        f.write_str(self.field_name())
     }
 }
 
-impl ModuleMiddleware for CondomMiddleware {
-    /// This method is called before applying middleware on functions,
-    /// and it will enforce the export limits on the module.
-    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
-        // Check the export limits and return an error if exceeded
-        if let Some(max_exports) = self.limits.max_exports {
-            if module_info.exports.len() > max_exports {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many exports. Blocked to prevent compile-time memory bloat",
-                ));
-            }
-        }
+/// A breach of one of the `CondomLimits`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CondomViolation {
+    /// Limit that was exceeded
+    pub limit: CondomLimit,
+    /// Offending item for the limits checked item by item: the function name
+    /// for `MaxFunctionNamesLen`, the section name for
+    /// `MaxCustomSectionsDataLen`
+    pub item: Option<String>,
+    /// Count or length found in the module
+    pub observed: usize,
+    /// Configured maximum
+    pub max: usize,
+}
 
-        // Check the number of function limits
-        if let Some(max_functions) = self.limits.max_functions {
-            if module_info.functions.len() > max_functions {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many functions. Blocked to prevent compile-time memory bloat",
-                ));
+impl fmt::Display for CondomViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item = self.item.as_deref().unwrap_or_default();
+        match self.limit {
+            CondomLimit::MaxExports => write!(f, "The WASM file has too many exports")?,
+            CondomLimit::MaxFunctions => write!(f, "The WASM file has too many functions")?,
+            CondomLimit::MaxSignatureLen => write!(
+                f,
+                "The WASM file contains a function with too many parameters and return values"
+            )?,
+            CondomLimit::MaxNameLen => write!(f, "The WASM file has a too long name")?,
+            CondomLimit::MaxImportsLen => write!(f, "The WASM file has too many imports")?,
+            CondomLimit::MaxTableInitializersLen => {
+                write!(f, "The WASM file has too many table initializers")?
             }
-        }
-
-        // Check the signature limits i.e. the number of arguments a function
-        // can take.
-        // NOTE, at the moment (2024-09-30) :
-        // - functions can returns only one value, see:
-        // https://developer.mozilla.org/en-US/docs/WebAssembly/Understanding_the_text_format#signatures_and_parameters
-        // - tweaking test_condom_middleware_exceeds_args_limit_max(), one can
-        //   see that wasmer limits number of arguments to 1024
-        if let Some(max_signature_len) = self.limits.max_signature_len {
-            for signature in module_info.signatures.values() {
-                if signature.params().len() + signature.results().len() > max_signature_len {
-                    return Err(MiddlewareError::new(
-                        "CondomMiddleware",
-                        "The WASM file contains a function with too many parameters and return values. Blocked to prevent compile-time memory bloat",
-                    ));
-                }
+            CondomLimit::MaxPassiveElementsLen => {
+                write!(f, "The WASM file has too many passive elements")?
             }
-        }
-
-        // Check the name limit
-        if let Some(max_name_len) = self.limits.max_name_len {
-            if let Some(name) = &module_info.name {
-                if name.len() > max_name_len {
-                    dbg!(max_name_len, name.len());
-                    return Err(MiddlewareError::new(
-                        "CondomMiddleware",
-                        "The WASM file has a too long name. Blocked to prevent compile-time memory bloat",
-                    ));
-                }
+            CondomLimit::MaxPassiveDataLen => write!(f, "The WASM file has too many passive data")?,
+            CondomLimit::MaxGlobalInitializersLen => {
+                write!(f, "The WASM file has too many global initializers")?
             }
-        }
-
-        // Check the import limits
-        if let Some(max_imports) = self.limits.max_imports_len {
-            if module_info.imports.len() > max_imports {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many imports. Blocked to prevent compile-time memory bloat",
-                ));
+            CondomLimit::MaxFunctionNamesLen => {
+                write!(f, "The WASM file has too long function names ({})", item)?
             }
-        }
-
-        // Check the table initializer limits
-        if let Some(max_table_initializers) = self.limits.max_table_initializers_len {
-            if module_info.table_initializers.len() > max_table_initializers {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many table initializers. Blocked to prevent compile-time memory bloat",
-                ));
+            CondomLimit::MaxTablesCount => write!(f, "The WASM file has too many tables")?,
+            CondomLimit::MaxMemoriesLen => write!(f, "The WASM file has too many memories")?,
+            CondomLimit::MaxGlobalsLen => write!(f, "The WASM file has too many globals")?,
+            CondomLimit::MaxCustomSectionsLen => {
+                write!(f, "The WASM file has too many custom sections")?
             }
-        }
-
-        // Check passive elements limits
-        if let Some(max_passive_elements) = self.limits.max_passive_elements_len {
-            if module_info.passive_elements.len() > max_passive_elements {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many passive elements. Blocked to prevent compile-time memory bloat",
-                ));
+            CondomLimit::MaxCustomSectionsDataLen => {
+                write!(f, "The WASM file custom section '{}' is too big", item)?
             }
+            CondomLimit::MaxTableSize => write!(f, "The WASM file has a too big table")?,
+            CondomLimit::MaxGlobalsDataLen => write!(f, "The WASM file has too much global data")?,
         }
+        write!(
+            f,
+            " (contains: {}, {}: {}). Blocked to prevent compile-time memory bloat",
+            self.observed, self.limit, self.max
+        )
+    }
+}
+
+/// Every condom violation found in a module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CondomReport {
+    pub violations: Vec<CondomViolation>,
+}
 
-        // Check passive data limits
-        if let Some(max_passive_data) = self.limits.max_passive_data_len {
-            if module_info.passive_data.len() > max_passive_data {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many passive data. Blocked to prevent compile-time memory bloat",
-                ));
+impl fmt::Display for CondomReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
             }
+            write!(f, "{}", violation)?;
         }
+        Ok(())
+    }
+}
 
-        // Check the global initializer limits
-        if let Some(max_global_initializers) = self.limits.max_global_initializers_len {
-            if module_info.global_initializers.len() > max_global_initializers {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    format!("The WASM file has too many global initializers (contains; {} limit: {}). Blocked to prevent compile-time memory bloat",
-                        module_info.global_initializers.len(),
-                        max_global_initializers,
-                    ),
-                ));
-            }
+impl std::error::Error for CondomReport {}
+
+/// Shape of a module, as far as the condom limits are concerned
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ModuleShape {
+    pub(crate) exports: usize,
+    pub(crate) functions: usize,
+    /// Number of parameters and return values of every signature
+    pub(crate) signature_lens: Vec<usize>,
+    pub(crate) name: Option<String>,
+    pub(crate) imports: usize,
+    pub(crate) table_initializers: usize,
+    pub(crate) passive_elements: usize,
+    pub(crate) passive_data: usize,
+    pub(crate) global_initializers: usize,
+    pub(crate) function_names: Vec<String>,
+    pub(crate) tables: usize,
+    pub(crate) memories: usize,
+    pub(crate) globals: usize,
+    pub(crate) custom_sections: usize,
+    /// Name and data length of every custom section
+    pub(crate) custom_sections_data: Vec<(String, usize)>,
+    /// Initial number of elements of every table
+    pub(crate) table_sizes: Vec<usize>,
+    /// Size in bytes of the values of every global
+    pub(crate) globals_data_len: usize,
+}
+
+/// Size in bytes of a value of the given type
+pub(crate) fn value_size(ty: Type) -> usize {
+    match ty {
+        Type::I32 | Type::F32 => 4,
+        Type::I64 | Type::F64 | Type::ExternRef | Type::FuncRef => 8,
+        Type::V128 => 16,
+    }
+}
+
+impl ModuleShape {
+    pub(crate) fn from_module_info(module_info: &ModuleInfo) -> Self {
+        // sort the names so that the reported violation does not depend on
+        // the hash map order
+        let mut function_names: Vec<_> = module_info.function_names.iter().collect();
+        function_names.sort_by_key(|(index, _)| index.as_u32());
+
+        Self {
+            exports: module_info.exports.len(),
+            functions: module_info.functions.len(),
+            signature_lens: module_info
+                .signatures
+                .values()
+                .map(|signature| signature.params().len() + signature.results().len())
+                .collect(),
+            name: module_info.name.clone(),
+            imports: module_info.imports.len(),
+            table_initializers: module_info.table_initializers.len(),
+            passive_elements: module_info.passive_elements.len(),
+            passive_data: module_info.passive_data.len(),
+            global_initializers: module_info.global_initializers.len(),
+            function_names: function_names
+                .into_iter()
+                .map(|(_, name)| name.clone())
+                .collect(),
+            tables: module_info.tables.len(),
+            memories: module_info.memories.len(),
+            globals: module_info.globals.len(),
+            custom_sections: module_info.custom_sections.len(),
+            custom_sections_data: module_info
+                .custom_sections_data
+                .iter()
+                .map(|(idx, data)| {
+                    let section_name = module_info
+                        .custom_sections
+                        .get_index(idx.as_u32() as usize)
+                        .map(|(name, _)| name.to_owned())
+                        .unwrap_or("Unknown section".to_string());
+                    (section_name, data.len())
+                })
+                .collect(),
+            table_sizes: module_info
+                .tables
+                .values()
+                .map(|table| table.minimum as usize)
+                .collect(),
+            globals_data_len: module_info
+                .globals
+                .values()
+                .map(|global| value_size(global.ty))
+                .sum(),
         }
+    }
+}
 
-        // Check the function name limits
-        if let Some(max_function_names) = self.limits.max_function_names_len {
-            for (_, f_name) in module_info.function_names.iter() {
-                if f_name.len() > max_function_names {
-                    return Err(MiddlewareError::new(
-                        "CondomMiddleware",
-                        format!("The WASM file has too long function names ({} is {}). Blocked to prevent compile-time memory bloat",
-                            f_name,
-                            f_name.len()),
-                ));
+impl CondomLimits {
+    /// Check a module against every limit, in a fixed order
+    pub(crate) fn check(&self, shape: &ModuleShape) -> Vec<CondomViolation> {
+        let mut violations = Vec::new();
+        let mut check = |limit: CondomLimit, max: Option<usize>, item: Option<&str>, observed| {
+            if let Some(max) = max {
+                if observed > max {
+                    violations.push(CondomViolation {
+                        limit,
+                        item: item.map(str::to_string),
+                        observed,
+                        max,
+                    });
                 }
             }
-        }
+        };
 
-        // Check the table limits
-        if let Some(max_tables) = self.limits.max_tables_count {
-            if module_info.tables.len() > max_tables {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    format!("The WASM file has too many tables (contains: {} limit: {}). Blocked to prevent compile-time memory bloat",
-                    module_info.tables.len(),
-                    max_tables,
-                    ),
-                ));
-            }
+        check(
+            CondomLimit::MaxExports,
+            self.max_exports,
+            None,
+            shape.exports,
+        );
+        check(
+            CondomLimit::MaxFunctions,
+            self.max_functions,
+            None,
+            shape.functions,
+        );
+        // NOTE, at the moment (2024-09-30) :
+        // - functions can returns only one value, see:
+        // https://developer.mozilla.org/en-US/docs/WebAssembly/Understanding_the_text_format#signatures_and_parameters
+        // - tweaking test_condom_middleware_exceeds_args_limit_max(), one can
+        //   see that wasmer limits number of arguments to 1024
+        for signature_len in &shape.signature_lens {
+            check(
+                CondomLimit::MaxSignatureLen,
+                self.max_signature_len,
+                None,
+                *signature_len,
+            );
         }
-
-        // Check the memory limits
-        if let Some(max_memories) = self.limits.max_memories_len {
-            if module_info.memories.len() > max_memories {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many memories. Blocked to prevent compile-time memory bloat",
-                ));
-            }
+        if let Some(name) = &shape.name {
+            check(
+                CondomLimit::MaxNameLen,
+                self.max_name_len,
+                Some(name.as_str()),
+                name.len(),
+            );
         }
-
-        // Check the global limits
-        if let Some(max_globals) = self.limits.max_globals_len {
-            if module_info.globals.len() > max_globals {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    format!("The WASM file has too many globals (contains: {}, limit: {}). Blocked to prevent compile-time memory bloat",
-                        module_info.globals.len(),
-                        max_globals
-                    )
-                ));
-            }
+        check(
+            CondomLimit::MaxImportsLen,
+            self.max_imports_len,
+            None,
+            shape.imports,
+        );
+        check(
+            CondomLimit::MaxTableInitializersLen,
+            self.max_table_initializers_len,
+            None,
+            shape.table_initializers,
+        );
+        check(
+            CondomLimit::MaxPassiveElementsLen,
+            self.max_passive_elements_len,
+            None,
+            shape.passive_elements,
+        );
+        check(
+            CondomLimit::MaxPassiveDataLen,
+            self.max_passive_data_len,
+            None,
+            shape.passive_data,
+        );
+        check(
+            CondomLimit::MaxGlobalInitializersLen,
+            self.max_global_initializers_len,
+            None,
+            shape.global_initializers,
+        );
+        for name in &shape.function_names {
+            check(
+                CondomLimit::MaxFunctionNamesLen,
+                self.max_function_names_len,
+                Some(name.as_str()),
+                name.len(),
+            );
         }
-
-        // custom_sections: IndexMap<String, CustomSectionIndex>,
-        // Check the custom section limits
-        if let Some(max_custom_sections) = self.limits.max_custom_sections_len {
-            if module_info.custom_sections.len() > max_custom_sections {
-                return Err(MiddlewareError::new(
-                    "CondomMiddleware",
-                    "The WASM file has too many custom sections. Blocked to prevent compile-time memory bloat",
-                ));
-            }
+        check(
+            CondomLimit::MaxTablesCount,
+            self.max_tables_count,
+            None,
+            shape.tables,
+        );
+        check(
+            CondomLimit::MaxMemoriesLen,
+            self.max_memories_len,
+            None,
+            shape.memories,
+        );
+        check(
+            CondomLimit::MaxGlobalsLen,
+            self.max_globals_len,
+            None,
+            shape.globals,
+        );
+        check(
+            CondomLimit::MaxCustomSectionsLen,
+            self.max_custom_sections_len,
+            None,
+            shape.custom_sections,
+        );
+        for (name, len) in &shape.custom_sections_data {
+            check(
+                CondomLimit::MaxCustomSectionsDataLen,
+                self.max_custom_sections_data_len,
+                Some(name.as_str()),
+                *len,
+            );
+        }
+        for size in &shape.table_sizes {
+            check(CondomLimit::MaxTableSize, self.max_table_size, None, *size);
         }
+        check(
+            CondomLimit::MaxGlobalsDataLen,
+            self.max_globals_data_len,
+            None,
+            shape.globals_data_len,
+        );
 
-        // Check the custom section data limits
-        if let Some(max_custom_sections_data) = self.limits.max_custom_sections_data_len {
-            for (idx, data) in module_info.custom_sections_data.iter() {
-                if data.len() > max_custom_sections_data {
-                    let section_name = module_info
-                        .custom_sections
-                        .get_index(idx.as_u32() as usize)
-                        .map(|(name, _)| name.to_owned())
-                        .unwrap_or("Unknown section".to_string());
+        violations
+    }
+}
 
-                    return Err(MiddlewareError::new(
-                        "CondomMiddleware",
-                        format!("The WASM file custom section {:?} named '{}' with size {} is too big (limit: {}). Blocked to prevent compile-time memory bloat",
-                            idx,
-                            section_name,
-                            data.len(),
-                            max_custom_sections_data,
-                        ),
-                    ));
-                };
-            }
+/// The module-level export limit middleware, named `CondomMiddleware`.
+pub struct CondomMiddleware {
+    /// Maximum allowed number of exports.
+    limits: CondomLimits,
+    /// Violations found by the last `transform_module_info` call
+    report: Mutex<Option<CondomReport>>,
+}
+
+impl CondomMiddleware {
+    /// Creates a new `CondomMiddleware`.
+    pub fn new(limits: CondomLimits) -> Self {
+        Self {
+            limits,
+            report: Mutex::new(None),
         }
+    }
 
-        Ok(())
+    /// Take the violations that made the last compilation fail, if any
+    pub fn take_report(&self) -> Option<CondomReport> {
+        self.report.lock().unwrap().take()
+    }
+}
+
+impl fmt::Debug for CondomMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CondomMiddleware")
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+impl ModuleMiddleware for CondomMiddleware {
+    /// This method is called before applying middleware on functions,
+    /// and it will enforce the export limits on the module.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut violations = self
+            .limits
+            .check(&ModuleShape::from_module_info(module_info));
+        if violations.is_empty() {
+            return Ok(());
+        }
+        if !self.limits.collect_all_violations {
+            violations.truncate(1);
+        }
+        let report = CondomReport { violations };
+        let error = MiddlewareError::new("CondomMiddleware", report.to_string());
+        *self.report.lock().unwrap() = Some(report);
+        Err(error)
     }
 
     /// Generates a `FunctionMiddleware` for a given function.
@@ -497,6 +689,108 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_condom_middleware_reports_violation() {
+        let condom_limits = CondomLimits {
+            max_exports: Some(2),
+            max_functions: Some(1),
+            ..Default::default()
+        };
+        let condom_middleware = Arc::new(CondomMiddleware::new(condom_limits));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(condom_middleware.clone());
+
+        let store = Store::new(EngineBuilder::new(compiler_config));
+        assert!(Module::new(&store, bytecode_exceeding_exports_limits()).is_err());
+
+        // Only the first violation is reported by default
+        let report = condom_middleware.take_report().unwrap();
+        assert_eq!(
+            report.violations,
+            vec![CondomViolation {
+                limit: CondomLimit::MaxExports,
+                item: None,
+                observed: 5,
+                max: 2,
+            }]
+        );
+        assert!(condom_middleware.take_report().is_none());
+    }
+
+    #[test]
+    fn test_condom_middleware_collects_all_violations() {
+        let condom_limits = CondomLimits {
+            max_exports: Some(2),
+            max_functions: Some(1),
+            max_globals_len: Some(2),
+            collect_all_violations: true,
+            ..Default::default()
+        };
+        let condom_middleware = Arc::new(CondomMiddleware::new(condom_limits));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(condom_middleware.clone());
+
+        let store = Store::new(EngineBuilder::new(compiler_config));
+        let error = Module::new(&store, bytecode_exceeding_exports_limits()).unwrap_err();
+        assert!(error.to_string().contains("too many exports"));
+        assert!(error.to_string().contains("too many functions"));
+
+        let report = condom_middleware.take_report().unwrap();
+        let limits: Vec<_> = report
+            .violations
+            .iter()
+            .map(|violation| (violation.limit, violation.observed, violation.max))
+            .collect();
+        assert_eq!(
+            limits,
+            vec![
+                (CondomLimit::MaxExports, 5, 2),
+                (CondomLimit::MaxFunctions, 3, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_condom_middleware_table_and_globals_limits() {
+        let condom_limits = CondomLimits {
+            max_table_size: Some(100),
+            max_globals_data_len: Some(8),
+            collect_all_violations: true,
+            ..Default::default()
+        };
+        let condom_middleware = Arc::new(CondomMiddleware::new(condom_limits));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(condom_middleware.clone());
+
+        let store = Store::new(EngineBuilder::new(compiler_config));
+        let bytecode = wat2wasm(
+            br#"
+            (module
+                (table 1000 funcref)
+                (global i32 (i32.const 0))
+                (global i64 (i64.const 0))
+            )
+            "#,
+        )
+        .unwrap();
+        let error = Module::new(&store, bytecode).unwrap_err();
+        assert!(error.to_string().contains("too big table"));
+
+        let report = condom_middleware.take_report().unwrap();
+        let limits: Vec<_> = report
+            .violations
+            .iter()
+            .map(|violation| (violation.limit, violation.observed, violation.max))
+            .collect();
+        assert_eq!(
+            limits,
+            vec![
+                (CondomLimit::MaxTableSize, 1000, 100),
+                (CondomLimit::MaxGlobalsDataLen, 12, 8)
+            ]
+        );
+    }
+
     use sysinfo::{Pid, System};
     fn get_memory_usage() -> Result<u64, String> {
         let mut system = System::new_all();
@@ -1216,6 +1510,9 @@ mod tests {
             max_globals_len: Some(nb_global_ini),
             max_custom_sections_len: Some(1),
             max_custom_sections_data_len: Some(custon_section_data_len),
+            max_table_size: None,
+            max_globals_data_len: None,
+            collect_all_violations: false,
         };
 
         let condom_middleware = Arc::new(CondomMiddleware::new(condom_limits.clone()));
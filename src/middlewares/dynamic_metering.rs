@@ -0,0 +1,315 @@
+//! Dynamic gas metering of memory operators.
+//!
+//! The `Metering` middleware charges every operator a static cost known at
+//! compile time. `memory.grow`, `memory.copy`, `memory.fill` and `memory.init`
+//! however work on a number of pages or bytes only known at runtime. This
+//! middleware injects, before each of these operators, a check that charges
+//! `operand * unit_cost` on the metering globals and traps with the points
+//! exhausted flag set if the remaining points do not cover it.
+//!
+//! It must be pushed *after* the `Metering` middleware as it relies on the
+//! globals it exports.
+
+use crate::GasCosts;
+use std::fmt::{self, Debug};
+use std::sync::Mutex;
+use wasmer::{
+    wasmparser::{BlockType, Operator},
+    FunctionMiddleware, LocalFunctionIndex, MiddlewareError, MiddlewareReaderState,
+    ModuleMiddleware,
+};
+use wasmer_types::{
+    ExportIndex, GlobalIndex, GlobalInit, GlobalType, ModuleInfo, Mutability, Type,
+};
+
+const REMAINING_POINTS_EXPORT: &str = "wasmer_metering_remaining_points";
+const POINTS_EXHAUSTED_EXPORT: &str = "wasmer_metering_points_exhausted";
+
+/// Runtime costs of the memory operators, per page for `memory.grow` and per
+/// byte for the bulk memory operators
+#[derive(Debug, Clone, Copy)]
+struct MemoryOperatorCosts {
+    grow_page: u64,
+    copy_byte: u64,
+    fill_byte: u64,
+    init_byte: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DynamicMeteringGlobalIndexes {
+    remaining_points: GlobalIndex,
+    points_exhausted: GlobalIndex,
+    /// Scratch global used to duplicate the operand on top of the stack
+    operand: GlobalIndex,
+}
+
+pub struct DynamicMetering {
+    costs: MemoryOperatorCosts,
+    /// The global indexes used by the injected code.
+    global_indexes: Mutex<Option<DynamicMeteringGlobalIndexes>>,
+}
+
+#[derive(Debug)]
+pub struct FunctionDynamicMetering {
+    costs: MemoryOperatorCosts,
+    global_indexes: DynamicMeteringGlobalIndexes,
+}
+
+impl DynamicMetering {
+    pub fn new(gas_costs: &GasCosts) -> Self {
+        // Unit costs are capped to u32::MAX so that `operand * unit_cost`
+        // always fits in an u64 (the operand is an u32)
+        let cap = |cost: u64| cost.min(u32::MAX as u64);
+        Self {
+            costs: MemoryOperatorCosts {
+                grow_page: cap(gas_costs.memory_grow_page_cost),
+                copy_byte: cap(gas_costs.memory_copy_byte_cost),
+                fill_byte: cap(gas_costs.memory_fill_byte_cost),
+                init_byte: cap(gas_costs.memory_init_byte_cost),
+            },
+            global_indexes: Mutex::new(None),
+        }
+    }
+}
+
+impl Debug for DynamicMetering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynamicMetering")
+            .field("costs", &self.costs)
+            .finish()
+    }
+}
+
+impl ModuleMiddleware for DynamicMetering {
+    fn generate_function_middleware(
+        &self,
+        _local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionDynamicMetering {
+            costs: self.costs,
+            global_indexes: self.global_indexes.lock().unwrap().unwrap(),
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut global_indexes = self.global_indexes.lock().unwrap();
+        if global_indexes.is_some() {
+            panic!("DynamicMetering::transform_module_info: Attempting to use a `DynamicMetering` middleware from multiple modules.");
+        }
+
+        let metering_global = |name: &str| match module_info.exports.get(name) {
+            Some(ExportIndex::Global(index)) => Ok(*index),
+            _ => Err(MiddlewareError::new(
+                "DynamicMetering",
+                format!(
+                    "metering global {} not found, Metering middleware must come first",
+                    name
+                ),
+            )),
+        };
+        let remaining_points = metering_global(REMAINING_POINTS_EXPORT)?;
+        let points_exhausted = metering_global(POINTS_EXHAUSTED_EXPORT)?;
+
+        let operand = module_info
+            .globals
+            .push(GlobalType::new(Type::I32, Mutability::Var));
+        module_info
+            .global_initializers
+            .push(GlobalInit::I32Const(0));
+
+        *global_indexes = Some(DynamicMeteringGlobalIndexes {
+            remaining_points,
+            points_exhausted,
+            operand,
+        });
+
+        Ok(())
+    }
+}
+
+impl FunctionDynamicMetering {
+    /// Push `operand * unit_cost` as an i64 on the stack
+    fn push_cost(&self, unit_cost: u64, state: &mut MiddlewareReaderState<'_>) {
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: self.global_indexes.operand.as_u32(),
+            },
+            Operator::I64ExtendI32U,
+            Operator::I64Const {
+                value: unit_cost as i64,
+            },
+            Operator::I64Mul,
+        ]);
+    }
+
+    /// Charge `operand * unit_cost` where operand is the i32 on top of the
+    /// stack, leaving the stack untouched
+    fn charge_operand(&self, unit_cost: u64, state: &mut MiddlewareReaderState<'_>) {
+        let remaining_points = self.global_indexes.remaining_points.as_u32();
+        let operand = self.global_indexes.operand.as_u32();
+
+        // Save the operand while keeping it on the stack
+        state.extend(&[
+            Operator::GlobalSet {
+                global_index: operand,
+            },
+            Operator::GlobalGet {
+                global_index: operand,
+            },
+        ]);
+
+        // if remaining_points < cost { points_exhausted = 1; unreachable }
+        state.push_operator(Operator::GlobalGet {
+            global_index: remaining_points,
+        });
+        self.push_cost(unit_cost, state);
+        state.extend(&[
+            Operator::I64LtU,
+            Operator::If {
+                blockty: BlockType::Empty,
+            },
+            Operator::I32Const { value: 1 },
+            Operator::GlobalSet {
+                global_index: self.global_indexes.points_exhausted.as_u32(),
+            },
+            Operator::Unreachable,
+            Operator::End,
+        ]);
+
+        // remaining_points -= cost
+        state.push_operator(Operator::GlobalGet {
+            global_index: remaining_points,
+        });
+        self.push_cost(unit_cost, state);
+        state.extend(&[
+            Operator::I64Sub,
+            Operator::GlobalSet {
+                global_index: remaining_points,
+            },
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionDynamicMetering {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        // For all these operators the page count or byte length is the
+        // operand on top of the stack
+        let unit_cost = match operator {
+            Operator::MemoryGrow { .. } => self.costs.grow_page,
+            Operator::MemoryCopy { .. } => self.costs.copy_byte,
+            Operator::MemoryFill { .. } => self.costs.fill_byte,
+            Operator::MemoryInit { .. } => self.costs.init_byte,
+            _ => 0,
+        };
+        if unit_cost > 0 {
+            self.charge_operand(unit_cost, state);
+        }
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use wasmer::{
+        imports, sys::EngineBuilder, wat2wasm, CompilerConfig, Cranelift, Instance, Module, Store,
+        TypedFunction,
+    };
+    use wasmer_middlewares::{
+        metering::{get_remaining_points, MeteringPoints},
+        Metering,
+    };
+
+    const LIMIT: u64 = 1_000_000;
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+                (memory 1)
+                (func (export "fill") (param i32)
+                    (memory.fill (i32.const 0) (i32.const 42) (local.get 0)))
+                (func (export "grow") (param i32) (result i32)
+                    (memory.grow (local.get 0)))
+            )
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn instantiate(gas_costs: &GasCosts) -> (Store, Instance) {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(Metering::new(LIMIT, |_: &Operator| 1)));
+        compiler_config.push_middleware(Arc::new(DynamicMetering::new(gas_costs)));
+        let mut store = Store::new(EngineBuilder::new(compiler_config));
+        let module = Module::new(&store, bytecode()).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+        (store, instance)
+    }
+
+    fn remaining_after_fill(gas_costs: &GasCosts, len: i32) -> MeteringPoints {
+        let (mut store, instance) = instantiate(gas_costs);
+        let fill: TypedFunction<i32, ()> =
+            instance.exports.get_typed_function(&store, "fill").unwrap();
+        let _ = fill.call(&mut store, len);
+        get_remaining_points(&mut store, &instance)
+    }
+
+    fn remaining(points: MeteringPoints) -> u64 {
+        match points {
+            MeteringPoints::Remaining(points) => points,
+            MeteringPoints::Exhausted => panic!("points should not be exhausted"),
+        }
+    }
+
+    #[test]
+    fn memory_fill_is_charged_per_byte() {
+        let gas_costs = GasCosts {
+            memory_fill_byte_cost: 2,
+            ..Default::default()
+        };
+
+        let empty = remaining(remaining_after_fill(&gas_costs, 0));
+        let filled = remaining(remaining_after_fill(&gas_costs, 1000));
+        assert_eq!(empty - filled, 2 * 1000);
+    }
+
+    #[test]
+    fn memory_fill_exhausts_points() {
+        let gas_costs = GasCosts {
+            memory_fill_byte_cost: LIMIT,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            remaining_after_fill(&gas_costs, 2),
+            MeteringPoints::Exhausted
+        );
+    }
+
+    #[test]
+    fn memory_grow_is_charged_per_page() {
+        let gas_costs = GasCosts {
+            memory_grow_page_cost: 100,
+            ..Default::default()
+        };
+
+        let (mut store, instance) = instantiate(&gas_costs);
+        let grow: TypedFunction<i32, i32> =
+            instance.exports.get_typed_function(&store, "grow").unwrap();
+        // Growing by 0 pages only costs the static cost of the call
+        grow.call(&mut store, 0).unwrap();
+        let before = remaining(get_remaining_points(&mut store, &instance));
+        let static_cost = LIMIT - before;
+        grow.call(&mut store, 3).unwrap();
+        let after = remaining(get_remaining_points(&mut store, &instance));
+        assert_eq!(before - after, static_cost + 3 * 100);
+    }
+}
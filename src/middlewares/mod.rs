@@ -1,4 +1,8 @@
 pub mod condom;
 pub mod dumper;
+pub mod dynamic_metering;
 pub mod gas_calibration;
 pub mod operator;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod watchdog;
@@ -0,0 +1,254 @@
+//! Gas attribution to the local functions of a module.
+//!
+//! Every local function gets an exported `i64` global accumulating the
+//! metering points it consumed itself, its callees excluded. The points are
+//! measured on the `Metering` remaining points global at each point where the
+//! control leaves or comes back to the function: function entry, calls and
+//! returns.
+//!
+//! Before each call the index of the calling function is stored in a global,
+//! and after each call to an imported function the remaining points are
+//! stored in another one, so that the host can attribute ABI calls to their
+//! caller, see `crate::profiling::Profiler`.
+//!
+//! It must be pushed *after* the `Metering` and `DynamicMetering` middlewares
+//! so that the injected code is not metered.
+
+use std::fmt::{self, Debug};
+use std::sync::Mutex;
+use wasmer::{
+    wasmparser::Operator, FunctionMiddleware, LocalFunctionIndex, MiddlewareError,
+    MiddlewareReaderState, ModuleMiddleware,
+};
+use wasmer_types::{
+    ExportIndex, FunctionIndex, GlobalIndex, GlobalInit, GlobalType, ModuleInfo, Mutability, Type,
+};
+
+const REMAINING_POINTS_EXPORT: &str = "wasmer_metering_remaining_points";
+/// Index of the function that made the last call
+pub(crate) const CURRENT_FUNCTION_EXPORT: &str = "wprof_current_function";
+/// Remaining points right after the last call to an imported function
+pub(crate) const IMPORT_RETURN_EXPORT: &str = "wprof_import_return_points";
+/// Prefix of the per-function gas globals, followed by `{index}:{name}`
+pub(crate) const FUNCTION_GAS_PREFIX: &str = "wprof_fn:";
+
+#[derive(Debug, Clone)]
+struct ProfilingGlobalIndexes {
+    remaining_points: GlobalIndex,
+    /// Remaining points when the running function last got the control back
+    last_points: GlobalIndex,
+    current_function: GlobalIndex,
+    import_return_points: GlobalIndex,
+    /// Gas global of every local function
+    function_gas: Vec<GlobalIndex>,
+    num_imported_functions: u32,
+}
+
+pub struct Profiling {
+    /// The global indexes used by the injected code.
+    global_indexes: Mutex<Option<ProfilingGlobalIndexes>>,
+}
+
+#[derive(Debug)]
+pub struct FunctionProfiling {
+    global_indexes: ProfilingGlobalIndexes,
+    function_index: u32,
+    function_gas: GlobalIndex,
+    /// Depth of the blocks opened in the function body, the function body
+    /// itself included
+    depth: usize,
+    started: bool,
+}
+
+impl Profiling {
+    pub fn new() -> Self {
+        Self {
+            global_indexes: Mutex::new(None),
+        }
+    }
+}
+
+impl Debug for Profiling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiling").finish()
+    }
+}
+
+fn push_global(module_info: &mut ModuleInfo, ty: Type, init: GlobalInit) -> GlobalIndex {
+    let index = module_info
+        .globals
+        .push(GlobalType::new(ty, Mutability::Var));
+    module_info.global_initializers.push(init);
+    index
+}
+
+impl ModuleMiddleware for Profiling {
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let global_indexes = self.global_indexes.lock().unwrap().clone().unwrap();
+        Box::new(FunctionProfiling {
+            function_index: global_indexes.num_imported_functions + local_function_index.as_u32(),
+            function_gas: global_indexes.function_gas[local_function_index.index()],
+            global_indexes,
+            depth: 1,
+            started: false,
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut global_indexes = self.global_indexes.lock().unwrap();
+        if global_indexes.is_some() {
+            panic!("Profiling::transform_module_info: Attempting to use a `Profiling` middleware from multiple modules.");
+        }
+
+        let remaining_points = match module_info.exports.get(REMAINING_POINTS_EXPORT) {
+            Some(ExportIndex::Global(index)) => *index,
+            _ => {
+                return Err(MiddlewareError::new(
+                    "Profiling",
+                    "metering global not found, Metering middleware must come first",
+                ))
+            }
+        };
+
+        let last_points = push_global(module_info, Type::I64, GlobalInit::I64Const(0));
+        let current_function = push_global(module_info, Type::I32, GlobalInit::I32Const(-1));
+        module_info.exports.insert(
+            CURRENT_FUNCTION_EXPORT.to_string(),
+            ExportIndex::Global(current_function),
+        );
+        let import_return_points = push_global(module_info, Type::I64, GlobalInit::I64Const(0));
+        module_info.exports.insert(
+            IMPORT_RETURN_EXPORT.to_string(),
+            ExportIndex::Global(import_return_points),
+        );
+
+        let num_imported_functions = module_info.num_imported_functions as u32;
+        let num_local_functions = module_info.functions.len() as u32 - num_imported_functions;
+        let mut function_gas = Vec::with_capacity(num_local_functions as usize);
+        for local_index in 0..num_local_functions {
+            let index = num_imported_functions + local_index;
+            let name = module_info
+                .function_names
+                .get(&FunctionIndex::from_u32(index))
+                .cloned()
+                .unwrap_or_else(|| format!("func{}", index));
+            let global_index = push_global(module_info, Type::I64, GlobalInit::I64Const(0));
+            module_info.exports.insert(
+                format!("{}{}:{}", FUNCTION_GAS_PREFIX, index, name),
+                ExportIndex::Global(global_index),
+            );
+            function_gas.push(global_index);
+        }
+
+        *global_indexes = Some(ProfilingGlobalIndexes {
+            remaining_points,
+            last_points,
+            current_function,
+            import_return_points,
+            function_gas,
+            num_imported_functions,
+        });
+
+        Ok(())
+    }
+}
+
+impl FunctionProfiling {
+    /// last_points = remaining_points
+    fn reset_last_points(&self, state: &mut MiddlewareReaderState<'_>) {
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: self.global_indexes.remaining_points.as_u32(),
+            },
+            Operator::GlobalSet {
+                global_index: self.global_indexes.last_points.as_u32(),
+            },
+        ]);
+    }
+
+    /// function_gas += last_points - remaining_points
+    fn charge_function(&self, state: &mut MiddlewareReaderState<'_>) {
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: self.function_gas.as_u32(),
+            },
+            Operator::GlobalGet {
+                global_index: self.global_indexes.last_points.as_u32(),
+            },
+            Operator::GlobalGet {
+                global_index: self.global_indexes.remaining_points.as_u32(),
+            },
+            Operator::I64Sub,
+            Operator::I64Add,
+            Operator::GlobalSet {
+                global_index: self.function_gas.as_u32(),
+            },
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionProfiling {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.started {
+            self.started = true;
+            self.reset_last_points(state);
+        }
+
+        match operator {
+            Operator::Call { .. } | Operator::CallIndirect { .. } => {
+                let calls_import = matches!(
+                    operator,
+                    Operator::Call { function_index }
+                        if function_index < self.global_indexes.num_imported_functions
+                );
+                self.charge_function(state);
+                state.extend(&[
+                    Operator::I32Const {
+                        value: self.function_index as i32,
+                    },
+                    Operator::GlobalSet {
+                        global_index: self.global_indexes.current_function.as_u32(),
+                    },
+                ]);
+                state.push_operator(operator);
+                if calls_import {
+                    state.extend(&[
+                        Operator::GlobalGet {
+                            global_index: self.global_indexes.remaining_points.as_u32(),
+                        },
+                        Operator::GlobalSet {
+                            global_index: self.global_indexes.import_return_points.as_u32(),
+                        },
+                    ]);
+                }
+                self.reset_last_points(state);
+            }
+            Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                self.depth += 1;
+                state.push_operator(operator);
+            }
+            Operator::End => {
+                self.depth -= 1;
+                // end of the function body
+                if self.depth == 0 {
+                    self.charge_function(state);
+                }
+                state.push_operator(operator);
+            }
+            Operator::Return => {
+                self.charge_function(state);
+                state.push_operator(operator);
+            }
+            _ => state.push_operator(operator),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+//! Wall-clock watchdog interrupting the calls that exceed their time budget.
+//!
+//! The ABIs check the deadline of the call, but code that never calls an ABI,
+//! like a pure wasm loop, would never be interrupted by these checks. This
+//! middleware injects, at each function entry and loop header, a countdown
+//! on a global. When the countdown reaches zero, the injected code calls
+//! through a table the function set by `arm`, which traps with
+//! `VMError::Timeout` once the deadline is passed and restarts the countdown
+//! otherwise.
+//!
+//! The table, its function type and the countdown global are added by the
+//! middleware, the code of the module cannot reach them. The modules already
+//! exporting their names are rejected. The countdown starts at `i64::MAX` so
+//! that an instance that is not armed never calls the empty table.
+//!
+//! It is only pushed when `ExecutionConfig::timeout` is set, the executions
+//! that are part of the consensus are compiled without it. It must be pushed
+//! *after* the metering middlewares so that the injected code is not metered,
+//! the gas used by a call does not depend on its timeout.
+
+use crate::{GasUsage, VMError};
+use std::fmt::{self, Debug};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use wasmer::{
+    wasmparser::{BlockType, Operator},
+    AsStoreMut, Function, FunctionEnv, FunctionEnvMut, FunctionMiddleware, Global, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Value,
+};
+use wasmer_types::{
+    ExportIndex, FunctionType, GlobalIndex, GlobalInit, GlobalType, ModuleInfo, Mutability,
+    SignatureIndex, TableIndex, TableType, Type,
+};
+
+const COUNTDOWN_EXPORT: &str = "massa_watchdog_countdown";
+const TABLE_EXPORT: &str = "massa_watchdog_table";
+
+/// Number of function entries and loop iterations between two checks of the
+/// deadline, small enough for a check every few tens of microseconds and large
+/// enough for the clock reads to be negligible
+const CHECK_INTERVAL: i64 = 10_000;
+
+/// Deadline of a call, inherited by its nested calls
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Deadline {
+    at: Instant,
+    budget: Duration,
+}
+
+impl Deadline {
+    /// Deadline of a call starting now with a time budget of `budget`
+    pub(crate) fn start(budget: Duration) -> Self {
+        Self {
+            at: Instant::now() + budget,
+            budget,
+        }
+    }
+
+    /// Fail with `VMError::Timeout` once the deadline is passed
+    pub(crate) fn check(&self) -> Result<(), VMError> {
+        if Instant::now() >= self.at {
+            return Err(VMError::Timeout {
+                function: String::new(),
+                budget: self.budget,
+                gas: GasUsage::default(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WatchdogIndexes {
+    countdown: GlobalIndex,
+    table: TableIndex,
+    /// Type of the watchdog function: no parameter and no result
+    signature: SignatureIndex,
+}
+
+#[derive(Default)]
+pub struct Watchdog {
+    /// The indexes used by the injected code.
+    indexes: Mutex<Option<WatchdogIndexes>>,
+}
+
+#[derive(Debug)]
+pub struct FunctionWatchdog {
+    indexes: WatchdogIndexes,
+    /// Whether the check of the function entry is injected yet
+    entered: bool,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog").finish()
+    }
+}
+
+impl ModuleMiddleware for Watchdog {
+    fn generate_function_middleware(
+        &self,
+        _local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionWatchdog {
+            indexes: self.indexes.lock().unwrap().unwrap(),
+            entered: false,
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let mut indexes = self.indexes.lock().unwrap();
+        if indexes.is_some() {
+            panic!("Watchdog::transform_module_info: Attempting to use a `Watchdog` middleware from multiple modules.");
+        }
+        for name in [COUNTDOWN_EXPORT, TABLE_EXPORT] {
+            if module_info.exports.contains_key(name) {
+                return Err(MiddlewareError::new(
+                    "Watchdog",
+                    format!("the module exports the reserved name {}", name),
+                ));
+            }
+        }
+
+        let countdown = module_info
+            .globals
+            .push(GlobalType::new(Type::I64, Mutability::Var));
+        module_info
+            .global_initializers
+            .push(GlobalInit::I64Const(i64::MAX));
+        module_info
+            .exports
+            .insert(COUNTDOWN_EXPORT.to_string(), ExportIndex::Global(countdown));
+
+        let table = module_info
+            .tables
+            .push(TableType::new(Type::FuncRef, 1, Some(1)));
+        module_info
+            .exports
+            .insert(TABLE_EXPORT.to_string(), ExportIndex::Table(table));
+
+        let signature = module_info
+            .signatures
+            .push(FunctionType::new(Vec::<Type>::new(), Vec::<Type>::new()));
+
+        *indexes = Some(WatchdogIndexes {
+            countdown,
+            table,
+            signature,
+        });
+
+        Ok(())
+    }
+}
+
+impl FunctionWatchdog {
+    /// Decrement the countdown and call the watchdog function when it
+    /// reaches zero
+    fn inject_check(&self, state: &mut MiddlewareReaderState<'_>) {
+        let countdown = self.indexes.countdown.as_u32();
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: countdown,
+            },
+            Operator::I64Const { value: 1 },
+            Operator::I64Sub,
+            Operator::GlobalSet {
+                global_index: countdown,
+            },
+            Operator::GlobalGet {
+                global_index: countdown,
+            },
+            Operator::I64Eqz,
+            Operator::If {
+                blockty: BlockType::Empty,
+            },
+            Operator::I32Const { value: 0 },
+            Operator::CallIndirect {
+                type_index: self.indexes.signature.as_u32(),
+                table_index: self.indexes.table.as_u32(),
+                table_byte: 0,
+            },
+            Operator::End,
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionWatchdog {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.entered {
+            self.entered = true;
+            self.inject_check(state);
+        }
+        // The branches to a loop jump right after its header, the check is
+        // done at each iteration
+        let is_loop = matches!(operator, Operator::Loop { .. });
+        state.push_operator(operator);
+        if is_loop {
+            self.inject_check(state);
+        }
+        Ok(())
+    }
+}
+
+/// Environment of the watchdog function of an instance
+struct WatchdogEnv {
+    deadline: Deadline,
+    countdown: Global,
+}
+
+fn check_deadline(mut env: FunctionEnvMut<WatchdogEnv>) -> Result<(), VMError> {
+    let (watchdog, mut store) = env.data_and_store_mut();
+    watchdog.deadline.check()?;
+    watchdog
+        .countdown
+        .set(&mut store, Value::I64(CHECK_INTERVAL))
+        .map_err(VMError::instance_error)
+}
+
+/// Interrupt the calls to `instance` once `deadline` is passed. A module
+/// compiled without a timeout has no watchdog, its calls are only interrupted
+/// at their ABI calls.
+pub(crate) fn arm(
+    store: &mut impl AsStoreMut,
+    instance: &Instance,
+    deadline: Deadline,
+) -> Result<(), VMError> {
+    let Ok(countdown) = instance.exports.get_global(COUNTDOWN_EXPORT) else {
+        return Ok(());
+    };
+    let countdown = countdown.clone();
+    let table = instance.exports.get_table(TABLE_EXPORT)?;
+    let env = FunctionEnv::new(
+        store,
+        WatchdogEnv {
+            deadline,
+            countdown: countdown.clone(),
+        },
+    );
+    let watchdog = Function::new_typed_with_env(store, &env, check_deadline);
+    table
+        .set(store, 0, Value::FuncRef(Some(watchdog)))
+        .map_err(VMError::instance_error)?;
+    countdown
+        .set(store, Value::I64(CHECK_INTERVAL))
+        .map_err(VMError::instance_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use wasmer::{
+        imports, sys::EngineBuilder, wat2wasm, CompilerConfig, Cranelift, Module, Store,
+        TypedFunction,
+    };
+
+    fn compile(wat: &str) -> (Store, Result<Module, wasmer::CompileError>) {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(Watchdog::new()));
+        let store = Store::new(EngineBuilder::new(compiler_config));
+        let module = Module::new(&store, wat2wasm(wat.as_bytes()).unwrap());
+        (store, module)
+    }
+
+    fn instantiate(wat: &str) -> (Store, Instance) {
+        let (mut store, module) = compile(wat);
+        let instance = Instance::new(&mut store, &module.unwrap(), &imports! {}).unwrap();
+        (store, instance)
+    }
+
+    #[test]
+    fn interrupt_loop() {
+        let (mut store, instance) = instantiate(
+            r#"
+            (module
+                (func (export "spin")
+                    (loop $l (br $l))))
+            "#,
+        );
+        let budget = Duration::from_millis(50);
+        arm(&mut store, &instance, Deadline::start(budget)).unwrap();
+        let spin: TypedFunction<(), ()> =
+            instance.exports.get_typed_function(&store, "spin").unwrap();
+        let err = spin.call(&mut store).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<VMError>(),
+            Some(VMError::Timeout { budget: b, .. }) if *b == budget
+        ));
+    }
+
+    #[test]
+    fn interrupt_recursion() {
+        let (mut store, instance) = instantiate(
+            r#"
+            (module
+                (func $f (export "recurse") (param i32)
+                    (if (local.get 0)
+                        (then
+                            (call $f (i32.sub (local.get 0) (i32.const 1)))
+                            (call $f (i32.sub (local.get 0) (i32.const 1)))))))
+            "#,
+        );
+        arm(
+            &mut store,
+            &instance,
+            Deadline::start(Duration::from_millis(50)),
+        )
+        .unwrap();
+        let recurse: TypedFunction<i32, ()> = instance
+            .exports
+            .get_typed_function(&store, "recurse")
+            .unwrap();
+        let err = recurse.call(&mut store, 64).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<VMError>(),
+            Some(VMError::Timeout { .. })
+        ));
+    }
+
+    #[test]
+    fn not_armed() {
+        let (mut store, instance) = instantiate(
+            r#"
+            (module
+                (func (export "count") (param i32) (result i32)
+                    (local i32)
+                    (loop $l
+                        (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+                        (br_if $l (i32.lt_u (local.get 1) (local.get 0))))
+                    (local.get 1)))
+            "#,
+        );
+        let count: TypedFunction<i32, i32> = instance
+            .exports
+            .get_typed_function(&store, "count")
+            .unwrap();
+        // more iterations than the check interval, the empty table is never
+        // called
+        assert_eq!(count.call(&mut store, 100_000).unwrap(), 100_000);
+        // and an armed instance with time left keeps running
+        arm(
+            &mut store,
+            &instance,
+            Deadline::start(Duration::from_secs(60)),
+        )
+        .unwrap();
+        assert_eq!(count.call(&mut store, 100_000).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn reject_reserved_exports() {
+        let (_, module) = compile(
+            r#"
+            (module
+                (global (export "massa_watchdog_countdown") i64 (i64.const 0)))
+            "#,
+        );
+        assert!(module.is_err());
+    }
+}
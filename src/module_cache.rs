@@ -0,0 +1,290 @@
+//! Cache of compiled runtime modules.
+//!
+//! Compiled modules are kept in an in-memory LRU and, optionally, persisted on
+//! disk. Entries are keyed by the SHA-256 of the bytecode together with the
+//! `ExecutionConfig` they were compiled with, so that changing any setting
+//! naturally invalidates the cached artifacts. Files on disk also
+//! start with a version header, stale files are ignored and overwritten.
+
+use crate::artifact::hash_features;
+use crate::execution::{Compiler, RuntimeModule};
+use crate::{ExecutionConfig, ModuleKind};
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Header of the on-disk cache entries, followed by `CACHE_VERSION`
+const CACHE_MAGIC: &[u8; 4] = b"MSCC";
+/// Version of the on-disk cache format. Also includes the crate version as
+/// artifacts compiled by another version of the runtime must not be reused.
+const CACHE_VERSION: &str = concat!("1:", env!("CARGO_PKG_VERSION"));
+
+pub type ModuleCacheKey = [u8; 32];
+
+/// Configuration of a `ModuleCache`
+#[derive(Clone, Debug)]
+pub struct ModuleCacheConfig {
+    /// Directory of the on-disk cache, `None` to only cache in memory
+    pub hd_cache_path: Option<PathBuf>,
+    /// Maximum number of modules kept in memory
+    pub lru_cache_size: usize,
+    /// Settings the modules are compiled with
+    pub execution_config: ExecutionConfig,
+}
+
+/// Two-level cache of compiled modules
+pub struct ModuleCache {
+    config: ModuleCacheConfig,
+    /// Fingerprint of the compilation settings, shared by every key
+    settings_fingerprint: ModuleCacheKey,
+    /// In-memory entries along with their last use tick
+    lru: HashMap<ModuleCacheKey, (u64, RuntimeModule)>,
+    tick: u64,
+}
+
+impl ModuleCache {
+    pub fn new(config: ModuleCacheConfig) -> Result<Self> {
+        if let Some(path) = &config.hd_cache_path {
+            std::fs::create_dir_all(path)?;
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION.as_bytes());
+        config.execution_config.hash_into(&mut hasher);
+        for kind in [ModuleKind::AssemblyScript, ModuleKind::WasmV1] {
+            hash_features(&mut hasher, config.execution_config.features(kind));
+        }
+        Ok(Self {
+            settings_fingerprint: hasher.finalize().into(),
+            config,
+            lru: HashMap::new(),
+            tick: 0,
+        })
+    }
+
+    pub fn config(&self) -> &ModuleCacheConfig {
+        &self.config
+    }
+
+    /// Compute the cache key of a bytecode compiled with `compiler`
+    pub fn key(&self, bytecode: &[u8], compiler: &Compiler) -> ModuleCacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(self.settings_fingerprint);
+        hasher.update(match compiler {
+            Compiler::CL => b"CL",
+            Compiler::SP => b"SP",
+        });
+        hasher.update(bytecode);
+        hasher.finalize().into()
+    }
+
+    /// Get a module compiled with Cranelift, looking in memory first, then on
+    /// disk, and compiling it as a last resort
+    pub fn load_module(&mut self, bytecode: &[u8]) -> Result<RuntimeModule> {
+        self.load(bytecode, Compiler::CL)
+    }
+
+    /// Same as `load_module` for temporary modules, compiled with Singlepass
+    pub fn load_tmp_module(&mut self, bytecode: &[u8]) -> Result<RuntimeModule> {
+        self.load(bytecode, Compiler::SP)
+    }
+
+    fn load(&mut self, bytecode: &[u8], compiler: Compiler) -> Result<RuntimeModule> {
+        let key = self.key(bytecode, &compiler);
+        self.tick += 1;
+        if let Some((last_use, module)) = self.lru.get_mut(&key) {
+            *last_use = self.tick;
+            return Ok(module.clone());
+        }
+
+        let module = match self.load_from_disk(&key) {
+            Some(module) => module,
+            None => {
+                let module = RuntimeModule::new(bytecode, compiler, &self.config.execution_config)?;
+                self.save_to_disk(&key, &module);
+                module
+            }
+        };
+        self.insert_in_lru(key, module.clone());
+        Ok(module)
+    }
+
+    fn insert_in_lru(&mut self, key: ModuleCacheKey, module: RuntimeModule) {
+        if self.config.lru_cache_size == 0 {
+            return;
+        }
+        if self.lru.len() >= self.config.lru_cache_size {
+            if let Some(oldest) = self
+                .lru
+                .iter()
+                .min_by_key(|(_, (last_use, _))| *last_use)
+                .map(|(key, _)| *key)
+            {
+                self.lru.remove(&oldest);
+            }
+        }
+        self.lru.insert(key, (self.tick, module));
+    }
+
+    fn entry_path(&self, key: &ModuleCacheKey) -> Option<PathBuf> {
+        let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.config
+            .hd_cache_path
+            .as_ref()
+            .map(|path| path.join(format!("{}.module", hex)))
+    }
+
+    /// Read a cache entry from disk, any invalid entry is treated as a miss
+    fn load_from_disk(&self, key: &ModuleCacheKey) -> Option<RuntimeModule> {
+        let data = std::fs::read(self.entry_path(key)?).ok()?;
+        let header = [CACHE_MAGIC.as_slice(), CACHE_VERSION.as_bytes()].concat();
+        if data.len() <= header.len() || !data.starts_with(&header) {
+            return None;
+        }
+        let execution_config = &self.config.execution_config;
+        RuntimeModule::deserialize(
+            &data[header.len()..],
+            execution_config.gas_costs().max_instance_cost,
+            execution_config,
+        )
+        .ok()
+    }
+
+    /// Write a cache entry on disk. The on-disk cache is best effort: failing
+    /// to write it only means the module will be compiled again later.
+    fn save_to_disk(&self, key: &ModuleCacheKey, module: &RuntimeModule) {
+        let Some(path) = self.entry_path(key) else {
+            return;
+        };
+        let Ok(ser_module) = module.serialize() else {
+            return;
+        };
+        let mut data =
+            Vec::with_capacity(CACHE_MAGIC.len() + CACHE_VERSION.len() + ser_module.len());
+        data.extend_from_slice(CACHE_MAGIC);
+        data.extend_from_slice(CACHE_VERSION.as_bytes());
+        data.extend_from_slice(&ser_module);
+        // write then rename so that a concurrent reader never sees a partial
+        // entry, with a temporary file per writer so that concurrent writers
+        // of the same entry do not write to the same file
+        let tmp_path = path.with_extension(format!(
+            "{}-{:016x}.tmp",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        if std::fs::write(&tmp_path, data).is_err() || std::fs::rename(&tmp_path, &path).is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GasCosts;
+
+    fn config(hd_cache_path: Option<PathBuf>) -> ModuleCacheConfig {
+        ModuleCacheConfig {
+            hd_cache_path,
+            lru_cache_size: 2,
+            execution_config: ExecutionConfig::default(),
+        }
+    }
+
+    fn tmp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("massa-sc-runtime-cache-{}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn test_key_depends_on_settings() {
+        let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm"));
+        let cache = ModuleCache::new(config(None)).unwrap();
+
+        let mut gas_costs = GasCosts::default();
+        gas_costs.launch_cost += 1;
+        let mut other_config = config(None);
+        other_config.execution_config = ExecutionConfig::builder()
+            .gas_costs(gas_costs)
+            .build()
+            .unwrap();
+        let other_cache = ModuleCache::new(other_config).unwrap();
+
+        assert_eq!(
+            cache.key(bytecode, &Compiler::CL),
+            cache.key(bytecode, &Compiler::CL)
+        );
+        assert_ne!(
+            cache.key(bytecode, &Compiler::CL),
+            other_cache.key(bytecode, &Compiler::CL)
+        );
+        assert_ne!(
+            cache.key(bytecode, &Compiler::CL),
+            cache.key(&bytecode[..bytecode.len() - 1], &Compiler::CL)
+        );
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = ModuleCache::new(config(None)).unwrap();
+        let bytecodes: [&[u8]; 3] = [
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm")),
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm")),
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/empty_main.wasm")),
+        ];
+        for bytecode in bytecodes {
+            cache.load_module(bytecode).unwrap();
+        }
+        assert_eq!(cache.lru.len(), 2);
+        assert!(!cache
+            .lru
+            .contains_key(&cache.key(bytecodes[0], &Compiler::CL)));
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm"));
+        let path = tmp_dir();
+
+        let mut cache = ModuleCache::new(config(Some(path.clone()))).unwrap();
+        let key = cache.key(bytecode, &Compiler::CL);
+        cache.load_module(bytecode).unwrap();
+        let entry_path = cache.entry_path(&key).unwrap();
+        assert!(entry_path.exists());
+
+        // A fresh cache finds the module on disk
+        let cache = ModuleCache::new(config(Some(path.clone()))).unwrap();
+        let module = cache.load_from_disk(&key).unwrap();
+        assert!(module.function_exists("main"));
+
+        // A corrupted entry is a miss and gets rewritten
+        std::fs::write(&entry_path, b"corrupted").unwrap();
+        let mut cache = ModuleCache::new(config(Some(path.clone()))).unwrap();
+        assert!(cache.load_from_disk(&key).is_none());
+        cache.load_module(bytecode).unwrap();
+        assert!(cache.load_from_disk(&key).is_some());
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_tmp_module_cache() {
+        let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm"));
+        let path = tmp_dir();
+
+        let mut cache = ModuleCache::new(config(Some(path.clone()))).unwrap();
+        let module = cache.load_tmp_module(bytecode).unwrap();
+        assert_eq!(module.compiler(), Compiler::SP);
+        assert_ne!(
+            cache.key(bytecode, &Compiler::SP),
+            cache.key(bytecode, &Compiler::CL)
+        );
+
+        let cache = ModuleCache::new(config(Some(path.clone()))).unwrap();
+        let module = cache
+            .load_from_disk(&cache.key(bytecode, &Compiler::SP))
+            .unwrap();
+        assert_eq!(module.compiler(), Compiler::SP);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+//! Gas profiling report of an execution, built with the `profiling` feature.
+//!
+//! The `Profiling` middleware accumulates the gas consumed by every local
+//! function in exported globals. ABI calls are attributed on the host side by
+//! snapshotting the metering points when an ABI is entered and reading the
+//! points the middleware saved when it returned. Sub-executions started by
+//! the call ABIs are attached to the ABI call that started them.
+
+use crate::middlewares::profiling::{
+    CURRENT_FUNCTION_EXPORT, FUNCTION_GAS_PREFIX, IMPORT_RETURN_EXPORT,
+};
+use std::collections::BTreeMap;
+use wasmer::{AsStoreMut, Extern, Global, Instance};
+
+/// Node of a gas profile: an execution, a wasm function, or an ABI call
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileNode {
+    pub name: String,
+    /// Gas consumed by the node itself, its children excluded
+    pub gas: u64,
+    pub children: Vec<ProfileNode>,
+}
+
+impl ProfileNode {
+    /// Gas consumed by the node and its children
+    pub fn total_gas(&self) -> u64 {
+        self.children.iter().fold(self.gas, |total, child| {
+            total.saturating_add(child.total_gas())
+        })
+    }
+
+    /// Collapsed stacks of the profile, one `frame;frame;frame gas` line per
+    /// node consuming gas, as accepted by flamegraph tools
+    pub fn to_collapsed_stacks(&self) -> String {
+        let mut output = String::new();
+        self.write_collapsed_stacks(&mut Vec::new(), &mut output);
+        output
+    }
+
+    fn write_collapsed_stacks(&self, stack: &mut Vec<String>, output: &mut String) {
+        // `;` separates the frames
+        stack.push(self.name.replace(';', ":"));
+        if self.gas > 0 {
+            output.push_str(&stack.join(";"));
+            output.push_str(&format!(" {}\n", self.gas));
+        }
+        for child in &self.children {
+            child.write_collapsed_stacks(stack, output);
+        }
+        stack.pop();
+    }
+}
+
+/// ABI call whose gas has not been attributed yet
+#[derive(Clone, Debug)]
+struct OpenAbiCall {
+    function: i32,
+    abi: String,
+    remaining_points: u64,
+}
+
+/// Host side of the profiling of an instance
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profiler {
+    current_function: Option<Global>,
+    import_return_points: Option<Global>,
+    open_call: Option<OpenAbiCall>,
+    /// Gas of the ABI calls, by calling function and ABI name
+    abi_gas: BTreeMap<(i32, String), u64>,
+    /// Profiles of the sub-executions, by calling function and ABI name
+    sub_executions: BTreeMap<(i32, String), Vec<ProfileNode>>,
+}
+
+impl Profiler {
+    /// Bind the profiler to the globals of an instrumented instance. The
+    /// profiler records nothing for modules compiled without the middleware.
+    pub(crate) fn init_with_instance(&mut self, instance: &Instance) {
+        self.current_function = instance
+            .exports
+            .get_global(CURRENT_FUNCTION_EXPORT)
+            .ok()
+            .cloned();
+        self.import_return_points = instance
+            .exports
+            .get_global(IMPORT_RETURN_EXPORT)
+            .ok()
+            .cloned();
+    }
+
+    /// Record the entry in an ABI, before any gas is charged by it
+    pub(crate) fn enter_abi(
+        &mut self,
+        store: &mut impl AsStoreMut,
+        abi: &str,
+        remaining_points: u64,
+    ) {
+        // ABIs do not nest in an instance: the previous call has returned
+        self.close_abi_call(store);
+        if let Some(current_function) = &self.current_function {
+            self.open_call = Some(OpenAbiCall {
+                function: current_function.get(store).i32().unwrap_or(-1),
+                abi: abi.to_string(),
+                remaining_points,
+            });
+        }
+    }
+
+    /// Attach the profile of a sub-execution to the running ABI call
+    pub(crate) fn add_sub_execution(&mut self, profile: ProfileNode) {
+        if let Some(open_call) = &self.open_call {
+            self.sub_executions
+                .entry((open_call.function, open_call.abi.clone()))
+                .or_default()
+                .push(profile);
+        }
+    }
+
+    fn close_abi_call(&mut self, store: &mut impl AsStoreMut) {
+        let (Some(open_call), Some(import_return_points)) =
+            (self.open_call.take(), &self.import_return_points)
+        else {
+            return;
+        };
+        let returned_points = import_return_points.get(store).i64().unwrap_or(0) as u64;
+        *self
+            .abi_gas
+            .entry((open_call.function, open_call.abi))
+            .or_default() += open_call.remaining_points.saturating_sub(returned_points);
+    }
+
+    /// Build the profile of the execution of `function`
+    pub(crate) fn report(
+        &mut self,
+        store: &mut impl AsStoreMut,
+        instance: &Instance,
+        function: &str,
+    ) -> ProfileNode {
+        self.close_abi_call(store);
+
+        let mut functions = BTreeMap::new();
+        for (export_name, export) in instance.exports.iter() {
+            let (Some(function), Extern::Global(global)) =
+                (export_name.strip_prefix(FUNCTION_GAS_PREFIX), export)
+            else {
+                continue;
+            };
+            let Some((index, name)) = function.split_once(':') else {
+                continue;
+            };
+            let Ok(index) = index.parse::<i32>() else {
+                continue;
+            };
+            let gas = global.get(store).i64().unwrap_or(0) as u64;
+            functions.insert(
+                index,
+                ProfileNode {
+                    name: name.to_string(),
+                    gas,
+                    children: Vec::new(),
+                },
+            );
+        }
+
+        for ((index, abi), gas) in std::mem::take(&mut self.abi_gas) {
+            let sub_executions = self
+                .sub_executions
+                .remove(&(index, abi.clone()))
+                .unwrap_or_default();
+            let sub_executions_gas = sub_executions
+                .iter()
+                .fold(0u64, |total, sub| total.saturating_add(sub.total_gas()));
+            let node = ProfileNode {
+                name: abi,
+                gas: gas.saturating_sub(sub_executions_gas),
+                children: sub_executions,
+            };
+            functions
+                .entry(index)
+                .or_insert_with(|| ProfileNode {
+                    name: format!("func{}", index),
+                    ..Default::default()
+                })
+                .children
+                .push(node);
+        }
+
+        ProfileNode {
+            name: function.to_string(),
+            gas: 0,
+            children: functions
+                .into_values()
+                .filter(|node| node.total_gas() > 0)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapsed_stacks() {
+        let profile = ProfileNode {
+            name: "main".to_string(),
+            gas: 0,
+            children: vec![ProfileNode {
+                name: "foo".to_string(),
+                gas: 10,
+                children: vec![ProfileNode {
+                    name: "assembly_script_call".to_string(),
+                    gas: 5,
+                    children: vec![ProfileNode {
+                        name: "bar;baz".to_string(),
+                        gas: 3,
+                        children: Vec::new(),
+                    }],
+                }],
+            }],
+        };
+        assert_eq!(profile.total_gas(), 18);
+        assert_eq!(
+            profile.to_collapsed_stacks(),
+            "main;foo 10\nmain;foo;assembly_script_call 5\nmain;foo;assembly_script_call;bar:baz 3\n"
+        );
+    }
+}
@@ -0,0 +1,648 @@
+//! Recording and replay of the `Interface` calls of an execution.
+//!
+//! `RecordingInterface` wraps the interface of an execution and logs every
+//! call made through it, with its arguments and its result. `ReplayInterface`
+//! serves the recorded results back and checks that the runtime makes the
+//! same calls in the same order. With the bytecode and the gas limit, a
+//! recording is enough to run an execution again offline, e.g. to compare
+//! the behavior or the gas usage of two versions of the runtime.
+//!
+//! The modules returned by `get_module` and `get_tmp_module` are not
+//! recorded: the replay compiles them again from the bytecode.
+
+use crate::execution::{Compiler, RuntimeModule};
+use crate::types::{Interface, InterfaceClone, InterfaceError, Result};
+use crate::ExecutionConfig;
+use displaydoc::Display;
+use massa_proto_rs::massa::model::v1::{
+    AddressCategory, ComparisonResult, NativeAmount, NativeTime, Slot,
+};
+use parking_lot::Mutex;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// `Interface` call of a recording
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub method: String,
+    pub args: Vec<Value>,
+    /// Errors are recorded as their message
+    pub result: std::result::Result<Value, String>,
+}
+
+/// `Interface` calls of an execution, in the order they were made
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub calls: Vec<RecordedCall>,
+}
+
+impl Recording {
+    /// Read a recording saved as JSON
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Save the recording as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(serde_json::to_writer(writer, self)?)
+    }
+}
+
+#[derive(Error, Display, Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// call {index} is `{actual}` but `{expected}` was recorded
+    MethodMismatch {
+        index: usize,
+        expected: String,
+        actual: String,
+    },
+    /// call {index} to `{method}` has other arguments than the recorded one
+    ArgumentsMismatch { index: usize, method: String },
+    /// call {index} to `{method}` was not recorded
+    UnexpectedCall { index: usize, method: String },
+    /// recorded result of call {index} to `{method}` is invalid
+    InvalidResult { index: usize, method: String },
+    /// {remaining} recorded calls were not made
+    MissingCalls { remaining: usize },
+}
+
+/// Conversion of the arguments and results of the `Interface` calls to JSON
+trait Record {
+    fn record(&self) -> Value;
+}
+
+/// Conversion of the recorded results back to their type
+trait Replay: Record + Sized {
+    fn replay(value: Value) -> Option<Self>;
+}
+
+macro_rules! serde_record {
+    ($($ty:ty),*) => {
+        $(
+            impl Record for $ty {
+                fn record(&self) -> Value {
+                    serde_json::to_value(self).unwrap_or_default()
+                }
+            }
+
+            impl Replay for $ty {
+                fn replay(value: Value) -> Option<Self> {
+                    serde_json::from_value(value).ok()
+                }
+            }
+        )*
+    };
+}
+
+serde_record!(
+    (),
+    bool,
+    u8,
+    u32,
+    u64,
+    i64,
+    f64,
+    String,
+    [u8; 32],
+    Vec<u8>,
+    Vec<Vec<u8>>,
+    Vec<String>,
+    BTreeSet<Vec<u8>>
+);
+
+/// Protobuf messages are recorded as their encoding
+macro_rules! message_record {
+    ($($ty:ty),*) => {
+        $(
+            impl Record for $ty {
+                fn record(&self) -> Value {
+                    self.encode_to_vec().record()
+                }
+            }
+
+            impl Replay for $ty {
+                fn replay(value: Value) -> Option<Self> {
+                    <$ty>::decode(Vec::<u8>::replay(value)?.as_slice()).ok()
+                }
+            }
+        )*
+    };
+}
+
+message_record!(NativeAmount, NativeTime, Slot);
+
+/// Protobuf enumerations are recorded as their value
+macro_rules! enumeration_record {
+    ($($ty:ty),*) => {
+        $(
+            impl Record for $ty {
+                fn record(&self) -> Value {
+                    (*self as i32).into()
+                }
+            }
+
+            impl Replay for $ty {
+                fn replay(value: Value) -> Option<Self> {
+                    <$ty>::try_from(i32::try_from(value.as_i64()?).ok()?).ok()
+                }
+            }
+        )*
+    };
+}
+
+enumeration_record!(AddressCategory, ComparisonResult);
+
+impl Record for str {
+    fn record(&self) -> Value {
+        self.into()
+    }
+}
+
+impl Record for [u8] {
+    fn record(&self) -> Value {
+        self.to_vec().record()
+    }
+}
+
+impl<T: Record + ?Sized> Record for &T {
+    fn record(&self) -> Value {
+        (**self).record()
+    }
+}
+
+impl<T: Record> Record for Option<T> {
+    fn record(&self) -> Value {
+        match self {
+            Some(value) => value.record(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: Replay> Replay for Option<T> {
+    fn replay(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::replay(value).map(Some),
+        }
+    }
+}
+
+impl<A: Record, B: Record> Record for (A, B) {
+    fn record(&self) -> Value {
+        Value::Array(vec![self.0.record(), self.1.record()])
+    }
+}
+
+impl<A: Replay, B: Replay> Replay for (A, B) {
+    fn replay(value: Value) -> Option<Self> {
+        let Value::Array(values) = value else {
+            return None;
+        };
+        let [a, b]: [Value; 2] = values.try_into().ok()?;
+        Some((A::replay(a)?, B::replay(b)?))
+    }
+}
+
+fn record_result<T: Record>(result: &Result<T>) -> std::result::Result<Value, String> {
+    match result {
+        Ok(value) => Ok(value.record()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// `Interface` logging every call made to the interface it wraps
+///
+/// The clones given to the sub-executions share the same recording.
+#[derive(Clone)]
+pub struct RecordingInterface {
+    interface: Box<dyn Interface>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl RecordingInterface {
+    pub fn new(interface: Box<dyn Interface>) -> Self {
+        Self {
+            interface,
+            recording: Default::default(),
+        }
+    }
+
+    /// Calls recorded so far
+    pub fn recording(&self) -> Recording {
+        self.recording.lock().clone()
+    }
+
+    fn record(&self, method: &str, args: Vec<Value>, result: std::result::Result<Value, String>) {
+        self.recording.lock().calls.push(RecordedCall {
+            method: method.to_string(),
+            args,
+            result,
+        });
+    }
+
+    fn record_module_call(
+        &self,
+        method: &str,
+        bytecode: &[u8],
+        gas_limit: u64,
+        result: &Result<RuntimeModule>,
+    ) {
+        let result = match result {
+            Ok(_) => Ok(Value::Null),
+            Err(err) => Err(err.to_string()),
+        };
+        self.record(method, vec![bytecode.record(), gas_limit.record()], result);
+    }
+}
+
+impl InterfaceClone for RecordingInterface {
+    fn clone_box(&self) -> Box<dyn Interface> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+    calls: Vec<RecordedCall>,
+    next: usize,
+    error: Option<ReplayError>,
+}
+
+/// `Interface` serving the results of a recording
+///
+/// Once a call differs from the recording, every call fails with the
+/// `ReplayError` describing the first difference.
+#[derive(Clone)]
+pub struct ReplayInterface {
+    state: Arc<Mutex<ReplayState>>,
+    /// Used to compile the modules of `get_module` and `get_tmp_module`
+    config: ExecutionConfig,
+}
+
+impl ReplayInterface {
+    pub fn new(recording: Recording, config: ExecutionConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ReplayState {
+                calls: recording.calls,
+                ..Default::default()
+            })),
+            config,
+        }
+    }
+
+    /// Check that the calls made so far are exactly the recorded ones
+    pub fn finish(&self) -> std::result::Result<(), ReplayError> {
+        let state = self.state.lock();
+        if let Some(err) = &state.error {
+            return Err(err.clone());
+        }
+        match state.calls.len() - state.next {
+            0 => Ok(()),
+            remaining => Err(ReplayError::MissingCalls { remaining }),
+        }
+    }
+
+    fn replay<T: Replay>(&self, method: &str, args: Vec<Value>) -> Result<T> {
+        let mut state = self.state.lock();
+        if let Some(err) = &state.error {
+            return Err(InterfaceError::GenericError(err.to_string()));
+        }
+        let index = state.next;
+        let replayed = match state.calls.get(index) {
+            None => Err(ReplayError::UnexpectedCall {
+                index,
+                method: method.to_string(),
+            }),
+            Some(call) if call.method != method => Err(ReplayError::MethodMismatch {
+                index,
+                expected: call.method.clone(),
+                actual: method.to_string(),
+            }),
+            Some(call) if call.args != args => Err(ReplayError::ArgumentsMismatch {
+                index,
+                method: method.to_string(),
+            }),
+            Some(call) => match call.result.clone() {
+                Ok(value) => T::replay(value).map(Ok).ok_or(ReplayError::InvalidResult {
+                    index,
+                    method: method.to_string(),
+                }),
+                Err(message) => Ok(Err(InterfaceError::GenericError(message))),
+            },
+        };
+        match replayed {
+            Ok(result) => {
+                state.next += 1;
+                result
+            }
+            Err(err) => {
+                let message = err.to_string();
+                state.error = Some(err);
+                Err(InterfaceError::GenericError(message))
+            }
+        }
+    }
+
+    fn replay_module_call(
+        &self,
+        method: &str,
+        bytecode: &[u8],
+        gas_limit: u64,
+        compiler: Compiler,
+    ) -> Result<RuntimeModule> {
+        self.replay::<()>(method, vec![bytecode.record(), gas_limit.record()])?;
+        RuntimeModule::new(bytecode, compiler, &self.config)
+            .map_err(|err| InterfaceError::GenericError(err.to_string()))
+    }
+}
+
+impl InterfaceClone for ReplayInterface {
+    fn clone_box(&self) -> Box<dyn Interface> {
+        Box::new(self.clone())
+    }
+}
+
+/// Implement `Interface` for both wrappers from the methods returning a
+/// `Result` of a recordable type. The other methods are written by hand.
+macro_rules! interface_calls {
+    ($(fn $method:ident(&self $(, $arg:ident: $ty:ty)*) -> Result<$ret:ty>;)*) => {
+        impl Interface for RecordingInterface {
+            $(
+                fn $method(&self $(, $arg: $ty)*) -> Result<$ret> {
+                    let args = vec![$($arg.record()),*];
+                    let result = self.interface.$method($($arg),*);
+                    self.record(stringify!($method), args, record_result(&result));
+                    result
+                }
+            )*
+
+            fn get_module(&self, bytecode: &[u8], gas_limit: u64) -> Result<RuntimeModule> {
+                let result = self.interface.get_module(bytecode, gas_limit);
+                self.record_module_call("get_module", bytecode, gas_limit, &result);
+                result
+            }
+
+            fn get_tmp_module(&self, bytecode: &[u8], gas_limit: u64) -> Result<RuntimeModule> {
+                let result = self.interface.get_tmp_module(bytecode, gas_limit);
+                self.record_module_call("get_tmp_module", bytecode, gas_limit, &result);
+                result
+            }
+
+            fn bytes_to_base58_check_wasmv1(&self, bytes: &[u8]) -> String {
+                let result = self.interface.bytes_to_base58_check_wasmv1(bytes);
+                self.record(
+                    "bytes_to_base58_check_wasmv1",
+                    vec![bytes.record()],
+                    Ok(result.record()),
+                );
+                result
+            }
+
+            fn save_gas_remaining_before_subexecution(&self, gas_used_until: u64) {
+                self.interface
+                    .save_gas_remaining_before_subexecution(gas_used_until);
+                self.record(
+                    "save_gas_remaining_before_subexecution",
+                    vec![gas_used_until.record()],
+                    Ok(Value::Null),
+                );
+            }
+        }
+
+        impl Interface for ReplayInterface {
+            $(
+                fn $method(&self $(, $arg: $ty)*) -> Result<$ret> {
+                    self.replay(stringify!($method), vec![$($arg.record()),*])
+                }
+            )*
+
+            fn get_module(&self, bytecode: &[u8], gas_limit: u64) -> Result<RuntimeModule> {
+                self.replay_module_call("get_module", bytecode, gas_limit, Compiler::CL)
+            }
+
+            fn get_tmp_module(&self, bytecode: &[u8], gas_limit: u64) -> Result<RuntimeModule> {
+                self.replay_module_call("get_tmp_module", bytecode, gas_limit, Compiler::SP)
+            }
+
+            fn bytes_to_base58_check_wasmv1(&self, bytes: &[u8]) -> String {
+                // a difference is reported by the next calls and by `finish`
+                self.replay("bytes_to_base58_check_wasmv1", vec![bytes.record()])
+                    .unwrap_or_default()
+            }
+
+            fn save_gas_remaining_before_subexecution(&self, gas_used_until: u64) {
+                let _ = self.replay::<()>(
+                    "save_gas_remaining_before_subexecution",
+                    vec![gas_used_until.record()],
+                );
+            }
+        }
+    };
+}
+
+interface_calls! {
+    fn increment_recursion_counter(&self) -> Result<()>;
+    fn decrement_recursion_counter(&self) -> Result<()>;
+    fn get_interface_version(&self) -> Result<u32>;
+    fn init_call(&self, address: &str, raw_coins: u64) -> Result<Vec<u8>>;
+    fn init_call_wasmv1(&self, address: &str, raw_coins: NativeAmount) -> Result<Vec<u8>>;
+    fn finish_call(&self) -> Result<()>;
+    fn get_balance(&self) -> Result<u64>;
+    fn get_balance_for(&self, address: &str) -> Result<u64>;
+    fn get_balance_wasmv1(&self, address: Option<String>) -> Result<NativeAmount>;
+    fn transfer_coins(&self, to_address: &str, raw_amount: u64) -> Result<()>;
+    fn transfer_coins_for(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        raw_amount: u64
+    ) -> Result<()>;
+    fn transfer_coins_wasmv1(
+        &self,
+        to_address: String,
+        raw_amount: NativeAmount,
+        from_address: Option<String>
+    ) -> Result<()>;
+    fn get_call_coins(&self) -> Result<u64>;
+    fn get_call_coins_wasmv1(&self) -> Result<NativeAmount>;
+    fn raw_set_bytecode(&self, bytecode: &[u8]) -> Result<()>;
+    fn raw_set_bytecode_for(&self, address: &str, bytecode: &[u8]) -> Result<()>;
+    fn set_bytecode_wasmv1(&self, bytecode: &[u8], address: Option<String>) -> Result<()>;
+    fn create_module(&self, module: &[u8]) -> Result<String>;
+    fn print(&self, message: &str) -> Result<()>;
+    fn get_keys(&self, prefix: Option<&[u8]>) -> Result<BTreeSet<Vec<u8>>>;
+    fn get_keys_for(&self, address: &str, prefix: Option<&[u8]>) -> Result<BTreeSet<Vec<u8>>>;
+    fn get_ds_keys_wasmv1(
+        &self,
+        prefix: &[u8],
+        address: Option<String>
+    ) -> Result<BTreeSet<Vec<u8>>>;
+    fn raw_get_data(&self, key: &[u8]) -> Result<Vec<u8>>;
+    fn raw_get_data_for(&self, address: &str, key: &[u8]) -> Result<Vec<u8>>;
+    fn get_ds_value_wasmv1(&self, key: &[u8], address: Option<String>) -> Result<Vec<u8>>;
+    fn raw_set_data(&self, key: &[u8], value: &[u8]) -> Result<()>;
+    fn raw_set_data_for(&self, address: &str, key: &[u8], value: &[u8]) -> Result<()>;
+    fn set_ds_value_wasmv1(&self, key: &[u8], value: &[u8], address: Option<String>) -> Result<()>;
+    fn raw_append_data(&self, key: &[u8], value: &[u8]) -> Result<()>;
+    fn raw_append_data_for(&self, address: &str, key: &[u8], value: &[u8]) -> Result<()>;
+    fn append_ds_value_wasmv1(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        address: Option<String>
+    ) -> Result<()>;
+    fn raw_delete_data(&self, key: &[u8]) -> Result<()>;
+    fn raw_delete_data_for(&self, address: &str, key: &[u8]) -> Result<()>;
+    fn delete_ds_entry_wasmv1(&self, key: &[u8], address: Option<String>) -> Result<()>;
+    fn has_data(&self, key: &[u8]) -> Result<bool>;
+    fn has_data_for(&self, address: &str, key: &[u8]) -> Result<bool>;
+    fn ds_entry_exists_wasmv1(&self, key: &[u8], address: Option<String>) -> Result<bool>;
+    fn raw_get_bytecode(&self) -> Result<Vec<u8>>;
+    fn raw_get_bytecode_for(&self, address: &str) -> Result<Vec<u8>>;
+    fn get_bytecode_wasmv1(&self, address: Option<String>) -> Result<Vec<u8>>;
+    fn get_op_keys(&self, prefix: Option<&[u8]>) -> Result<Vec<Vec<u8>>>;
+    fn get_op_keys_wasmv1(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>>;
+    fn op_entry_exists(&self, key: &[u8]) -> Result<bool>;
+    fn get_op_data(&self, key: &[u8]) -> Result<Vec<u8>>;
+    fn caller_has_write_access(&self) -> Result<bool>;
+    fn hash(&self, data: &[u8]) -> Result<[u8; 32]>;
+    fn hash_blake3(&self, bytes: &[u8]) -> Result<[u8; 32]>;
+    fn signature_verify(&self, data: &[u8], signature: &str, public_key: &str) -> Result<bool>;
+    fn evm_signature_verify(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+        public_key: &[u8]
+    ) -> Result<bool>;
+    fn evm_get_address_from_pubkey(&self, public_key: &[u8]) -> Result<Vec<u8>>;
+    fn evm_get_pubkey_from_signature(&self, hash: &[u8], signature: &[u8]) -> Result<Vec<u8>>;
+    fn is_address_eoa(&self, address: &str) -> Result<bool>;
+    fn address_from_public_key(&self, public_key: &str) -> Result<String>;
+    fn validate_address(&self, address: &str) -> Result<bool>;
+    fn get_time(&self) -> Result<u64>;
+    fn unsafe_random(&self) -> Result<i64>;
+    fn unsafe_random_f64(&self) -> Result<f64>;
+    fn unsafe_random_wasmv1(&self, num_bytes: u64) -> Result<Vec<u8>>;
+    fn get_current_period(&self) -> Result<u64>;
+    fn get_current_thread(&self) -> Result<u8>;
+    fn get_current_slot(&self) -> Result<Slot>;
+    fn get_owned_addresses(&self) -> Result<Vec<String>>;
+    fn get_call_stack(&self) -> Result<Vec<String>>;
+    fn generate_event(&self, event: String) -> Result<()>;
+    fn generate_event_wasmv1(&self, event: Vec<u8>) -> Result<()>;
+    fn send_message(
+        &self,
+        target_address: &str,
+        target_handler: &str,
+        validity_start: (u64, u8),
+        validity_end: (u64, u8),
+        max_gas: u64,
+        raw_fee: u64,
+        raw_coins: u64,
+        data: &[u8],
+        filter: Option<(&str, Option<&[u8]>)>
+    ) -> Result<()>;
+    fn get_origin_operation_id(&self) -> Result<Option<String>>;
+    fn hash_sha256(&self, bytes: &[u8]) -> Result<[u8; 32]>;
+    fn hash_keccak256(&self, bytes: &[u8]) -> Result<[u8; 32]>;
+    fn chain_id(&self) -> Result<u64>;
+    fn get_deferred_call_quote(
+        &self,
+        target_slot: (u64, u8),
+        gas_limit: u64,
+        params_size: u64
+    ) -> Result<(bool, u64)>;
+    fn deferred_call_register(
+        &self,
+        target_addr: &str,
+        target_func: &str,
+        target_slot: (u64, u8),
+        max_gas: u64,
+        params: &[u8],
+        coins: u64
+    ) -> Result<String>;
+    fn deferred_call_exists(&self, id: &str) -> Result<bool>;
+    fn deferred_call_cancel(&self, id: &str) -> Result<()>;
+    fn native_amount_from_str_wasmv1(&self, amount: &str) -> Result<NativeAmount>;
+    fn native_amount_to_string_wasmv1(&self, amount: &NativeAmount) -> Result<String>;
+    fn check_native_amount_wasmv1(&self, amount: &NativeAmount) -> Result<bool>;
+    fn add_native_amount_wasmv1(
+        &self,
+        amount1: &NativeAmount,
+        amount2: &NativeAmount
+    ) -> Result<NativeAmount>;
+    fn sub_native_amount_wasmv1(
+        &self,
+        amount1: &NativeAmount,
+        amount2: &NativeAmount
+    ) -> Result<NativeAmount>;
+    fn scalar_mul_native_amount_wasmv1(
+        &self,
+        amount: &NativeAmount,
+        factor: u64
+    ) -> Result<NativeAmount>;
+    fn scalar_div_rem_native_amount_wasmv1(
+        &self,
+        dividend: &NativeAmount,
+        divisor: u64
+    ) -> Result<(NativeAmount, NativeAmount)>;
+    fn div_rem_native_amount_wasmv1(
+        &self,
+        dividend: &NativeAmount,
+        divisor: &NativeAmount
+    ) -> Result<(u64, NativeAmount)>;
+    fn check_address_wasmv1(&self, to_check: &str) -> Result<bool>;
+    fn check_pubkey_wasmv1(&self, to_check: &str) -> Result<bool>;
+    fn check_signature_wasmv1(&self, to_check: &str) -> Result<bool>;
+    fn get_address_category_wasmv1(&self, to_check: &str) -> Result<AddressCategory>;
+    fn get_address_version_wasmv1(&self, address: &str) -> Result<u64>;
+    fn get_pubkey_version_wasmv1(&self, pubkey: &str) -> Result<u64>;
+    fn get_signature_version_wasmv1(&self, signature: &str) -> Result<u64>;
+    fn checked_add_native_time_wasmv1(
+        &self,
+        time1: &NativeTime,
+        time2: &NativeTime
+    ) -> Result<NativeTime>;
+    fn checked_sub_native_time_wasmv1(
+        &self,
+        time1: &NativeTime,
+        time2: &NativeTime
+    ) -> Result<NativeTime>;
+    fn checked_mul_native_time_wasmv1(&self, time: &NativeTime, factor: u64) -> Result<NativeTime>;
+    fn checked_scalar_div_native_time_wasmv1(
+        &self,
+        dividend: &NativeTime,
+        divisor: u64
+    ) -> Result<(NativeTime, NativeTime)>;
+    fn checked_div_native_time_wasmv1(
+        &self,
+        dividend: &NativeTime,
+        divisor: &NativeTime
+    ) -> Result<(u64, NativeTime)>;
+    fn base58_check_to_bytes_wasmv1(&self, s: &str) -> Result<Vec<u8>>;
+    fn compare_address_wasmv1(&self, left: &str, right: &str) -> Result<ComparisonResult>;
+    fn compare_native_amount_wasmv1(
+        &self,
+        left: &NativeAmount,
+        right: &NativeAmount
+    ) -> Result<ComparisonResult>;
+    fn compare_native_time_wasmv1(
+        &self,
+        left: &NativeTime,
+        right: &NativeTime
+    ) -> Result<ComparisonResult>;
+    fn compare_pub_key_wasmv1(&self, left: &str, right: &str) -> Result<ComparisonResult>;
+    fn checkpoint(&self) -> Result<u64>;
+    fn rollback(&self, id: u64) -> Result<()>;
+    fn commit(&self, id: u64) -> Result<()>;
+}
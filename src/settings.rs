@@ -1,13 +1,7 @@
 pub(crate) const MAIN: &str = "main";
 
-pub(crate) fn max_number_of_pages() -> u32 {
-    64
-}
-
-pub(crate) fn max_datastore_entry_count() -> usize {
-    100_000
-}
-
-pub(crate) fn max_op_datastore_entry_count() -> usize {
-    128
-}
+/// Defaults of `ExecutionConfig`
+pub(crate) const MAX_NUMBER_OF_PAGES: u32 = 64;
+pub(crate) const MAX_TABLE_ELEMENTS: u32 = 65_536;
+pub(crate) const MAX_DATASTORE_ENTRY_COUNT: usize = 100_000;
+pub(crate) const MAX_OP_DATASTORE_ENTRY_COUNT: usize = 128;
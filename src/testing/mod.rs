@@ -0,0 +1,1471 @@
+//! In-memory `Interface` for the embedders and the contract tests, built with
+//! the `testing` feature.
+//!
+//! `InMemoryInterface` holds a ledger, the datastore of the operation, the
+//! call stack, the events, the async messages and the deferred calls of the
+//! execution, and implements the `Interface` methods on it the way the node
+//! does. The clones given to the sub-executions share the same state, which
+//! is set up and inspected with `InMemoryInterface::state`.
+//!
+//! Hashes and addresses are computed with blake3 and the signatures are
+//! ed25519 signatures of the blake3 hash of the data, like in the node. The
+//! crate has no secp256k1 implementation, the EVM signatures are checked
+//! against the ones registered in the state. Deferred calls are quoted 1
+//! nanocoin per gas unit and per parameter byte, whatever the occupancy of the
+//! target slot.
+
+mod simulator;
+
+use crate::execution::{Compiler, RuntimeModule};
+use crate::types::{Interface, InterfaceClone, InterfaceError, Result};
+use crate::{ExecutionConfig, ModuleCache};
+use ed25519_dalek::{Signature, VerifyingKey};
+use massa_proto_rs::massa::model::v1::{
+    AddressCategory, ComparisonResult, NativeAmount, NativeTime, Slot,
+};
+use parking_lot::{Mutex, MutexGuard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+pub use simulator::Simulator;
+
+/// Decimals of the raw amounts of coins
+const AMOUNT_DECIMALS: u32 = 9;
+const USER_ADDRESS_PREFIX: &str = "AU";
+const SC_ADDRESS_PREFIX: &str = "AS";
+const PUBLIC_KEY_PREFIX: &str = "P";
+const DEFERRED_CALL_PREFIX: &str = "D";
+
+/// Ledger entry of an address
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    /// Raw amount of coins
+    pub balance: u64,
+    pub bytecode: Vec<u8>,
+    pub datastore: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Element of the call stack
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    pub address: String,
+    /// Raw amount of coins sent with the call
+    pub coins: u64,
+    /// Addresses the call has write access to: its own address and the
+    /// addresses of the modules it created
+    pub owned_addresses: Vec<String>,
+}
+
+impl CallFrame {
+    pub fn new(address: &str, coins: u64) -> Self {
+        Self {
+            address: address.to_string(),
+            coins,
+            owned_addresses: vec![address.to_string()],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Address at the top of the call stack when the event was generated
+    pub emitter: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsyncMessage {
+    pub sender: String,
+    pub target_address: String,
+    pub target_handler: String,
+    pub validity_start: (u64, u8),
+    pub validity_end: (u64, u8),
+    pub max_gas: u64,
+    pub fee: u64,
+    pub coins: u64,
+    pub data: Vec<u8>,
+    /// Address and optional datastore key triggering the message
+    pub filter: Option<(String, Option<Vec<u8>>)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeferredCall {
+    pub sender: String,
+    pub target_address: String,
+    pub target_function: String,
+    pub target_slot: (u64, u8),
+    pub max_gas: u64,
+    pub params: Vec<u8>,
+    pub coins: u64,
+    pub fee: u64,
+}
+
+/// State of an `InMemoryInterface`
+#[derive(Clone, Debug)]
+pub struct InMemoryState {
+    pub ledger: BTreeMap<String, Account>,
+    /// Datastore of the operation being executed
+    pub op_datastore: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Current call last
+    pub call_stack: Vec<CallFrame>,
+    pub events: Vec<Event>,
+    pub messages: Vec<AsyncMessage>,
+    pub deferred_calls: BTreeMap<String, DeferredCall>,
+    /// Messages printed with `print`
+    pub printed: Vec<String>,
+    /// Current period and thread
+    pub slot: (u64, u8),
+    pub thread_count: u8,
+    /// Duration of a period in milliseconds
+    pub t0: u64,
+    pub genesis_timestamp: u64,
+    pub chain_id: u64,
+    pub interface_version: u32,
+    pub origin_operation_id: Option<String>,
+    pub recursion_depth: u16,
+    pub max_recursion_depth: u16,
+    /// Maximum gas of a deferred call
+    pub max_deferred_call_gas: u64,
+    /// Public keys of the valid EVM signatures, by data and signature
+    pub evm_signatures: BTreeMap<(Vec<u8>, Vec<u8>), Vec<u8>>,
+    pub gas_remaining_before_subexecution: Option<u64>,
+    /// Seeds the addresses of the created modules and the ids of the
+    /// deferred calls
+    pub nonce: u64,
+    pub rng: StdRng,
+}
+
+impl Default for InMemoryState {
+    fn default() -> Self {
+        Self {
+            ledger: BTreeMap::new(),
+            op_datastore: BTreeMap::new(),
+            call_stack: Vec::new(),
+            events: Vec::new(),
+            messages: Vec::new(),
+            deferred_calls: BTreeMap::new(),
+            printed: Vec::new(),
+            slot: (0, 0),
+            thread_count: 32,
+            t0: 16_000,
+            genesis_timestamp: 1_704_289_800_000,
+            chain_id: 77_658_377,
+            interface_version: 1,
+            origin_operation_id: None,
+            recursion_depth: 0,
+            max_recursion_depth: 25,
+            max_deferred_call_gas: 1_000_000_000,
+            evm_signatures: BTreeMap::new(),
+            gas_remaining_before_subexecution: None,
+            nonce: 0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl InMemoryState {
+    fn current_call(&self) -> Result<&CallFrame> {
+        self.call_stack
+            .last()
+            .ok_or_else(|| "the call stack is empty".into())
+    }
+
+    fn current_address(&self) -> Result<String> {
+        Ok(self.current_call()?.address.clone())
+    }
+
+    /// `address`, or the current address if it is `None`
+    fn address_or_current(&self, address: Option<String>) -> Result<String> {
+        match address {
+            Some(address) => Ok(address),
+            None => self.current_address(),
+        }
+    }
+
+    fn account(&self, address: &str) -> Result<&Account> {
+        self.ledger
+            .get(address)
+            .ok_or_else(|| format!("address {} not found in the ledger", address).into())
+    }
+
+    fn account_mut(&mut self, address: &str) -> Result<&mut Account> {
+        self.ledger
+            .get_mut(address)
+            .ok_or_else(|| format!("address {} not found in the ledger", address).into())
+    }
+
+    fn check_write_access(&self, address: &str) -> Result<()> {
+        if self
+            .current_call()?
+            .owned_addresses
+            .iter()
+            .any(|owned| owned == address)
+        {
+            Ok(())
+        } else {
+            Err(format!("no write access to address {}", address).into())
+        }
+    }
+
+    fn debit(&mut self, address: &str, amount: u64) -> Result<()> {
+        let account = self.account_mut(address)?;
+        account.balance = account.balance.checked_sub(amount).ok_or_else(|| {
+            InterfaceError::from(format!("insufficient balance for address {}", address))
+        })?;
+        Ok(())
+    }
+
+    fn credit(&mut self, address: &str, amount: u64) -> Result<()> {
+        let account = self.ledger.entry(address.to_string()).or_default();
+        account.balance = account
+            .balance
+            .checked_add(amount)
+            .ok_or("balance overflow")?;
+        Ok(())
+    }
+
+    fn transfer(&mut self, from: &str, to: &str, amount: u64) -> Result<()> {
+        self.check_write_access(from)?;
+        decode_address(to)?;
+        self.debit(from, amount)?;
+        self.credit(to, amount)
+    }
+
+    fn data(&self, address: &str, key: &[u8]) -> Result<Vec<u8>> {
+        self.account(address)?
+            .datastore
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("data entry not found for address {}", address).into())
+    }
+
+    fn keys(&self, address: &str, prefix: &[u8]) -> Result<BTreeSet<Vec<u8>>> {
+        Ok(self
+            .account(address)?
+            .datastore
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn set_data(&mut self, address: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check_write_access(address)?;
+        self.account_mut(address)?
+            .datastore
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn append_data(&mut self, address: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check_write_access(address)?;
+        self.account_mut(address)?
+            .datastore
+            .get_mut(key)
+            .ok_or_else(|| {
+                InterfaceError::from(format!("data entry not found for address {}", address))
+            })?
+            .extend_from_slice(value);
+        Ok(())
+    }
+
+    fn delete_data(&mut self, address: &str, key: &[u8]) -> Result<()> {
+        self.check_write_access(address)?;
+        self.account_mut(address)?
+            .datastore
+            .remove(key)
+            .map(|_| ())
+            .ok_or_else(|| format!("data entry not found for address {}", address).into())
+    }
+
+    fn has_data(&self, address: &str, key: &[u8]) -> Result<bool> {
+        Ok(self.account(address)?.datastore.contains_key(key))
+    }
+
+    fn set_bytecode(&mut self, address: &str, bytecode: &[u8]) -> Result<()> {
+        self.check_write_access(address)?;
+        self.account_mut(address)?.bytecode = bytecode.to_vec();
+        Ok(())
+    }
+
+    fn init_call(&mut self, address: &str, coins: u64) -> Result<Vec<u8>> {
+        let bytecode = self.account(address)?.bytecode.clone();
+        let caller = self.current_address()?;
+        self.transfer(&caller, address, coins)?;
+        self.call_stack.push(CallFrame::new(address, coins));
+        Ok(bytecode)
+    }
+
+    fn next_nonce(&mut self) -> [u8; 8] {
+        self.nonce += 1;
+        self.nonce.to_be_bytes()
+    }
+
+    /// Quoted availability and fee of a deferred call
+    fn deferred_call_quote(
+        &self,
+        target_slot: (u64, u8),
+        gas_limit: u64,
+        params_size: u64,
+    ) -> (bool, u64) {
+        let available = target_slot > self.slot
+            && target_slot.1 < self.thread_count
+            && gas_limit <= self.max_deferred_call_gas;
+        (available, gas_limit.saturating_add(params_size))
+    }
+}
+
+/// `Interface` keeping the ledger and the execution context in memory
+///
+/// The state is shared by the clones of the interface.
+#[derive(Clone)]
+pub struct InMemoryInterface {
+    state: Arc<Mutex<InMemoryState>>,
+    /// Used to compile the modules of `get_module` and `get_tmp_module`
+    config: ExecutionConfig,
+    /// Compiled modules, shared by the clones
+    module_cache: Option<Arc<Mutex<ModuleCache>>>,
+    /// States saved by `checkpoint`, the index of a state is its id
+    checkpoints: Arc<Mutex<Vec<InMemoryState>>>,
+}
+
+impl Default for InMemoryInterface {
+    fn default() -> Self {
+        Self::new(ExecutionConfig::default())
+    }
+}
+
+impl InMemoryInterface {
+    pub fn new(config: ExecutionConfig) -> Self {
+        Self {
+            state: Default::default(),
+            config,
+            module_cache: None,
+            checkpoints: Default::default(),
+        }
+    }
+
+    /// Interface loading the modules from `module_cache`, compiled with its
+    /// execution config
+    pub fn with_module_cache(module_cache: ModuleCache) -> Self {
+        Self {
+            state: Default::default(),
+            config: module_cache.config().execution_config.clone(),
+            module_cache: Some(Arc::new(Mutex::new(module_cache))),
+            checkpoints: Default::default(),
+        }
+    }
+
+    /// Lock the state to set it up or inspect it
+    pub fn state(&self) -> MutexGuard<'_, InMemoryState> {
+        self.state.lock()
+    }
+
+    /// Number of checkpoints neither committed nor rolled back
+    pub fn open_checkpoints(&self) -> usize {
+        self.checkpoints.lock().len()
+    }
+
+    /// Set the balance of an address, creating it if needed
+    pub fn set_balance(&self, address: &str, raw_amount: u64) {
+        self.state()
+            .ledger
+            .entry(address.to_string())
+            .or_default()
+            .balance = raw_amount;
+    }
+
+    /// Set the bytecode of an address, creating it if needed
+    pub fn set_bytecode(&self, address: &str, bytecode: &[u8]) {
+        self.state()
+            .ledger
+            .entry(address.to_string())
+            .or_default()
+            .bytecode = bytecode.to_vec();
+    }
+
+    /// Set a datastore entry of an address, creating it if needed
+    pub fn set_data(&self, address: &str, key: &[u8], value: &[u8]) {
+        self.state()
+            .ledger
+            .entry(address.to_string())
+            .or_default()
+            .datastore
+            .insert(key.to_vec(), value.to_vec());
+    }
+
+    /// Push a call on the call stack, creating its address if needed
+    pub fn push_call(&self, address: &str, coins: u64) {
+        let mut state = self.state();
+        state.ledger.entry(address.to_string()).or_default();
+        state.call_stack.push(CallFrame::new(address, coins));
+    }
+
+    fn compile(&self, bytecode: &[u8], compiler: Compiler) -> Result<RuntimeModule> {
+        let module = match (&self.module_cache, compiler) {
+            (Some(cache), Compiler::CL) => cache.lock().load_module(bytecode),
+            (Some(cache), Compiler::SP) => cache.lock().load_tmp_module(bytecode),
+            (None, compiler) => RuntimeModule::new(bytecode, compiler, &self.config),
+        };
+        module.map_err(|err| InterfaceError::GenericError(err.to_string()))
+    }
+}
+
+impl InterfaceClone for InMemoryInterface {
+    fn clone_box(&self) -> Box<dyn Interface> {
+        Box::new(self.clone())
+    }
+}
+
+fn to_native_amount(raw_amount: u64) -> NativeAmount {
+    NativeAmount {
+        mantissa: raw_amount,
+        scale: AMOUNT_DECIMALS,
+    }
+}
+
+/// Raw amount of a `NativeAmount`, which must be representable with
+/// `AMOUNT_DECIMALS` decimals
+fn to_raw_amount(amount: &NativeAmount) -> Result<u64> {
+    let invalid = || InterfaceError::from(format!("invalid amount {:?}", amount));
+    if amount.scale <= AMOUNT_DECIMALS {
+        let factor = 10u64.pow(AMOUNT_DECIMALS - amount.scale);
+        amount.mantissa.checked_mul(factor).ok_or_else(invalid)
+    } else {
+        let divisor = 10u64.checked_pow(amount.scale - AMOUNT_DECIMALS);
+        match divisor {
+            Some(divisor) if amount.mantissa % divisor == 0 => Ok(amount.mantissa / divisor),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn parse_raw_amount(amount: &str) -> Result<u64> {
+    let invalid = || InterfaceError::from(format!("invalid amount {}", amount));
+    let (integer, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if integer.is_empty()
+        || !is_digits(integer)
+        || !is_digits(fraction)
+        || fraction.len() > AMOUNT_DECIMALS as usize
+    {
+        return Err(invalid());
+    }
+    let fraction = format!("{:0<width$}", fraction, width = AMOUNT_DECIMALS as usize);
+    integer
+        .parse::<u64>()
+        .ok()
+        .and_then(|integer| integer.checked_mul(10u64.pow(AMOUNT_DECIMALS)))
+        .and_then(|integer| integer.checked_add(fraction.parse().ok()?))
+        .ok_or_else(invalid)
+}
+
+fn format_raw_amount(raw_amount: u64) -> String {
+    let unit = 10u64.pow(AMOUNT_DECIMALS);
+    let fraction = raw_amount % unit;
+    if fraction == 0 {
+        return (raw_amount / unit).to_string();
+    }
+    let fraction = format!("{:0>width$}", fraction, width = AMOUNT_DECIMALS as usize);
+    format!("{}.{}", raw_amount / unit, fraction.trim_end_matches('0'))
+}
+
+fn compare<T: Ord>(left: T, right: T) -> ComparisonResult {
+    match left.cmp(&right) {
+        std::cmp::Ordering::Less => ComparisonResult::Lower,
+        std::cmp::Ordering::Equal => ComparisonResult::Equal,
+        std::cmp::Ordering::Greater => ComparisonResult::Greater,
+    }
+}
+
+/// Version and payload of a base58check string made of a version byte and
+/// `payload_len` bytes, after `prefix`
+fn decode_versioned(value: &str, prefix: &str, payload_len: usize) -> Result<(u64, Vec<u8>)> {
+    let invalid = || InterfaceError::from(format!("invalid base58check value {}", value));
+    let encoded = value.strip_prefix(prefix).ok_or_else(invalid)?;
+    let bytes = bs58::decode(encoded)
+        .with_check(None)
+        .into_vec()
+        .map_err(|_| invalid())?;
+    match bytes.split_first() {
+        Some((&version, payload)) if version < 0x80 && payload.len() == payload_len => {
+            Ok((version as u64, payload.to_vec()))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn encode_versioned(prefix: &str, payload: &[u8]) -> String {
+    let mut bytes = vec![0];
+    bytes.extend_from_slice(payload);
+    format!(
+        "{}{}",
+        prefix,
+        bs58::encode(bytes).with_check().into_string()
+    )
+}
+
+/// Category, version and hash of an address
+fn decode_address(address: &str) -> Result<(AddressCategory, u64, Vec<u8>)> {
+    let (category, prefix) = if address.starts_with(USER_ADDRESS_PREFIX) {
+        (AddressCategory::UserAddress, USER_ADDRESS_PREFIX)
+    } else {
+        (AddressCategory::ScAddress, SC_ADDRESS_PREFIX)
+    };
+    let (version, hash) = decode_versioned(address, prefix, 32)?;
+    Ok((category, version, hash))
+}
+
+fn decode_public_key(public_key: &str) -> Result<(u64, Vec<u8>)> {
+    decode_versioned(public_key, PUBLIC_KEY_PREFIX, 32)
+}
+
+fn decode_signature(signature: &str) -> Result<(u64, Vec<u8>)> {
+    decode_versioned(signature, "", 64)
+}
+
+fn blake3_hash(bytes: &[u8]) -> [u8; 32] {
+    *blake3::hash(bytes).as_bytes()
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+fn checked_time(milliseconds: Option<u64>, operation: &str) -> Result<NativeTime> {
+    milliseconds
+        .map(|milliseconds| NativeTime { milliseconds })
+        .ok_or_else(|| format!("native time {} overflow or division by zero", operation).into())
+}
+
+impl Interface for InMemoryInterface {
+    fn increment_recursion_counter(&self) -> Result<()> {
+        let mut state = self.state();
+        if state.recursion_depth >= state.max_recursion_depth {
+            return Err(InterfaceError::DepthError(format!(
+                "recursion depth limit {} reached",
+                state.max_recursion_depth
+            )));
+        }
+        state.recursion_depth += 1;
+        Ok(())
+    }
+
+    fn decrement_recursion_counter(&self) -> Result<()> {
+        let mut state = self.state();
+        state.recursion_depth = state
+            .recursion_depth
+            .checked_sub(1)
+            .ok_or_else(|| InterfaceError::DepthError("recursion counter underflow".into()))?;
+        Ok(())
+    }
+
+    fn get_interface_version(&self) -> Result<u32> {
+        Ok(self.state().interface_version)
+    }
+
+    fn init_call(&self, address: &str, raw_coins: u64) -> Result<Vec<u8>> {
+        self.state().init_call(address, raw_coins)
+    }
+
+    fn init_call_wasmv1(&self, address: &str, raw_coins: NativeAmount) -> Result<Vec<u8>> {
+        self.state().init_call(address, to_raw_amount(&raw_coins)?)
+    }
+
+    fn finish_call(&self) -> Result<()> {
+        self.state()
+            .call_stack
+            .pop()
+            .map(|_| ())
+            .ok_or_else(|| "the call stack is empty".into())
+    }
+
+    fn get_balance(&self) -> Result<u64> {
+        let state = self.state();
+        Ok(state.account(&state.current_address()?)?.balance)
+    }
+
+    fn get_balance_for(&self, address: &str) -> Result<u64> {
+        Ok(self.state().account(address)?.balance)
+    }
+
+    fn get_balance_wasmv1(&self, address: Option<String>) -> Result<NativeAmount> {
+        let state = self.state();
+        let address = state.address_or_current(address)?;
+        Ok(to_native_amount(state.account(&address)?.balance))
+    }
+
+    fn transfer_coins(&self, to_address: &str, raw_amount: u64) -> Result<()> {
+        let mut state = self.state();
+        let from_address = state.current_address()?;
+        state.transfer(&from_address, to_address, raw_amount)
+    }
+
+    fn transfer_coins_for(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        raw_amount: u64,
+    ) -> Result<()> {
+        self.state().transfer(from_address, to_address, raw_amount)
+    }
+
+    fn transfer_coins_wasmv1(
+        &self,
+        to_address: String,
+        raw_amount: NativeAmount,
+        from_address: Option<String>,
+    ) -> Result<()> {
+        let mut state = self.state();
+        let from_address = state.address_or_current(from_address)?;
+        state.transfer(&from_address, &to_address, to_raw_amount(&raw_amount)?)
+    }
+
+    fn get_call_coins(&self) -> Result<u64> {
+        Ok(self.state().current_call()?.coins)
+    }
+
+    fn get_call_coins_wasmv1(&self) -> Result<NativeAmount> {
+        Ok(to_native_amount(self.state().current_call()?.coins))
+    }
+
+    fn raw_set_bytecode(&self, bytecode: &[u8]) -> Result<()> {
+        let mut state = self.state();
+        let address = state.current_address()?;
+        state.set_bytecode(&address, bytecode)
+    }
+
+    fn raw_set_bytecode_for(&self, address: &str, bytecode: &[u8]) -> Result<()> {
+        self.state().set_bytecode(address, bytecode)
+    }
+
+    fn set_bytecode_wasmv1(&self, bytecode: &[u8], address: Option<String>) -> Result<()> {
+        let mut state = self.state();
+        let address = state.address_or_current(address)?;
+        state.set_bytecode(&address, bytecode)
+    }
+
+    fn create_module(&self, module: &[u8]) -> Result<String> {
+        let mut state = self.state();
+        let creator = state.current_address()?;
+        let nonce = state.next_nonce();
+        let address = encode_versioned(
+            SC_ADDRESS_PREFIX,
+            &blake3_hash(&[creator.as_bytes(), &nonce].concat()),
+        );
+        state.ledger.insert(
+            address.clone(),
+            Account {
+                bytecode: module.to_vec(),
+                ..Default::default()
+            },
+        );
+        if let Some(call) = state.call_stack.last_mut() {
+            call.owned_addresses.push(address.clone());
+        }
+        Ok(address)
+    }
+
+    fn print(&self, message: &str) -> Result<()> {
+        self.state().printed.push(message.to_string());
+        Ok(())
+    }
+
+    fn get_keys(&self, prefix: Option<&[u8]>) -> Result<BTreeSet<Vec<u8>>> {
+        let state = self.state();
+        state.keys(&state.current_address()?, prefix.unwrap_or_default())
+    }
+
+    fn get_keys_for(&self, address: &str, prefix: Option<&[u8]>) -> Result<BTreeSet<Vec<u8>>> {
+        self.state().keys(address, prefix.unwrap_or_default())
+    }
+
+    fn get_ds_keys_wasmv1(
+        &self,
+        prefix: &[u8],
+        address: Option<String>,
+    ) -> Result<BTreeSet<Vec<u8>>> {
+        let state = self.state();
+        state.keys(&state.address_or_current(address)?, prefix)
+    }
+
+    fn raw_get_data(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let state = self.state();
+        state.data(&state.current_address()?, key)
+    }
+
+    fn raw_get_data_for(&self, address: &str, key: &[u8]) -> Result<Vec<u8>> {
+        self.state().data(address, key)
+    }
+
+    fn get_ds_value_wasmv1(&self, key: &[u8], address: Option<String>) -> Result<Vec<u8>> {
+        let state = self.state();
+        state.data(&state.address_or_current(address)?, key)
+    }
+
+    fn raw_set_data(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut state = self.state();
+        let address = state.current_address()?;
+        state.set_data(&address, key, value)
+    }
+
+    fn raw_set_data_for(&self, address: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.state().set_data(address, key, value)
+    }
+
+    fn set_ds_value_wasmv1(&self, key: &[u8], value: &[u8], address: Option<String>) -> Result<()> {
+        let mut state = self.state();
+        let address = state.address_or_current(address)?;
+        state.set_data(&address, key, value)
+    }
+
+    fn raw_append_data(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut state = self.state();
+        let address = state.current_address()?;
+        state.append_data(&address, key, value)
+    }
+
+    fn raw_append_data_for(&self, address: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.state().append_data(address, key, value)
+    }
+
+    fn append_ds_value_wasmv1(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        address: Option<String>,
+    ) -> Result<()> {
+        let mut state = self.state();
+        let address = state.address_or_current(address)?;
+        state.append_data(&address, key, value)
+    }
+
+    fn raw_delete_data(&self, key: &[u8]) -> Result<()> {
+        let mut state = self.state();
+        let address = state.current_address()?;
+        state.delete_data(&address, key)
+    }
+
+    fn raw_delete_data_for(&self, address: &str, key: &[u8]) -> Result<()> {
+        self.state().delete_data(address, key)
+    }
+
+    fn delete_ds_entry_wasmv1(&self, key: &[u8], address: Option<String>) -> Result<()> {
+        let mut state = self.state();
+        let address = state.address_or_current(address)?;
+        state.delete_data(&address, key)
+    }
+
+    fn has_data(&self, key: &[u8]) -> Result<bool> {
+        let state = self.state();
+        state.has_data(&state.current_address()?, key)
+    }
+
+    fn has_data_for(&self, address: &str, key: &[u8]) -> Result<bool> {
+        self.state().has_data(address, key)
+    }
+
+    fn ds_entry_exists_wasmv1(&self, key: &[u8], address: Option<String>) -> Result<bool> {
+        let state = self.state();
+        state.has_data(&state.address_or_current(address)?, key)
+    }
+
+    fn raw_get_bytecode(&self) -> Result<Vec<u8>> {
+        let state = self.state();
+        Ok(state.account(&state.current_address()?)?.bytecode.clone())
+    }
+
+    fn raw_get_bytecode_for(&self, address: &str) -> Result<Vec<u8>> {
+        Ok(self.state().account(address)?.bytecode.clone())
+    }
+
+    fn get_bytecode_wasmv1(&self, address: Option<String>) -> Result<Vec<u8>> {
+        let state = self.state();
+        let address = state.address_or_current(address)?;
+        Ok(state.account(&address)?.bytecode.clone())
+    }
+
+    fn get_op_keys(&self, prefix: Option<&[u8]>) -> Result<Vec<Vec<u8>>> {
+        self.get_op_keys_wasmv1(prefix.unwrap_or_default())
+    }
+
+    fn get_op_keys_wasmv1(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .state()
+            .op_datastore
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn op_entry_exists(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.state().op_datastore.contains_key(key))
+    }
+
+    fn get_op_data(&self, key: &[u8]) -> Result<Vec<u8>> {
+        self.state()
+            .op_datastore
+            .get(key)
+            .cloned()
+            .ok_or_else(|| "operation datastore entry not found".into())
+    }
+
+    fn caller_has_write_access(&self) -> Result<bool> {
+        let state = self.state();
+        Ok(match state.call_stack.as_slice() {
+            [.., caller, current] => caller.owned_addresses.contains(&current.address),
+            _ => false,
+        })
+    }
+
+    fn hash(&self, data: &[u8]) -> Result<[u8; 32]> {
+        Ok(blake3_hash(data))
+    }
+
+    fn hash_blake3(&self, bytes: &[u8]) -> Result<[u8; 32]> {
+        Ok(blake3_hash(bytes))
+    }
+
+    fn signature_verify(&self, data: &[u8], signature: &str, public_key: &str) -> Result<bool> {
+        let (_, signature) = decode_signature(signature)?;
+        let (_, public_key) = decode_public_key(public_key)?;
+        let signature = Signature::from_slice(&signature)
+            .map_err(|e| InterfaceError::from(format!("invalid signature: {}", e)))?;
+        let public_key = public_key
+            .as_slice()
+            .try_into()
+            .ok()
+            .and_then(|key| VerifyingKey::from_bytes(key).ok())
+            .ok_or_else(|| InterfaceError::from("invalid public key"))?;
+        // the node signs the hash of the data
+        Ok(public_key
+            .verify_strict(&blake3_hash(data), &signature)
+            .is_ok())
+    }
+
+    fn evm_signature_verify(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<bool> {
+        Ok(self
+            .state()
+            .evm_signatures
+            .get(&(message.to_vec(), signature.to_vec()))
+            .is_some_and(|signer| signer == public_key))
+    }
+
+    fn evm_get_address_from_pubkey(&self, public_key: &[u8]) -> Result<Vec<u8>> {
+        // uncompressed key, with or without its 0x04 tag
+        let key = match public_key {
+            [0x04, key @ ..] if key.len() == 64 => key,
+            key if key.len() == 64 => key,
+            _ => return Err("invalid EVM public key".into()),
+        };
+        Ok(keccak256(key)[12..].to_vec())
+    }
+
+    fn evm_get_pubkey_from_signature(&self, hash: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+        self.state()
+            .evm_signatures
+            .get(&(hash.to_vec(), signature.to_vec()))
+            .cloned()
+            .ok_or_else(|| "unknown EVM signature".into())
+    }
+
+    fn is_address_eoa(&self, address: &str) -> Result<bool> {
+        Ok(decode_address(address)?.0 == AddressCategory::UserAddress)
+    }
+
+    fn address_from_public_key(&self, public_key: &str) -> Result<String> {
+        let (_, key) = decode_public_key(public_key)?;
+        Ok(encode_versioned(USER_ADDRESS_PREFIX, &blake3_hash(&key)))
+    }
+
+    fn validate_address(&self, address: &str) -> Result<bool> {
+        Ok(decode_address(address).is_ok())
+    }
+
+    fn get_time(&self) -> Result<u64> {
+        let state = self.state();
+        let (period, thread) = state.slot;
+        let thread_offset = state.t0 / state.thread_count.max(1) as u64 * thread as u64;
+        Ok(state
+            .genesis_timestamp
+            .saturating_add(period.saturating_mul(state.t0))
+            .saturating_add(thread_offset))
+    }
+
+    fn unsafe_random(&self) -> Result<i64> {
+        Ok(self.state().rng.gen())
+    }
+
+    fn unsafe_random_f64(&self) -> Result<f64> {
+        Ok(self.state().rng.gen())
+    }
+
+    fn unsafe_random_wasmv1(&self, num_bytes: u64) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; num_bytes as usize];
+        self.state().rng.fill(bytes.as_mut_slice());
+        Ok(bytes)
+    }
+
+    fn get_current_period(&self) -> Result<u64> {
+        Ok(self.state().slot.0)
+    }
+
+    fn get_current_thread(&self) -> Result<u8> {
+        Ok(self.state().slot.1)
+    }
+
+    fn get_current_slot(&self) -> Result<Slot> {
+        let (period, thread) = self.state().slot;
+        Ok(Slot {
+            period,
+            thread: thread as u32,
+        })
+    }
+
+    fn get_owned_addresses(&self) -> Result<Vec<String>> {
+        Ok(self.state().current_call()?.owned_addresses.clone())
+    }
+
+    fn get_call_stack(&self) -> Result<Vec<String>> {
+        Ok(self
+            .state()
+            .call_stack
+            .iter()
+            .map(|call| call.address.clone())
+            .collect())
+    }
+
+    fn generate_event(&self, event: String) -> Result<()> {
+        self.generate_event_wasmv1(event.into_bytes())
+    }
+
+    fn generate_event_wasmv1(&self, event: Vec<u8>) -> Result<()> {
+        let mut state = self.state();
+        let emitter = state.current_address()?;
+        state.events.push(Event {
+            emitter,
+            data: event,
+        });
+        Ok(())
+    }
+
+    fn get_module(&self, bytecode: &[u8], _gas_limit: u64) -> Result<RuntimeModule> {
+        self.compile(bytecode, Compiler::CL)
+    }
+
+    fn get_tmp_module(&self, bytecode: &[u8], _gas_limit: u64) -> Result<RuntimeModule> {
+        self.compile(bytecode, Compiler::SP)
+    }
+
+    fn send_message(
+        &self,
+        target_address: &str,
+        target_handler: &str,
+        validity_start: (u64, u8),
+        validity_end: (u64, u8),
+        max_gas: u64,
+        raw_fee: u64,
+        raw_coins: u64,
+        data: &[u8],
+        filter: Option<(&str, Option<&[u8]>)>,
+    ) -> Result<()> {
+        let mut state = self.state();
+        if validity_end < validity_start
+            || validity_start.1 >= state.thread_count
+            || validity_end.1 >= state.thread_count
+        {
+            return Err("invalid message validity period".into());
+        }
+        decode_address(target_address)?;
+        let sender = state.current_address()?;
+        state.debit(
+            &sender,
+            raw_coins.checked_add(raw_fee).ok_or("coins overflow")?,
+        )?;
+        state.messages.push(AsyncMessage {
+            sender,
+            target_address: target_address.to_string(),
+            target_handler: target_handler.to_string(),
+            validity_start,
+            validity_end,
+            max_gas,
+            fee: raw_fee,
+            coins: raw_coins,
+            data: data.to_vec(),
+            filter: filter.map(|(address, key)| (address.to_string(), key.map(<[u8]>::to_vec))),
+        });
+        Ok(())
+    }
+
+    fn get_origin_operation_id(&self) -> Result<Option<String>> {
+        Ok(self.state().origin_operation_id.clone())
+    }
+
+    fn hash_sha256(&self, bytes: &[u8]) -> Result<[u8; 32]> {
+        Ok(sha256(bytes))
+    }
+
+    fn hash_keccak256(&self, bytes: &[u8]) -> Result<[u8; 32]> {
+        Ok(keccak256(bytes))
+    }
+
+    fn chain_id(&self) -> Result<u64> {
+        Ok(self.state().chain_id)
+    }
+
+    fn get_deferred_call_quote(
+        &self,
+        target_slot: (u64, u8),
+        gas_limit: u64,
+        params_size: u64,
+    ) -> Result<(bool, u64)> {
+        Ok(self
+            .state()
+            .deferred_call_quote(target_slot, gas_limit, params_size))
+    }
+
+    fn deferred_call_register(
+        &self,
+        target_addr: &str,
+        target_func: &str,
+        target_slot: (u64, u8),
+        max_gas: u64,
+        params: &[u8],
+        coins: u64,
+    ) -> Result<String> {
+        let mut state = self.state();
+        let (available, fee) = state.deferred_call_quote(target_slot, max_gas, params.len() as u64);
+        if !available {
+            return Err("the deferred call slot is not available".into());
+        }
+        decode_address(target_addr)?;
+        let sender = state.current_address()?;
+        state.debit(&sender, coins.checked_add(fee).ok_or("coins overflow")?)?;
+        let nonce = state.next_nonce();
+        let id = encode_versioned(DEFERRED_CALL_PREFIX, &nonce);
+        state.deferred_calls.insert(
+            id.clone(),
+            DeferredCall {
+                sender,
+                target_address: target_addr.to_string(),
+                target_function: target_func.to_string(),
+                target_slot,
+                max_gas,
+                params: params.to_vec(),
+                coins,
+                fee,
+            },
+        );
+        Ok(id)
+    }
+
+    fn deferred_call_exists(&self, id: &str) -> Result<bool> {
+        Ok(self.state().deferred_calls.contains_key(id))
+    }
+
+    fn deferred_call_cancel(&self, id: &str) -> Result<()> {
+        let mut state = self.state();
+        let current_address = state.current_address()?;
+        match state.deferred_calls.get(id) {
+            Some(call) if call.sender == current_address => (),
+            Some(_) => return Err("only the sender can cancel a deferred call".into()),
+            None => return Err(format!("deferred call {} not found", id).into()),
+        }
+        if let Some(call) = state.deferred_calls.remove(id) {
+            state.credit(&call.sender, call.coins)?;
+        }
+        Ok(())
+    }
+
+    fn native_amount_from_str_wasmv1(&self, amount: &str) -> Result<NativeAmount> {
+        Ok(to_native_amount(parse_raw_amount(amount)?))
+    }
+
+    fn native_amount_to_string_wasmv1(&self, amount: &NativeAmount) -> Result<String> {
+        Ok(format_raw_amount(to_raw_amount(amount)?))
+    }
+
+    fn check_native_amount_wasmv1(&self, amount: &NativeAmount) -> Result<bool> {
+        Ok(to_raw_amount(amount).is_ok())
+    }
+
+    fn add_native_amount_wasmv1(
+        &self,
+        amount1: &NativeAmount,
+        amount2: &NativeAmount,
+    ) -> Result<NativeAmount> {
+        to_raw_amount(amount1)?
+            .checked_add(to_raw_amount(amount2)?)
+            .map(to_native_amount)
+            .ok_or_else(|| "native amount overflow".into())
+    }
+
+    fn sub_native_amount_wasmv1(
+        &self,
+        amount1: &NativeAmount,
+        amount2: &NativeAmount,
+    ) -> Result<NativeAmount> {
+        to_raw_amount(amount1)?
+            .checked_sub(to_raw_amount(amount2)?)
+            .map(to_native_amount)
+            .ok_or_else(|| "native amount underflow".into())
+    }
+
+    fn scalar_mul_native_amount_wasmv1(
+        &self,
+        amount: &NativeAmount,
+        factor: u64,
+    ) -> Result<NativeAmount> {
+        to_raw_amount(amount)?
+            .checked_mul(factor)
+            .map(to_native_amount)
+            .ok_or_else(|| "native amount overflow".into())
+    }
+
+    fn scalar_div_rem_native_amount_wasmv1(
+        &self,
+        dividend: &NativeAmount,
+        divisor: u64,
+    ) -> Result<(NativeAmount, NativeAmount)> {
+        let dividend = to_raw_amount(dividend)?;
+        match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+            (Some(quotient), Some(remainder)) => {
+                Ok((to_native_amount(quotient), to_native_amount(remainder)))
+            }
+            _ => Err("native amount division by zero".into()),
+        }
+    }
+
+    fn div_rem_native_amount_wasmv1(
+        &self,
+        dividend: &NativeAmount,
+        divisor: &NativeAmount,
+    ) -> Result<(u64, NativeAmount)> {
+        let dividend = to_raw_amount(dividend)?;
+        let divisor = to_raw_amount(divisor)?;
+        match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+            (Some(quotient), Some(remainder)) => Ok((quotient, to_native_amount(remainder))),
+            _ => Err("native amount division by zero".into()),
+        }
+    }
+
+    fn check_address_wasmv1(&self, to_check: &str) -> Result<bool> {
+        Ok(decode_address(to_check).is_ok())
+    }
+
+    fn check_pubkey_wasmv1(&self, to_check: &str) -> Result<bool> {
+        Ok(decode_public_key(to_check).is_ok())
+    }
+
+    fn check_signature_wasmv1(&self, to_check: &str) -> Result<bool> {
+        Ok(decode_signature(to_check).is_ok())
+    }
+
+    fn get_address_category_wasmv1(&self, to_check: &str) -> Result<AddressCategory> {
+        Ok(decode_address(to_check)?.0)
+    }
+
+    fn get_address_version_wasmv1(&self, address: &str) -> Result<u64> {
+        Ok(decode_address(address)?.1)
+    }
+
+    fn get_pubkey_version_wasmv1(&self, pubkey: &str) -> Result<u64> {
+        Ok(decode_public_key(pubkey)?.0)
+    }
+
+    fn get_signature_version_wasmv1(&self, signature: &str) -> Result<u64> {
+        Ok(decode_signature(signature)?.0)
+    }
+
+    fn checked_add_native_time_wasmv1(
+        &self,
+        time1: &NativeTime,
+        time2: &NativeTime,
+    ) -> Result<NativeTime> {
+        checked_time(
+            time1.milliseconds.checked_add(time2.milliseconds),
+            "addition",
+        )
+    }
+
+    fn checked_sub_native_time_wasmv1(
+        &self,
+        time1: &NativeTime,
+        time2: &NativeTime,
+    ) -> Result<NativeTime> {
+        checked_time(
+            time1.milliseconds.checked_sub(time2.milliseconds),
+            "subtraction",
+        )
+    }
+
+    fn checked_mul_native_time_wasmv1(&self, time: &NativeTime, factor: u64) -> Result<NativeTime> {
+        checked_time(time.milliseconds.checked_mul(factor), "multiplication")
+    }
+
+    fn checked_scalar_div_native_time_wasmv1(
+        &self,
+        dividend: &NativeTime,
+        divisor: u64,
+    ) -> Result<(NativeTime, NativeTime)> {
+        Ok((
+            checked_time(dividend.milliseconds.checked_div(divisor), "division")?,
+            checked_time(dividend.milliseconds.checked_rem(divisor), "division")?,
+        ))
+    }
+
+    fn checked_div_native_time_wasmv1(
+        &self,
+        dividend: &NativeTime,
+        divisor: &NativeTime,
+    ) -> Result<(u64, NativeTime)> {
+        Ok((
+            checked_time(
+                dividend.milliseconds.checked_div(divisor.milliseconds),
+                "division",
+            )?
+            .milliseconds,
+            checked_time(
+                dividend.milliseconds.checked_rem(divisor.milliseconds),
+                "division",
+            )?,
+        ))
+    }
+
+    fn base58_check_to_bytes_wasmv1(&self, s: &str) -> Result<Vec<u8>> {
+        bs58::decode(s)
+            .with_check(None)
+            .into_vec()
+            .map_err(|err| format!("invalid base58check value: {}", err).into())
+    }
+
+    fn bytes_to_base58_check_wasmv1(&self, bytes: &[u8]) -> String {
+        bs58::encode(bytes).with_check().into_string()
+    }
+
+    fn compare_address_wasmv1(&self, left: &str, right: &str) -> Result<ComparisonResult> {
+        let (left_category, _, left) = decode_address(left)?;
+        let (right_category, _, right) = decode_address(right)?;
+        Ok(compare(
+            (left_category as i32, left),
+            (right_category as i32, right),
+        ))
+    }
+
+    fn compare_native_amount_wasmv1(
+        &self,
+        left: &NativeAmount,
+        right: &NativeAmount,
+    ) -> Result<ComparisonResult> {
+        Ok(compare(to_raw_amount(left)?, to_raw_amount(right)?))
+    }
+
+    fn compare_native_time_wasmv1(
+        &self,
+        left: &NativeTime,
+        right: &NativeTime,
+    ) -> Result<ComparisonResult> {
+        Ok(compare(left.milliseconds, right.milliseconds))
+    }
+
+    fn compare_pub_key_wasmv1(&self, left: &str, right: &str) -> Result<ComparisonResult> {
+        Ok(compare(
+            decode_public_key(left)?.1,
+            decode_public_key(right)?.1,
+        ))
+    }
+
+    fn save_gas_remaining_before_subexecution(&self, gas_used_until: u64) {
+        self.state().gas_remaining_before_subexecution = Some(gas_used_until);
+    }
+
+    fn checkpoint(&self) -> Result<u64> {
+        let mut checkpoints = self.checkpoints.lock();
+        checkpoints.push(self.state().clone());
+        Ok(checkpoints.len() as u64 - 1)
+    }
+
+    fn rollback(&self, id: u64) -> Result<()> {
+        let mut checkpoints = self.checkpoints.lock();
+        if id as usize >= checkpoints.len() {
+            return Err(format!("checkpoint {} not found", id).into());
+        }
+        // the checkpoints taken after `id` are dropped with it
+        *self.state() = checkpoints.swap_remove(id as usize);
+        checkpoints.truncate(id as usize);
+        Ok(())
+    }
+
+    fn commit(&self, id: u64) -> Result<()> {
+        let mut checkpoints = self.checkpoints.lock();
+        if id as usize >= checkpoints.len() {
+            return Err(format!("checkpoint {} not found", id).into());
+        }
+        checkpoints.truncate(id as usize);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(mantissa: u64, scale: u32) -> NativeAmount {
+        NativeAmount { mantissa, scale }
+    }
+
+    #[test]
+    fn test_ledger_and_calls() {
+        let interface = InMemoryInterface::default();
+        let user = encode_versioned(USER_ADDRESS_PREFIX, &[1; 32]);
+        let contract = encode_versioned(SC_ADDRESS_PREFIX, &[2; 32]);
+        interface.push_call(&user, 0);
+        interface.set_balance(&user, 1_000);
+        interface.set_bytecode(&contract, b"bytecode");
+
+        assert_eq!(interface.init_call(&contract, 300).unwrap(), b"bytecode");
+        assert_eq!(
+            interface.get_call_stack().unwrap(),
+            vec![user.clone(), contract.clone()]
+        );
+        assert_eq!(interface.get_balance().unwrap(), 300);
+        assert_eq!(interface.get_call_coins().unwrap(), 300);
+        assert!(!interface.caller_has_write_access().unwrap());
+        interface.raw_set_data(b"key", b"a").unwrap();
+        interface.raw_append_data(b"key", b"b").unwrap();
+        assert_eq!(interface.raw_get_data(b"key").unwrap(), b"ab");
+        assert!(interface.raw_set_data_for(&user, b"key", b"a").is_err());
+        assert!(interface.transfer_coins(&user, 301).is_err());
+        interface.transfer_coins(&user, 100).unwrap();
+        interface.generate_event("done".to_string()).unwrap();
+        interface.finish_call().unwrap();
+
+        let state = interface.state();
+        assert_eq!(state.ledger[&user].balance, 800);
+        assert_eq!(state.ledger[&contract].balance, 200);
+        assert_eq!(state.ledger[&contract].datastore[&b"key".to_vec()], b"ab");
+        assert_eq!(state.events[0].emitter, contract);
+        assert_eq!(state.call_stack.len(), 1);
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let interface = InMemoryInterface::default();
+        let user = encode_versioned(USER_ADDRESS_PREFIX, &[1; 32]);
+        interface.push_call(&user, 0);
+
+        let outer = interface.checkpoint().unwrap();
+        interface.raw_set_data(b"outer", b"1").unwrap();
+        let inner = interface.checkpoint().unwrap();
+        interface.raw_set_data(b"inner", b"1").unwrap();
+        interface.rollback(inner).unwrap();
+        assert!(!interface.has_data(b"inner").unwrap());
+        assert!(interface.has_data(b"outer").unwrap());
+        assert!(interface.commit(inner).is_err());
+        interface.commit(outer).unwrap();
+
+        let checkpoint = interface.checkpoint().unwrap();
+        interface.raw_delete_data(b"outer").unwrap();
+        interface.rollback(checkpoint).unwrap();
+        assert!(interface.has_data(b"outer").unwrap());
+    }
+
+    #[test]
+    fn test_native_values() {
+        let interface = InMemoryInterface::default();
+        let one = interface.native_amount_from_str_wasmv1("1.5").unwrap();
+        assert_eq!(one, amount(1_500_000_000, 9));
+        assert_eq!(
+            interface
+                .native_amount_to_string_wasmv1(&amount(25, 1))
+                .unwrap(),
+            "2.5"
+        );
+        assert!(interface
+            .native_amount_from_str_wasmv1("1.0000000001")
+            .is_err());
+        assert!(!interface
+            .check_native_amount_wasmv1(&amount(1, 10))
+            .unwrap());
+        assert_eq!(
+            interface
+                .add_native_amount_wasmv1(&one, &amount(5, 1))
+                .unwrap(),
+            amount(2_000_000_000, 9)
+        );
+        assert!(interface
+            .sub_native_amount_wasmv1(&amount(1, 0), &one)
+            .is_err());
+        assert_eq!(
+            interface
+                .div_rem_native_amount_wasmv1(&one, &amount(1, 0))
+                .unwrap(),
+            (1, amount(500_000_000, 9))
+        );
+        assert_eq!(
+            interface
+                .compare_native_amount_wasmv1(&amount(10, 1), &amount(1, 0))
+                .unwrap(),
+            ComparisonResult::Equal
+        );
+        assert!(interface
+            .checked_scalar_div_native_time_wasmv1(&NativeTime { milliseconds: 1 }, 0)
+            .is_err());
+
+        let address = interface
+            .address_from_public_key(&encode_versioned(PUBLIC_KEY_PREFIX, &[3; 32]))
+            .unwrap();
+        assert!(interface.is_address_eoa(&address).unwrap());
+        assert_eq!(
+            interface.get_address_category_wasmv1(&address).unwrap(),
+            AddressCategory::UserAddress
+        );
+        assert!(!interface.check_address_wasmv1("AU12").unwrap());
+    }
+
+    #[test]
+    fn test_signature_verify() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let interface = InMemoryInterface::default();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = encode_versioned(PUBLIC_KEY_PREFIX, key.verifying_key().as_bytes());
+        let data = b"massa".to_vec();
+        let hash = interface.hash_blake3(&data).unwrap();
+        assert_eq!(hash, *blake3::hash(&data).as_bytes());
+        let signature = encode_versioned("", &key.sign(&hash).to_bytes());
+
+        assert!(interface
+            .signature_verify(&data, &signature, &public_key)
+            .unwrap());
+        // signed data is the hash, not the data itself
+        let data_signature = encode_versioned("", &key.sign(&data).to_bytes());
+        assert!(!interface
+            .signature_verify(&data, &data_signature, &public_key)
+            .unwrap());
+        let other_key = encode_versioned(
+            PUBLIC_KEY_PREFIX,
+            SigningKey::from_bytes(&[8; 32]).verifying_key().as_bytes(),
+        );
+        assert!(!interface
+            .signature_verify(&data, &signature, &other_key)
+            .unwrap());
+        assert!(interface
+            .signature_verify(&data, "not a signature", &public_key)
+            .is_err());
+    }
+}
@@ -0,0 +1,327 @@
+//! Local execution of calls to contracts deployed in an `InMemoryInterface`.
+//!
+//! Contracts call each other through the interface: `init_call` moves the
+//! coins and returns the bytecode of the callee, and `get_module` loads it
+//! from the module cache, so `abi_call` and `assembly_script_call` are
+//! resolved at any depth. A failed call reverts the state, as the node does.
+
+use super::{
+    blake3_hash, encode_versioned, Account, CallFrame, Event, InMemoryInterface, InMemoryState,
+    SC_ADDRESS_PREFIX, USER_ADDRESS_PREFIX,
+};
+use crate::error::{ExecutionFailure, GasUsage};
+use crate::{
+    run_function_detailed, ExecutionConfig, Interface, InterfaceError, ModuleCache,
+    ModuleCacheConfig, Response, VMError,
+};
+use parking_lot::MutexGuard;
+use std::sync::Arc;
+
+/// Number of compiled modules kept in memory
+const MODULE_CACHE_SIZE: usize = 100;
+
+/// Contracts and users of a local simulation
+pub struct Simulator {
+    interface: InMemoryInterface,
+    config: ExecutionConfig,
+}
+
+impl Simulator {
+    pub fn new(config: ExecutionConfig) -> anyhow::Result<Self> {
+        let module_cache = ModuleCache::new(ModuleCacheConfig {
+            hd_cache_path: None,
+            lru_cache_size: MODULE_CACHE_SIZE,
+            execution_config: config.clone(),
+        })?;
+        Ok(Self {
+            interface: InMemoryInterface::with_module_cache(module_cache),
+            config,
+        })
+    }
+
+    /// Interface the calls are executed with
+    pub fn interface(&self) -> &InMemoryInterface {
+        &self.interface
+    }
+
+    /// Lock the state to set it up or inspect it
+    pub fn state(&self) -> MutexGuard<'_, InMemoryState> {
+        self.interface.state()
+    }
+
+    /// Create a user address holding `balance` raw coins
+    pub fn create_user(&self, balance: u64) -> String {
+        self.create_account(
+            USER_ADDRESS_PREFIX,
+            Account {
+                balance,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Deploy `bytecode` at a new smart contract address
+    pub fn deploy(&self, bytecode: &[u8]) -> String {
+        self.create_account(
+            SC_ADDRESS_PREFIX,
+            Account {
+                bytecode: bytecode.to_vec(),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn create_account(&self, prefix: &str, account: Account) -> String {
+        let mut state = self.state();
+        let nonce = state.next_nonce();
+        let address = encode_versioned(prefix, &blake3_hash(&nonce));
+        state.ledger.insert(address.clone(), account);
+        address
+    }
+
+    /// Call `function` of the contract at `address` as `caller`, sending it
+    /// `coins` raw coins. The state is reverted if the call fails.
+    pub fn call(
+        &self,
+        caller: &str,
+        address: &str,
+        function: &str,
+        param: &[u8],
+        coins: u64,
+        gas_limit: u64,
+    ) -> Result<Response, ExecutionFailure> {
+        let snapshot = self.state().clone();
+        let result = self.execute(caller, address, function, param, coins, gas_limit);
+        let mut state = self.state();
+        match result {
+            Ok(_) => state.call_stack.clear(),
+            Err(_) => *state = snapshot,
+        }
+        result
+    }
+
+    fn execute(
+        &self,
+        caller: &str,
+        address: &str,
+        function: &str,
+        param: &[u8],
+        coins: u64,
+        gas_limit: u64,
+    ) -> Result<Response, ExecutionFailure> {
+        let interface_failure = |error: InterfaceError| {
+            ExecutionFailure::from(VMError::Interface {
+                error: Arc::new(error),
+                gas: GasUsage::default(),
+            })
+            .locate(function, 1)
+        };
+        {
+            let mut state = self.state();
+            state.ledger.entry(caller.to_string()).or_default();
+            state.call_stack = vec![CallFrame::new(caller, 0)];
+        }
+        let bytecode = self
+            .interface
+            .init_call(address, coins)
+            .map_err(interface_failure)?;
+        let module = self
+            .interface
+            .get_module(&bytecode, gas_limit)
+            .map_err(interface_failure)?;
+        run_function_detailed(
+            &self.interface,
+            module,
+            function,
+            param,
+            gas_limit,
+            &self.config,
+        )
+    }
+
+    /// Raw balance of an address, 0 if it is not in the ledger
+    pub fn balance(&self, address: &str) -> u64 {
+        self.state()
+            .ledger
+            .get(address)
+            .map_or(0, |account| account.balance)
+    }
+
+    /// Events generated by the successful calls
+    pub fn events(&self) -> Vec<Event> {
+        self.state().events.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use massa_proto_rs::massa::abi::v1::{CallRequest, SetDsValueRequest};
+    use massa_proto_rs::massa::model::v1::NativeAmount;
+    use prost::Message;
+    use serial_test::serial;
+
+    /// WasmV1 contract exporting the given functions, each calling the given
+    /// ABIs in order with the given encoded requests, and trapping at the end
+    /// if asked to
+    fn wasmv1_contract(functions: &[(&str, Vec<(&str, Vec<u8>)>, bool)]) -> Vec<u8> {
+        let mut imports = String::new();
+        let mut data = String::new();
+        let mut funcs = String::new();
+        let mut imported = Vec::new();
+        let mut offset = 1024;
+        for (function, calls, traps) in functions {
+            let mut body = String::new();
+            for (abi, request) in calls {
+                if !imported.contains(abi) {
+                    imported.push(*abi);
+                    imports += &format!(
+                        r#"(import "massa" "{abi}" (func ${abi} (param i32) (result i32)))"#
+                    );
+                }
+                let mut arg = (request.len() as u32).to_le_bytes().to_vec();
+                arg.extend(request);
+                let bytes: String = arg.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+                data += &format!(r#"(data (i32.const {offset}) "{bytes}")"#);
+                body += &format!("i32.const {offset} call ${abi} drop ");
+                offset += arg.len();
+            }
+            if *traps {
+                body += "unreachable ";
+            }
+            funcs += &format!(
+                r#"(func (export "{function}") (param i32) (result i32)
+                    {body}
+                    i32.const 0)"#
+            );
+        }
+        let wat = format!(
+            r#"(module
+                {imports}
+                (memory $0 1)
+                (export "memory" (memory $0))
+                {data}
+                (global $next (mut i32) (i32.const {offset}))
+                (func (export "__alloc") (param $len i32) (result i32)
+                    global.get $next
+                    (global.set $next (i32.add (global.get $next) (local.get $len))))
+                {funcs})"#
+        );
+        let mut bytecode = vec![1u8];
+        bytecode.extend_from_slice(&wasmer::wat2wasm(wat.as_bytes()).unwrap());
+        bytecode
+    }
+
+    fn set_ds_value(key: &[u8]) -> (&'static str, Vec<u8>) {
+        let request = SetDsValueRequest {
+            key: key.to_vec(),
+            value: b"value".to_vec(),
+            ..Default::default()
+        };
+        ("abi_set_ds_value", request.encode_to_vec())
+    }
+
+    fn call(address: &str, function: &str, raw_coins: u64) -> (&'static str, Vec<u8>) {
+        let request = CallRequest {
+            target_sc_address: address.to_string(),
+            target_function_name: function.to_string(),
+            function_arg: Vec::new(),
+            call_coins: Some(NativeAmount {
+                mantissa: raw_coins,
+                scale: 9,
+            }),
+        };
+        ("abi_call", request.encode_to_vec())
+    }
+
+    fn has_key(simulator: &Simulator, address: &str, key: &[u8]) -> bool {
+        simulator.state().ledger[address]
+            .datastore
+            .contains_key(key)
+    }
+
+    #[test]
+    #[serial]
+    fn test_call_and_revert() {
+        let simulator = Simulator::new(ExecutionConfig::default()).unwrap();
+        let user = simulator.create_user(1_000);
+        let bytecode = wasmer::wat2wasm(
+            br#"(module
+                (memory $0 1)
+                (export "memory" (memory $0))
+                (func (export "receive"))
+                (func (export "fail")
+                    unreachable))"#,
+        )
+        .unwrap();
+        let contract = simulator.deploy(&bytecode);
+
+        simulator
+            .call(&user, &contract, "receive", &[], 300, 100_000)
+            .unwrap();
+        assert_eq!(simulator.balance(&user), 700);
+        assert_eq!(simulator.balance(&contract), 300);
+        assert!(simulator.state().call_stack.is_empty());
+
+        let failure = simulator
+            .call(&user, &contract, "fail", &[], 200, 100_000)
+            .unwrap_err();
+        assert!(matches!(failure.error, VMError::Trap { .. }));
+        assert_eq!(failure.function, "fail");
+        assert_eq!(simulator.balance(&user), 700);
+        assert_eq!(simulator.balance(&contract), 300);
+
+        let failure = simulator
+            .call(&user, &contract, "receive", &[], 1_000, 100_000)
+            .unwrap_err();
+        assert!(matches!(failure.error, VMError::Interface { .. }));
+        assert_eq!(simulator.balance(&user), 700);
+        assert!(simulator.state().call_stack.is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_nested_call_and_revert() {
+        let simulator = Simulator::new(ExecutionConfig::default()).unwrap();
+        let user = simulator.create_user(1_000);
+        let callee = simulator.deploy(&wasmv1_contract(&[
+            ("write", vec![set_ds_value(b"callee")], false),
+            ("fail", vec![set_ds_value(b"failed callee")], true),
+        ]));
+        let caller = simulator.deploy(&wasmv1_contract(&[
+            (
+                "call_write",
+                vec![set_ds_value(b"caller"), call(&callee, "write", 10)],
+                false,
+            ),
+            (
+                "call_fail",
+                vec![set_ds_value(b"failed caller"), call(&callee, "fail", 10)],
+                false,
+            ),
+        ]));
+
+        // the callee is loaded from the module cache by `abi_call`
+        simulator
+            .call(&user, &caller, "call_write", &[], 100, 100_000_000)
+            .unwrap();
+        assert!(has_key(&simulator, &caller, b"caller"));
+        assert!(has_key(&simulator, &callee, b"callee"));
+        assert_eq!(simulator.balance(&caller), 90);
+        assert_eq!(simulator.balance(&callee), 10);
+
+        // the failure of the callee reverts the writes and transfers of both
+        let failure = simulator
+            .call(&user, &caller, "call_fail", &[], 100, 100_000_000)
+            .unwrap_err();
+        assert!(matches!(failure.error, VMError::Trap { .. }));
+        assert_eq!(failure.function, "fail");
+        assert_eq!(failure.depth, 2);
+        assert!(!has_key(&simulator, &caller, b"failed caller"));
+        assert!(!has_key(&simulator, &callee, b"failed callee"));
+        assert_eq!(simulator.balance(&user), 900);
+        assert_eq!(simulator.balance(&caller), 90);
+        assert_eq!(simulator.balance(&callee), 10);
+        assert!(simulator.state().call_stack.is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::as_execution::ASModule;
 use crate::types::{Interface, InterfaceClone, Result};
-use crate::{Compiler, CondomLimits, GasCosts, RuntimeModule};
+use crate::{Compiler, ExecutionConfig, RuntimeModule};
 
 use massa_proto_rs::massa::model::v1::*;
 use sha2::{Digest, Sha256};
@@ -129,9 +129,8 @@ impl Interface for TestInterface {
         let as_module = ASModule::new(
             bytecode,
             gas_limit,
-            GasCosts::default(),
             Compiler::CL,
-            CondomLimits::default(),
+            &ExecutionConfig::default(),
         )
         .unwrap();
         let module = RuntimeModule::ASModule(as_module);
@@ -143,9 +142,8 @@ impl Interface for TestInterface {
         let as_module = ASModule::new(
             bytecode,
             gas_limit,
-            GasCosts::default(),
             Compiler::SP,
-            CondomLimits::default(),
+            &ExecutionConfig::default(),
         )
         .unwrap();
         let module = RuntimeModule::ASModule(as_module);
@@ -4,7 +4,7 @@ use crate::middlewares::operator::{
     _OPERATOR_BULK_MEMORY, _OPERATOR_NON_TRAPPING_FLOAT_TO_INT, _OPERATOR_THREAD, _OPERATOR_VECTOR,
 };
 use crate::tests::TestInterface;
-use crate::{run_main_gc, types::Interface, CondomLimits, GasCosts, RuntimeModule};
+use crate::{run_main_gc, types::Interface, ExecutionConfig, RuntimeModule};
 use std::collections::HashSet;
 
 use anyhow::Result;
@@ -20,22 +20,9 @@ fn test_basic_abi_call_counter() -> Result<()> {
         "/wasm/gc_abi_call_basic.wasm"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )?;
-    let gas_calibration_result = run_main_gc(
-        &interface,
-        runtime_module,
-        b"",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config)?;
+    let gas_calibration_result = run_main_gc(&interface, runtime_module, b"", 100_000, &config)?;
     // println!("gas_calibration_result: {:?}", gas_calibration_result);
 
     // Note:
@@ -93,19 +80,12 @@ fn test_basic_abi_call_counter_wasmv1() -> Result<()> {
         "/wasm/test_gc_abi_call_basic.wasm_add"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     // let runtime_module =
     //     RuntimeModule::new(bytecode, 100_000, gas_costs.clone(),
     // Compiler::SP, CondomLimits::default())?;?;
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -116,14 +96,7 @@ fn test_basic_abi_call_counter_wasmv1() -> Result<()> {
         }
     }
 
-    let gas_calibration_result = run_main_gc(
-        &*interface,
-        runtime_module,
-        b"",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let gas_calibration_result = run_main_gc(&*interface, runtime_module, b"", 100_000, &config)?;
     // println!("gas_calibration_result: {:?}", gas_calibration_result);
 
     // Note:
@@ -184,22 +157,9 @@ fn test_basic_abi_call_loop() -> Result<()> {
         "/wasm/gc_abi_call_for.wasm"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )?;
-    let gas_calibration_result = run_main_gc(
-        &interface,
-        runtime_module,
-        b"",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config)?;
+    let gas_calibration_result = run_main_gc(&interface, runtime_module, b"", 100_000, &config)?;
     assert_eq!(
         gas_calibration_result.counters.len(),
         2 + 5 + OPERATOR_CARDINALITY
@@ -227,22 +187,9 @@ fn test_basic_abi_call_loop_wasmv1() -> Result<()> {
         "/wasm/test_gc_abi_call_for.wasm_add"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )?;
-    let gas_calibration_result = run_main_gc(
-        &interface,
-        runtime_module,
-        b"",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config)?;
+    let gas_calibration_result = run_main_gc(&interface, runtime_module, b"", 100_000, &config)?;
     assert_eq!(
         gas_calibration_result.counters.len(),
         2 + 2 + OPERATOR_CARDINALITY
@@ -272,22 +219,9 @@ fn test_basic_op() -> Result<()> {
         "/wasm/gc_basic_op.wasm"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )?;
-    let gas_calibration_result = run_main_gc(
-        &interface,
-        runtime_module,
-        b"",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config)?;
+    let gas_calibration_result = run_main_gc(&interface, runtime_module, b"", 100_000, &config)?;
     // 1 for env.abort + 4 env.abort parameters
     assert_eq!(
         gas_calibration_result.counters.len(),
@@ -336,22 +270,9 @@ fn test_basic_op_wasmv1() -> Result<()> {
         "/wasm/test_gc_basic_op.wasm_add"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )?;
-    let gas_calibration_result = run_main_gc(
-        &interface,
-        runtime_module,
-        b"",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config)?;
+    let gas_calibration_result = run_main_gc(&interface, runtime_module, b"", 100_000, &config)?;
     // 1 for env.abort + 1 env.abort parameters
     assert_eq!(
         gas_calibration_result.counters.len(),
@@ -406,22 +327,10 @@ fn test_basic_abi_call_param_size() -> Result<()> {
         "/wasm/gc_abi_call_param_size.wasm"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )?;
-    let gas_calibration_result = run_main_gc(
-        &interface,
-        runtime_module,
-        b"9876543",
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )?;
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config)?;
+    let gas_calibration_result =
+        run_main_gc(&interface, runtime_module, b"9876543", 100_000, &config)?;
     // println!("gas_calibration_result: {:?}", gas_calibration_result);
 
     // Note:
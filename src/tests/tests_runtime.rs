@@ -1,19 +1,126 @@
 use crate::as_execution::{ASContext, ASModule};
+use crate::middlewares::operator::OPERATOR_VARIANTS;
 use crate::tests::TestInterface;
 use crate::{
-    run_function, run_main,
+    run_function, run_main, run_main_detailed,
     types::{GasCosts, Interface},
-    RuntimeModule,
+    Recording, RecordingInterface, ReplayError, ReplayInterface, RuntimeModule, TrapCode, VMError,
 };
-use crate::{Compiler, CondomLimits};
+use crate::{Compiler, ExecutionConfig};
+use massa_proto_rs::massa::abi::v1::{CallRequest, SetDsValueRequest};
+use massa_proto_rs::massa::model::v1::NativeAmount;
+use prost::Message;
 use rand::Rng;
 use serial_test::serial;
+use std::time::Duration;
 use wasmer::Store;
 use wasmer::WasmPtr;
 
+#[cfg(feature = "testing")]
+use crate::{
+    testing::{CallFrame, Simulator},
+    ExecutionFailure, Response,
+};
 #[cfg(feature = "execution-trace")]
 use crate::{AbiTrace, AbiTraceType, AbiTraceValue};
 
+/// WasmV1 module whose `main` calls the given ABIs, in order, with the given
+/// encoded requests, then traps if `trap` is set. `main` returns the response
+/// of the last ABI call.
+fn wasmv1_calls_module(calls: &[(&str, Vec<u8>)], trap: bool) -> Vec<u8> {
+    let mut imports = String::new();
+    let mut data = String::new();
+    let mut body = String::new();
+    let mut offset = 1024;
+    for (i, (abi, request)) in calls.iter().enumerate() {
+        if !calls[..i].iter().any(|(imported, _)| imported == abi) {
+            imports +=
+                &format!(r#"(import "massa" "{abi}" (func ${abi} (param i32) (result i32)))"#);
+        }
+        let mut arg = (request.len() as u32).to_le_bytes().to_vec();
+        arg.extend(request);
+        let bytes: String = arg.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+        data += &format!(r#"(data (i32.const {offset}) "{bytes}")"#);
+        if i > 0 {
+            body += "drop ";
+        }
+        body += &format!("i32.const {offset} call ${abi} ");
+        offset += arg.len();
+    }
+    if calls.is_empty() {
+        // empty buffer, at the start of the memory
+        body += "i32.const 0 ";
+    }
+    if trap {
+        body += "unreachable ";
+    }
+    // the buffers allocated by the runtime go after the requests
+    let wat = format!(
+        r#"(module
+            {imports}
+            (memory $0 1)
+            (export "memory" (memory $0))
+            {data}
+            (global $next (mut i32) (i32.const {offset}))
+            (func (export "__alloc") (param $len i32) (result i32)
+                global.get $next
+                (global.set $next (i32.add (global.get $next) (local.get $len))))
+            (func (export "main") (param i32) (result i32)
+                {body}))"#
+    );
+    let mut module = vec![1u8];
+    module.extend_from_slice(&wasmer::wat2wasm(wat.as_bytes()).unwrap());
+    module
+}
+
+/// Call of `abi_set_ds_value` writing `key` in the datastore of the current
+/// address
+fn set_ds_value_call(key: &[u8]) -> (&'static str, Vec<u8>) {
+    let request = SetDsValueRequest {
+        key: key.to_vec(),
+        value: b"value".to_vec(),
+        ..Default::default()
+    };
+    ("abi_set_ds_value", request.encode_to_vec())
+}
+
+/// Call of `abi_call` to `main` of the contract at `address`, sending it
+/// `raw_coins` raw coins
+fn call_main_call(address: &str, raw_coins: u64) -> (&'static str, Vec<u8>) {
+    let request = CallRequest {
+        target_sc_address: address.to_string(),
+        target_function_name: "main".to_string(),
+        function_arg: Vec::new(),
+        call_coins: Some(NativeAmount {
+            mantissa: raw_coins,
+            scale: 9,
+        }),
+    };
+    ("abi_call", request.encode_to_vec())
+}
+
+/// Run `main` of the contract deployed at `address` without the revert of
+/// `Simulator::call`, only the checkpoints of the runtime revert the state
+#[cfg(feature = "testing")]
+fn run_deployed_main(
+    simulator: &Simulator,
+    address: &str,
+    limit: u64,
+) -> Result<Response, ExecutionFailure> {
+    let bytecode = simulator.state().ledger[address].bytecode.clone();
+    simulator.state().call_stack = vec![CallFrame::new(address, 0)];
+    let interface = simulator.interface();
+    let module = interface.get_module(&bytecode, limit).unwrap();
+    run_main_detailed(interface, module, limit, &ExecutionConfig::default())
+}
+
+#[cfg(feature = "testing")]
+fn has_key(simulator: &Simulator, address: &str, key: &[u8]) -> bool {
+    simulator.state().ledger[address]
+        .datastore
+        .contains_key(key)
+}
+
 #[test]
 #[serial]
 #[ignore]
@@ -24,24 +131,10 @@ fn test_exhaustive_smart_contract() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_exhaustive_smart_contract.wasm_add"
     ));
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let config = ExecutionConfig::default();
+
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
@@ -53,24 +146,10 @@ fn test_native_time_arithmetic_abis() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_native_time_arithmetic.wasm_add"
     ));
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let config = ExecutionConfig::default();
+
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
@@ -82,24 +161,10 @@ fn test_structs_check_and_version_abis() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_structs_check_and_version.wasm_add"
     ));
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let config = ExecutionConfig::default();
+
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
@@ -111,24 +176,10 @@ fn test_datastore_abis() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_datastore.wasm_add"
     ));
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let config = ExecutionConfig::default();
+
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
@@ -140,24 +191,10 @@ fn test_ledger_op_keys_abis() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_ledger_op_keys.wasm_add"
     ));
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let config = ExecutionConfig::default();
+
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
@@ -170,23 +207,9 @@ fn test_metering_safety() {
         "/wasm/metering_override.wasm"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    let resp = run_main(
-        &interface,
-        runtime_module,
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config).unwrap();
+    let resp = run_main(&interface, runtime_module, 100_000, &config).unwrap();
     assert_ne!(resp.remaining_gas, 42);
 }
 
@@ -200,23 +223,9 @@ fn test_instantiation_safety() {
         "/wasm/start_func_abi_call.wasm"
     ));
 
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
-    let runtime_module = RuntimeModule::new(
-        bytecode,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    let error = run_main(
-        &interface,
-        runtime_module,
-        100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )
-    .unwrap_err();
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config).unwrap();
+    let error = run_main(&interface, runtime_module, 100_000, &config).unwrap_err();
     let expected_error = "ABI calls are not available during instantiation";
     assert!(error.to_string().contains(expected_error));
 }
@@ -225,26 +234,12 @@ fn test_instantiation_safety() {
 #[serial]
 /// Test basic main-only SC execution
 fn test_run_main() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&*interface, runtime_module, 100_000, &config).unwrap();
 }
 
 #[cfg(feature = "execution-trace")]
@@ -252,37 +247,32 @@ fn test_run_main() {
 #[serial]
 /// Test basic main-only SC execution
 fn test_run_main_get_execution_traces() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    let resp = run_main(
-        &*interface,
-        runtime_module,
-        100_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let resp = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
 
     assert_eq!(resp.trace.is_empty(), false);
+    let trace = &resp.trace[0];
     assert_eq!(
         resp.trace,
         vec![AbiTrace {
             name: "assembly_script_generate_event".to_string(),
             params: vec![("event", "hello world!".to_string()).into()],
             return_value: AbiTraceType::None,
-            sub_calls: None
+            sub_calls: None,
+            gas_before: trace.gas_before,
+            gas_after: trace.gas_after,
+            duration_ns: trace.duration_ns,
+            error: None,
+            depth: 1,
         }]
-    )
+    );
+    // the ABI call is charged
+    assert!(trace.gas_before > trace.gas_after);
+    assert!(trace.gas_before <= 100_000);
 }
 
 #[test]
@@ -298,8 +288,7 @@ fn test_run_register_wasmv1() {
     // ));
 
     // let runtime_module =
-    //     RuntimeModule::new(module, gas_costs.clone(),
-    // Compiler::SP, condom_limits.clone()))?;.unwrap();
+    //     RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     // match runtime_module.clone() {
     //     RuntimeModule::ASModule(_) => {
@@ -334,7 +323,7 @@ fn test_run_register_wasmv1() {
 //     ));
 
 //     let runtime_module =
-//         RuntimeModule::new(module, gas_costs.clone(), Compiler::SP, condom_limits.clone())?;
+//         RuntimeModule::new(module, Compiler::SP, &config)?;
 //             .unwrap();
 
 //     match runtime_module.clone() {
@@ -345,28 +334,21 @@ fn test_run_register_wasmv1() {
 //             println!("Module type WasmV1Module");
 //         }
 //     }
-//     run_main(&*interface, runtime_module, 100_000_000, gas_costs, condom_limits).unwrap();
+//     run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
 // }
 
 #[test]
 #[serial]
 /// Test test_get_current_period_and_thread
 fn test_get_current_period_and_thread_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_period_thread.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -376,35 +358,21 @@ fn test_get_current_period_and_thread_wasmv1_as() {
             println!("Module type WasmV1Module");
         }
     }
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
 #[serial]
 /// Test test_native_hash
 fn test_native_hash_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_hash.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -414,35 +382,21 @@ fn test_native_hash_wasmv1_as() {
             println!("Module type WasmV1Module");
         }
     }
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
 #[serial]
 /// This test call the main function of a SC that calls generate_event abi
 fn test_generate_event_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_generate_event.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -452,35 +406,21 @@ fn test_generate_event_wasmv1_as() {
             println!("Module type WasmV1Module");
         }
     }
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
 #[serial]
 /// This test arithmetic operations on native amount
 fn test_native_amount_arithmetic_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_native_amount_arithmetic.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -490,35 +430,53 @@ fn test_native_amount_arithmetic_wasmv1_as() {
             println!("Module type WasmV1Module");
         }
     }
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
+}
+
+#[test]
+#[serial]
+/// Ensure that WasmV1 ABI argument and return buffers are charged per byte
+fn test_abi_buffer_size_cost_wasmv1_as() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let module = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/wasm/test_native_amount_arithmetic.wasm_add"
+    ));
+
+    let run = |gas_costs: GasCosts| {
+        let config = ExecutionConfig::builder()
+            .gas_costs(gas_costs)
+            .build()
+            .unwrap();
+        let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+        run_main(&*interface, runtime_module, 100_000_000, &config).unwrap()
+    };
+
+    let flat = run(GasCosts::default());
+
+    let mut gas_costs = GasCosts::default();
+    gas_costs.default_abi_read_byte_cost = 10;
+    let with_read_cost = run(gas_costs);
+    assert!(with_read_cost.remaining_gas < flat.remaining_gas);
+
+    let mut gas_costs = GasCosts::default();
+    gas_costs.default_abi_write_byte_cost = 10;
+    let with_write_cost = run(gas_costs);
+    assert!(with_write_cost.remaining_gas < flat.remaining_gas);
 }
 
 #[test]
 #[serial]
 /// This test call the main function of a SC that will abort
 fn test_abort_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_abort.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -529,13 +487,7 @@ fn test_abort_wasmv1_as() {
         }
     }
 
-    let res = run_main(
-        &*interface,
-        runtime_module,
-        100_000,
-        gas_costs,
-        condom_limits,
-    );
+    let res = run_main(&*interface, runtime_module, 100_000, &config);
 
     match res {
         Err(e) if e.to_string().contains("abort test message") => {
@@ -556,21 +508,14 @@ fn test_abort_wasmv1_as() {
 #[serial]
 /// This test call the main function of a SC that will abort
 fn test_assert_in_release_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_assert_in_release.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -581,13 +526,7 @@ fn test_assert_in_release_wasmv1_as() {
         }
     }
 
-    let res = run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    );
+    let res = run_main(&*interface, runtime_module, 100_000_000, &config);
 
     match res {
         Err(e) if e.to_string().contains("expected assert") => {
@@ -608,21 +547,14 @@ fn test_assert_in_release_wasmv1_as() {
 #[serial]
 /// This test call the main function of a SC that calls transfer_coins abi
 fn test_transfer_coins_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_transfer_coins.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -633,14 +565,7 @@ fn test_transfer_coins_wasmv1_as() {
         }
     }
 
-    let _resp = run_main(
-        &*interface,
-        runtime_module,
-        100_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let _resp = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
 
     #[cfg(feature = "execution-trace")]
     {
@@ -664,21 +589,14 @@ fn test_transfer_coins_wasmv1_as() {
 #[serial]
 /// This test call the main function of a SC that calls bs58 encode/decode abi
 fn test_bs58_to_from_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_bs58_to_from.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -688,35 +606,21 @@ fn test_bs58_to_from_wasmv1_as() {
             println!("Module type WasmV1Module");
         }
     }
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
 #[serial]
 /// This test call the main function of a SC that calls comparisons abis
 fn test_compare_wasmv1_as() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/test_compare.wasm_add"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
     match runtime_module.clone() {
         RuntimeModule::ASModule(_) => {
@@ -726,42 +630,19 @@ fn test_compare_wasmv1_as() {
             println!("Module type WasmV1Module");
         }
     }
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    run_main(&*interface, runtime_module, 100_000_000, &config).unwrap();
 }
 
 #[test]
 #[serial]
 /// Test basic function-only SC execution
 fn test_run_function() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_func.wasm"));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_function(
-        &*interface,
-        runtime_module,
-        "ping",
-        b"",
-        100_000,
-        gas_costs,
-        condom_limits,
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_function(&*interface, runtime_module, "ping", b"", 100_000, &config).unwrap();
 }
 
 // NOTE: this test is outdated as module are now pre-compiled with max_instance_cost
@@ -775,7 +656,7 @@ fn test_run_function() {
 //     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
 
 //     // Test giving not enough gas to create the instance
-//     let runtime_module = RuntimeModule::new(module, 100, gas_costs.clone(), Compiler::SP, condom_limits.clone()).unwrap();
+//     let runtime_module = RuntimeModule::new(module, 100, Compiler::SP, &config).unwrap();
 //     let error = run_main(&*interface, runtime_module, 100_000, gas_costs.clone())
 //         .unwrap_err()
 //         .to_string();
@@ -786,7 +667,7 @@ fn test_run_function() {
 
 //     // Test giving enough gas to create the instance but not enough for the VM
 //     let runtime_module =
-//         RuntimeModule::new(module, 100_000, gas_costs.clone(), Compiler::SP, condom_limits.clone()).unwrap();
+//         RuntimeModule::new(module, 100_000, Compiler::SP, &config).unwrap();
 //     let error = run_main(&*interface, runtime_module, 100, gas_costs)
 //         .unwrap_err()
 //         .to_string();
@@ -797,25 +678,530 @@ fn test_run_function() {
 #[serial]
 /// Test that a no-main SC executed through `run_main` fails as expected
 fn test_run_main_without_main() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/no_main.wasm"));
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&*interface, runtime_module, 100_000, &config)
+        .expect_err("An error should spawn here");
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+#[serial]
+/// Ensure that the gas profile attributes the gas to the functions and ABIs
+fn test_run_main_gas_profile() {
+    let config = ExecutionConfig::default();
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
+
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let resp = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
+
+    assert_eq!(resp.profile.name, "main");
+    assert!(resp.profile.total_gas() > 0);
+    assert!(resp.profile.total_gas() <= 100_000 - resp.remaining_gas);
+    let stacks = resp.profile.to_collapsed_stacks();
+    assert!(
+        stacks
+            .lines()
+            .any(|line| line.starts_with("main;")
+                && line.contains(";assembly_script_generate_event "))
+    );
+}
+
+#[test]
+#[serial]
+/// Ensure that a call exceeding its time budget is interrupted at its next
+/// ABI call
+fn test_execution_timeout() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+
+    let config = config
+        .to_builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap();
+    run_main(&*interface, runtime_module.clone(), 100_000, &config).unwrap();
+
+    let config = config
+        .to_builder()
+        .timeout(Duration::from_nanos(1))
+        .build()
+        .unwrap();
+    let error = run_main(&*interface, runtime_module, 100_000, &config).unwrap_err();
+    assert!(matches!(
+        error,
+        VMError::Timeout { function, budget, .. }
+            if function == "main" && budget == Duration::from_nanos(1)
+    ));
+}
+
+#[test]
+#[serial]
+/// Ensure that the timeout interrupts the code that does not call any ABI,
+/// like a loop running after an ABI call, in both kinds of modules
+fn test_execution_timeout_in_wasm_loop() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let budget = Duration::from_millis(100);
+    let config = ExecutionConfig::default()
+        .to_builder()
+        .timeout(budget)
+        .build()
+        .unwrap();
+    // enough gas for the loops to run far longer than the time budget
+    let limit = 1_000_000_000_000_000;
+
+    let as_module = wasmer::wat2wasm(
+        br#"(module
+            (import "massa" "assembly_script_get_time" (func $get_time (result i64)))
+            (memory $0 1)
+            (export "memory" (memory $0))
+            (func (export "main") (result i32)
+                call $get_time
+                drop
+                (loop $spin (br $spin))
+                i32.const 0))"#,
     )
     .unwrap();
-    run_main(
-        &*interface,
-        runtime_module,
-        100_000,
-        gas_costs,
-        condom_limits,
+    let mut wasmv1_module = vec![1u8];
+    wasmv1_module.extend_from_slice(
+        &wasmer::wat2wasm(
+            br#"(module
+                (memory $0 1)
+                (export "memory" (memory $0))
+                (func (export "__alloc") (param i32) (result i32)
+                    i32.const 0)
+                (func $spin
+                    (loop $spin (br $spin)))
+                (func (export "main") (param i32) (result i32)
+                    call $spin
+                    i32.const 0))"#,
+        )
+        .unwrap(),
+    );
+
+    for module in [&as_module[..], &wasmv1_module[..]] {
+        let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+        let failure = run_main_detailed(&*interface, runtime_module, limit, &config).unwrap_err();
+        assert!(matches!(
+            &failure.error,
+            VMError::Timeout { function, budget: b, .. } if function == "main" && *b == budget
+        ));
+        let gas = failure.gas_usage();
+        assert!(gas.gas_used > gas.init_gas_cost);
+    }
+}
+
+#[test]
+#[serial]
+/// Ensure that a failed execution reports where it failed, the gas it
+/// consumed and the ABI calls it made until the failure
+fn test_execution_failure_details() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
+    let config = ExecutionConfig::default()
+        .to_builder()
+        .timeout(Duration::from_nanos(1))
+        .build()
+        .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+
+    let failure = run_main_detailed(&*interface, runtime_module, 100_000, &config).unwrap_err();
+    assert!(matches!(failure.error, VMError::Timeout { .. }));
+    assert_eq!(failure.function, "main");
+    assert_eq!(failure.depth, 1);
+    let gas = failure.gas_usage();
+    assert!(gas.gas_used > gas.init_gas_cost);
+
+    // the ABI call interrupted by the timeout is the last traced one
+    #[cfg(feature = "execution-trace")]
+    {
+        let trace = failure.trace.last().unwrap();
+        assert_eq!(trace.name, "assembly_script_generate_event");
+        assert_eq!(trace.depth, 1);
+        assert_eq!(trace.error, Some(failure.error.to_string()));
+    }
+}
+
+#[test]
+#[serial]
+#[cfg(feature = "execution-trace")]
+/// Ensure that the ABI calls of WasmV1 modules are traced with their encoded
+/// request and response
+fn test_wasmv1_abi_trace() {
+    use massa_proto_rs::massa::abi::v1::{abi_response, resp_result, AbiResponse, RespResult};
+
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let request = SetDsValueRequest {
+        key: b"key".to_vec(),
+        value: b"value".to_vec(),
+        ..Default::default()
+    };
+    let module = wasmv1_calls_module(&[("abi_set_ds_value", request.encode_to_vec())], false);
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(&module, Compiler::SP, &config).unwrap();
+
+    let response = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
+    assert_eq!(response.trace.len(), 1);
+    let trace = &response.trace[0];
+    assert_eq!(trace.name, "abi_set_ds_value");
+    assert!(trace.is_success());
+    assert_eq!(
+        trace.params,
+        vec![AbiTraceValue::from(("request", request.encode_to_vec()))]
+    );
+    let AbiTraceType::ByteArray(return_value) = &trace.return_value else {
+        panic!(
+            "expected an encoded response, got: {:?}",
+            trace.return_value
+        );
+    };
+    assert!(matches!(
+        AbiResponse::decode(&return_value[..]).unwrap().resp,
+        Some(abi_response::Resp::Res(RespResult {
+            res: Some(resp_result::Res::SetDsValueResult(_))
+        }))
+    ));
+}
+
+#[test]
+#[serial]
+/// Ensure that read-only calls are rejected by the runtime when they use a
+/// state-mutating ABI, and run as usual otherwise
+fn test_read_only_execution() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let config = ExecutionConfig::default()
+        .to_builder()
+        .read_only(true)
+        .build()
+        .unwrap();
+    let rejected_abi = |module: &[u8]| {
+        let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+        let error = run_main(&*interface, runtime_module, 100_000, &config).unwrap_err();
+        assert_eq!(error.kind(), "ReadOnly");
+        let VMError::ReadOnly { abi, gas } = error else {
+            unreachable!()
+        };
+        assert!(gas.gas_used > 0);
+        abi
+    };
+
+    // events do not modify the state
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&*interface, runtime_module, 100_000, &config).unwrap();
+
+    let module = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/wasm/test_transfer_coins.wasm_add"
+    ));
+    assert_eq!(rejected_abi(module), "abi_transfer_coins");
+    let module = wasmv1_calls_module(&[set_ds_value_call(b"key")], false);
+    assert_eq!(rejected_abi(&module), "abi_set_ds_value");
+    // a call is rejected when it sends coins, before reaching the interface
+    let module = wasmv1_calls_module(&[call_main_call("AS12", 1)], false);
+    assert_eq!(rejected_abi(&module), "abi_call");
+
+    let module = wasmer::wat2wasm(
+        br#"(module
+            (import "massa" "assembly_script_set_data"
+                (func $set_data (param i32 i32)))
+            (memory $0 1)
+            (export "memory" (memory $0))
+            (data (i32.const 100) "\03\00\00\00key")
+            (func (export "main") (result i32)
+                (call $set_data (i32.const 104) (i32.const 104))
+                i32.const 0))"#,
     )
-    .expect_err("An error should spawn here");
+    .unwrap();
+    assert_eq!(rejected_abi(&module), "assembly_script_set_data");
+    let module = wasmer::wat2wasm(
+        br#"(module
+            (import "massa" "assembly_script_call"
+                (func $call (param i32 i32 i32 i64) (result i32)))
+            (memory $0 1)
+            (export "memory" (memory $0))
+            (func (export "main") (result i32)
+                (call $call (i32.const 0) (i32.const 0) (i32.const 0) (i64.const 1))))"#,
+    )
+    .unwrap();
+    assert_eq!(rejected_abi(&module), "assembly_script_call");
+
+    // the nested calls are read-only too
+    #[cfg(feature = "testing")]
+    {
+        let simulator = Simulator::new(config.clone()).unwrap();
+        let user = simulator.create_user(0);
+        let callee = simulator.deploy(&wasmv1_calls_module(&[set_ds_value_call(b"key")], false));
+        let caller = simulator.deploy(&wasmv1_calls_module(&[call_main_call(&callee, 0)], false));
+        let failure = simulator
+            .call(&user, &caller, "main", &[], 0, 100_000_000)
+            .unwrap_err();
+        assert!(matches!(
+            &failure.error,
+            VMError::ReadOnly { abi, .. } if abi == "abi_set_ds_value"
+        ));
+        assert_eq!(failure.depth, 2);
+    }
+}
+
+#[test]
+#[serial]
+#[cfg(feature = "testing")]
+/// Ensure that the changes made by a failed nested call are rolled back,
+/// whether the caller keeps running or fails with it
+fn test_nested_call_rollback() {
+    use massa_proto_rs::massa::abi::v1::LocalExecutionRequest;
+
+    let limit = 100_000_000;
+    let simulator = Simulator::new(ExecutionConfig::default()).unwrap();
+    let failing = wasmv1_calls_module(&[set_ds_value_call(b"failed")], true);
+
+    // the local execution fails, its caller keeps running
+    let local_execution = LocalExecutionRequest {
+        bytecode: failing.clone(),
+        target_function_name: "main".to_string(),
+        function_arg: Vec::new(),
+    };
+    let caller = simulator.deploy(&wasmv1_calls_module(
+        &[
+            ("abi_local_execution", local_execution.encode_to_vec()),
+            set_ds_value_call(b"after"),
+        ],
+        false,
+    ));
+    run_deployed_main(&simulator, &caller, limit).unwrap();
+    assert!(!has_key(&simulator, &caller, b"failed"));
+    assert!(has_key(&simulator, &caller, b"after"));
+    assert_eq!(simulator.interface().open_checkpoints(), 0);
+
+    // the callee fails, and its caller with it
+    let callee = simulator.deploy(&failing);
+    let caller = simulator.deploy(&wasmv1_calls_module(
+        &[set_ds_value_call(b"before"), call_main_call(&callee, 0)],
+        false,
+    ));
+    let failure = run_deployed_main(&simulator, &caller, limit).unwrap_err();
+    assert!(matches!(failure.error, VMError::Trap { .. }));
+    assert_eq!(failure.depth, 2);
+    assert!(!has_key(&simulator, &callee, b"failed"));
+    // the write of the caller is not part of the rolled back sub-execution
+    assert!(has_key(&simulator, &caller, b"before"));
+    assert_eq!(simulator.interface().open_checkpoints(), 0);
+}
+
+#[test]
+#[serial]
+#[cfg(feature = "testing")]
+/// Ensure that a try call reports the failure of its callee, AssemblyScript
+/// or WasmV1, charges the gas the callee used, or the whole gas given to it
+/// when the failure happened before the callee started, and rolls back the
+/// changes of the callee
+fn test_try_call_failures() {
+    use crate::wasmv1_execution::{TryCallRequest, TryCallResult};
+
+    let limit = 100_000_000;
+    let max_gas = 10_000_000;
+    let simulator = Simulator::new(ExecutionConfig::default()).unwrap();
+    // outcome of a try call to `function` of `callee`, and the gas used by
+    // the calling contract
+    let try_call = |callee: &str, function: &str| {
+        let request = TryCallRequest {
+            target_sc_address: callee.to_string(),
+            target_function_name: function.to_string(),
+            function_arg: Vec::new(),
+            call_coins: Some(NativeAmount {
+                mantissa: 0,
+                scale: 0,
+            }),
+            max_gas,
+        };
+        let caller = simulator.deploy(&wasmv1_calls_module(
+            &[
+                set_ds_value_call(b"before"),
+                ("abi_try_call", request.encode_to_vec()),
+            ],
+            false,
+        ));
+        let response = run_deployed_main(&simulator, &caller, limit).unwrap();
+        // the caller keeps its own changes
+        assert!(has_key(&simulator, &caller, b"before"));
+        assert_eq!(simulator.interface().open_checkpoints(), 0);
+        let result = TryCallResult::decode(&response.ret[..]).unwrap();
+        (result, limit - response.remaining_gas)
+    };
+
+    let as_callee = simulator.deploy(
+        &wasmer::wat2wasm(
+            br#"(module
+                (import "massa" "assembly_script_set_data"
+                    (func $set_data (param i32 i32)))
+                (memory $0 1)
+                (export "memory" (memory $0))
+                (data (i32.const 100) "\06\00\00\00failed")
+                (func (export "fail") (result i32)
+                    (call $set_data (i32.const 104) (i32.const 104))
+                    unreachable))"#,
+        )
+        .unwrap(),
+    );
+    let wasmv1_callee =
+        simulator.deploy(&wasmv1_calls_module(&[set_ds_value_call(b"failed")], true));
+    for (callee, function) in [(&as_callee, "fail"), (&wasmv1_callee, "main")] {
+        let (result, caller_gas) = try_call(callee, function);
+        let error = result.error.unwrap();
+        assert_eq!(error.kind, "Trap");
+        assert!(error.gas_used > 0 && error.gas_used < max_gas);
+        assert!(caller_gas > error.gas_used);
+        assert!(!has_key(&simulator, callee, b"failed"));
+    }
+
+    // the callee has no bytecode, it fails before starting
+    let (result, caller_gas) = try_call(&simulator.create_user(0), "main");
+    let error = result.error.unwrap();
+    assert_eq!(error.kind, "Interface");
+    assert_eq!(error.gas_used, max_gas);
+    assert!(caller_gas > max_gas);
+}
+
+#[test]
+#[serial]
+/// Ensure that an ABI failing with an error that is neither a trap nor a gas
+/// exhaustion keeps its message and still reports the gas consumed until then
+fn test_abi_failure_gas_usage() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let bytecode = wasmer::wat2wasm(
+        br#"(module
+            (import "massa" "assembly_script_transfer_coins"
+                (func $transfer_coins (param i32 i64)))
+            (memory $0 1)
+            (export "memory" (memory $0))
+            (func (export "main") (result i32)
+                i32.const 0
+                i64.const -1
+                call $transfer_coins
+                i32.const 0))"#,
+    )
+    .unwrap();
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(&bytecode, Compiler::SP, &config).unwrap();
+
+    let failure = run_main_detailed(&*interface, runtime_module, 100_000, &config).unwrap_err();
+    assert!(matches!(
+        &failure.error,
+        VMError::ExecutionError { error, .. } if error.contains("Negative raw amount.")
+    ));
+    let gas = failure.gas_usage();
+    let abi_cost = config.gas_costs().abi_costs["assembly_script_transfer_coins"];
+    assert!(gas.gas_used >= gas.init_gas_cost + abi_cost);
+}
+
+#[test]
+#[serial]
+/// Ensure that traps carry the wasm backtrace with the function names
+fn test_trap_backtrace() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let bytecode = wasmer::wat2wasm(
+        br#"(module
+            (memory $0 1)
+            (export "memory" (memory $0))
+            (func $transfer
+                unreachable)
+            (func (export "main") (result i32)
+                call $transfer
+                i32.const 0))"#,
+    )
+    .unwrap();
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(&bytecode, Compiler::SP, &config).unwrap();
+
+    let error = run_main(&*interface, runtime_module, 100_000, &config).unwrap_err();
+    let VMError::Trap {
+        function,
+        code,
+        backtrace,
+        ..
+    } = &error
+    else {
+        panic!("expected a trap, got: {}", error);
+    };
+    assert_eq!(function, "main");
+    assert_eq!(*code, TrapCode::UnreachableCodeReached);
+    assert!(backtrace.frames.len() >= 2);
+    assert_eq!(
+        backtrace.frames[0].function_name.as_deref(),
+        Some("transfer")
+    );
+    assert_eq!(backtrace.frames[0].location, None);
+    assert!(error.to_string().contains("in transfer at offset"));
+}
+
+#[test]
+#[serial]
+/// Replay a recorded execution and check that a different execution is detected
+fn test_record_and_replay() {
+    let interface = RecordingInterface::new(Box::new(TestInterface));
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/basic_main.wasm"));
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let response = run_main(&interface, runtime_module, 100_000, &config).unwrap();
+
+    let recording = interface.recording();
+    assert!(recording
+        .calls
+        .iter()
+        .any(|call| call.method == "generate_event"));
+    let path = std::env::temp_dir().join("test_record_and_replay.json");
+    recording.save(&path).unwrap();
+    let recording = Recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let replay = ReplayInterface::new(recording.clone(), config.clone());
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let replayed = run_main(&replay, runtime_module, 100_000, &config).unwrap();
+    replay.finish().unwrap();
+    assert_eq!(replayed.remaining_gas, response.remaining_gas);
+    assert_eq!(replayed.ret, response.ret);
+
+    // the recorded event is not the one generated by the execution
+    let mut altered = recording;
+    let call = altered
+        .calls
+        .iter_mut()
+        .find(|call| call.method == "generate_event")
+        .unwrap();
+    call.args = vec!["another event".into()];
+    let replay = ReplayInterface::new(altered, config.clone());
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    assert!(run_main(&replay, runtime_module, 100_000, &config).is_err());
+    assert!(matches!(
+        replay.finish(),
+        Err(ReplayError::ArgumentsMismatch { method, .. }) if method == "generate_event"
+    ));
+}
+
+#[test]
+#[serial]
+/// Ensure that the memory page limit can be changed per call and is reported
+/// along with the peak memory in the response
+fn test_memory_pages_per_call() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/empty_main.wasm"));
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+
+    let response = run_main(&*interface, runtime_module.clone(), 10_000_000, &config).unwrap();
+    assert_eq!(response.max_memory_pages, config.max_memory_pages());
+    assert!(response.peak_memory_pages > 0);
+    assert!(response.peak_memory_pages <= response.max_memory_pages);
+
+    let config = config.to_builder().max_memory_pages(128).build().unwrap();
+    let response = run_main(&*interface, runtime_module, 10_000_000, &config).unwrap();
+    assert_eq!(response.max_memory_pages, 128);
 }
 
 #[test]
@@ -826,25 +1212,16 @@ fn test_run_main_without_main() {
 /// This test ensure that this initial cost is correctly debited.
 fn test_run_empty_main() {
     let mut gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/empty_main.wasm"));
     gas_costs.launch_cost = 0;
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    let a = run_main(
-        &*interface,
-        runtime_module.clone(),
-        10_000_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )
-    .expect("Failed to run empty_main.wasm");
+    let config = ExecutionConfig::builder()
+        .gas_costs(gas_costs.clone())
+        .build()
+        .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let a = run_main(&*interface, runtime_module.clone(), 10_000_000, &config)
+        .expect("Failed to run empty_main.wasm");
     // Here we avoid hard-coding a value (that can change in future wasmer
     // release)
     assert!(a.remaining_gas > 0);
@@ -852,18 +1229,86 @@ fn test_run_empty_main() {
     let mut rng = rand::thread_rng();
     let cost = rng.gen_range(1..1_000_000);
     gas_costs.launch_cost = cost;
-    let b = run_main(
-        &*interface,
-        runtime_module,
-        10_000_000,
-        gas_costs,
-        condom_limits,
-    )
-    .expect("Failed to run empty_main.wasm");
+    let config = ExecutionConfig::builder()
+        .gas_costs(gas_costs)
+        .build()
+        .unwrap();
+    let b = run_main(&*interface, runtime_module, 10_000_000, &config)
+        .expect("Failed to run empty_main.wasm");
     // Between 2 calls, the metering cost should be the difference
     assert_eq!(a.remaining_gas - b.remaining_gas, cost);
 }
 
+#[test]
+#[serial]
+/// Ensure that per-operator gas costs are used by the metering middleware
+/// instead of the flat operator cost
+fn test_operator_cost_table() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/empty_main.wasm"));
+
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let a = run_main(&*interface, runtime_module, 10_000_000, &config)
+        .expect("Failed to run empty_main.wasm");
+
+    // Every operator costs twice the default operator cost
+    let mut gas_costs = GasCosts::default();
+    for op_name in OPERATOR_VARIANTS {
+        gas_costs
+            .operator_costs
+            .insert(op_name.to_string(), 2 * gas_costs.operator_cost);
+    }
+    let config = ExecutionConfig::builder()
+        .gas_costs(gas_costs)
+        .build()
+        .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    let b = run_main(&*interface, runtime_module, 10_000_000, &config)
+        .expect("Failed to run empty_main.wasm");
+
+    assert!(b.init_gas_cost > a.init_gas_cost);
+    assert!(b.remaining_gas < a.remaining_gas);
+}
+
+#[test]
+#[serial]
+/// Ensure that each operator is charged its own cost from the table
+fn test_operator_cost_table_per_operator() {
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+    let bytecode = wasmer::wat2wasm(
+        br#"(module
+            (memory $0 1)
+            (export "memory" (memory $0))
+            (func (export "main") (result i32)
+                i64.const 7
+                i64.const 2
+                i64.div_u
+                drop
+                i32.const 0))"#,
+    )
+    .unwrap();
+
+    let config = ExecutionConfig::default();
+    let runtime_module = RuntimeModule::new(&bytecode, Compiler::SP, &config).unwrap();
+    let a = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
+
+    let mut gas_costs = GasCosts::default();
+    gas_costs.operator_costs.insert("I64Const".to_string(), 5);
+    gas_costs.operator_costs.insert("I64DivU".to_string(), 100);
+    let config = ExecutionConfig::builder()
+        .gas_costs(gas_costs)
+        .build()
+        .unwrap();
+    let runtime_module = RuntimeModule::new(&bytecode, Compiler::SP, &config).unwrap();
+    let b = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
+
+    // 2 `i64.const` at 5 instead of 1, 1 `i64.div_u` at 100 instead of 1,
+    // the other operators keep the default cost
+    assert_eq!(b.init_gas_cost, a.init_gas_cost);
+    assert_eq!(a.remaining_gas - b.remaining_gas, 2 * 4 + 99);
+}
+
 #[test]
 #[serial]
 /// Even if our SC is empty there is still an initial and minimum metering cost,
@@ -878,7 +1323,7 @@ fn test_run_main_rust_wasmv1() {
     //     "/../massa-rust-sc-examples/target/wasm32-unknown-unknown/debug/
     // massa_rust_sc_deploy_sc.wasm_add")); gas_costs.launch_cost = 0;
     // let runtime_module =
-    //     RuntimeModule::new(module, gas_costs.clone(), Compiler::SP, condom_limits.clone())?;
+    //     RuntimeModule::new(module, Compiler::SP, &config)?;
     //         .unwrap();
 
     // let a = match run_main(
@@ -912,25 +1357,11 @@ fn test_run_main_rust_wasmv1() {
 /// * hasOpKey
 /// * getOpData
 fn test_op_fn() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/op_fn.wasm"));
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
-    run_main(
-        &*interface,
-        runtime_module,
-        10_000_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    )
-    .expect("Failed to run op_fn.wasm");
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    run_main(&*interface, runtime_module, 10_000_000, &config).expect("Failed to run op_fn.wasm");
 }
 
 /// Test `seed`, `Date.now`, `console.log` and `abort`
@@ -939,28 +1370,15 @@ fn test_op_fn() {
 #[test]
 #[serial]
 fn test_builtins() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/use_builtins.wasm"
     ));
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
     // let before = chrono::offset::Utc::now().timestamp_millis();
-    match run_main(
-        &*interface,
-        runtime_module,
-        10_000_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    ) {
+    match run_main(&*interface, runtime_module, 10_000_000, &config) {
         Err(e) => {
             let msg = e.to_string();
             // make sure the error was caused by a manual abort
@@ -979,35 +1397,71 @@ fn test_builtins() {
     }
 }
 
+#[test]
+#[serial]
+/// Test that guest aborts and missing functions are reported as typed errors
+fn test_structured_errors() {
+    let config = ExecutionConfig::default();
+    let interface: Box<dyn Interface> = Box::new(TestInterface);
+
+    let module = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/wasm/use_builtins.wasm"
+    ));
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    match run_main(&*interface, runtime_module, 10_000_000, &config) {
+        Err(VMError::GuestAbort {
+            message,
+            location,
+            gas,
+        }) => {
+            assert!(message.contains("Manual abort"));
+            assert!(location.file.ends_with("use_builtins.ts"));
+            assert!(gas.gas_used >= gas.init_gas_cost);
+            assert!(gas.gas_used <= 10_000_000);
+        }
+        res => panic!("Expected a guest abort, got: {:?}", res.map(|_| ())),
+    }
+
+    let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/empty_main.wasm"));
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
+    match run_function(
+        &*interface,
+        runtime_module,
+        "missing_function",
+        b"",
+        100_000,
+        &config,
+    ) {
+        Err(VMError::FunctionNotFound { function, gas }) => {
+            assert_eq!(function, "missing_function");
+            assert!(gas.gas_used <= 100_000);
+        }
+        res => panic!("Expected a missing function, got: {:?}", res.map(|_| ())),
+    }
+}
+
 #[test]
 #[serial]
 /// Test `assert` & `process.exit
 ///
 /// These are AS functions that we choose to handle in the VM
 fn test_builtin_assert_and_exit() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
     let module = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/use_builtin_assert.wasm"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
     match run_function(
         &*interface,
         runtime_module,
         "assert_with_msg",
         b"",
         100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
+        &config,
     ) {
         Err(e) => {
             assert!(e.to_string().contains("Result is not true!"))
@@ -1015,21 +1469,14 @@ fn test_builtin_assert_and_exit() {
         _ => panic!("test should return an error!"),
     }
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
     if run_function(
         &*interface,
         runtime_module,
         "assert_no_msg",
         b"",
         100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
+        &config,
     )
     .is_ok()
     {
@@ -1041,21 +1488,14 @@ fn test_builtin_assert_and_exit() {
         "/wasm/use_builtin_exit.wasm"
     ));
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
     match run_function(
         &*interface,
         runtime_module,
         "exit_no_code",
         b"",
         100_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
+        &config,
     ) {
         Err(e) => {
             assert!(e.to_string().contains("exit with code: 0"))
@@ -1063,21 +1503,14 @@ fn test_builtin_assert_and_exit() {
         _ => panic!("test should return an error!"),
     }
 
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
     match run_function(
         &*interface,
         runtime_module,
         "exit_with_code",
         b"",
         100_000,
-        gas_costs,
-        condom_limits,
+        &config,
     ) {
         Err(e) => {
             assert!(e.to_string().contains("exit with code: 2"))
@@ -1090,8 +1523,7 @@ fn test_builtin_assert_and_exit() {
 #[serial]
 /// Test WASM files compiled with unsupported builtin functions
 fn test_unsupported_builtins() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
     let interface: Box<dyn Interface> = Box::new(TestInterface);
 
     // Test for hrtime
@@ -1099,21 +1531,9 @@ fn test_unsupported_builtins() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/unsupported_builtin_hrtime.wasm"
     ));
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
-    match run_main(
-        &*interface,
-        runtime_module,
-        10_000_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    ) {
+    match run_main(&*interface, runtime_module, 10_000_000, &config) {
         Err(e) => {
             assert!(e
                 .to_string()
@@ -1127,21 +1547,9 @@ fn test_unsupported_builtins() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/unsupported_builtin_random_values.wasm"
     ));
-    let runtime_module = RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    )
-    .unwrap();
+    let runtime_module = RuntimeModule::new(module, Compiler::SP, &config).unwrap();
 
-    match run_main(
-        &*interface,
-        runtime_module,
-        10_000_000,
-        gas_costs.clone(),
-        condom_limits.clone(),
-    ) {
+    match run_main(&*interface, runtime_module, 10_000_000, &config) {
         Err(e) => {
             assert!(e
                 .to_string()
@@ -1159,16 +1567,10 @@ fn test_unsupported_builtins() {
 /// WAT files are mostly used in testing
 fn test_wat() {
     {
-        let gas_costs = GasCosts::default();
-        let condom_limits = CondomLimits::default();
+        let config = ExecutionConfig::default();
         let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wat"));
 
-        let runtime_module = RuntimeModule::new(
-            bytecode,
-            gas_costs.clone(),
-            Compiler::SP,
-            condom_limits.clone(),
-        );
+        let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config);
 
         match runtime_module {
             Ok(_) => panic!(".wat are not supported anymore"),
@@ -1178,26 +1580,12 @@ fn test_wat() {
         }
     }
     {
-        let gas_costs = GasCosts::default();
-        let condom_limits = CondomLimits::default();
+        let config = ExecutionConfig::default();
         let interface: Box<dyn Interface> = Box::new(TestInterface);
         let bytecode = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/dummy.wasm"));
 
-        let runtime_module = RuntimeModule::new(
-            bytecode,
-            gas_costs.clone(),
-            Compiler::SP,
-            condom_limits.clone(),
-        )
-        .unwrap();
-        let response = run_main(
-            &*interface,
-            runtime_module,
-            100_000,
-            gas_costs.clone(),
-            condom_limits.clone(),
-        )
-        .unwrap();
+        let runtime_module = RuntimeModule::new(bytecode, Compiler::SP, &config).unwrap();
+        let response = run_main(&*interface, runtime_module, 100_000, &config).unwrap();
 
         // Note: for now, exec main always return an empty vec
         let excepted: Vec<u8> = Vec::new();
@@ -1209,16 +1597,10 @@ fn test_wat() {
 #[serial]
 /// Test a WASM execution using features disabled in engine (simd & threads)
 fn test_features_disabled() {
-    let gas_costs = GasCosts::default();
-    let condom_limits = CondomLimits::default();
+    let config = ExecutionConfig::default();
 
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/simd.wasm"));
-    match RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    ) {
+    match RuntimeModule::new(module, Compiler::SP, &config) {
         Err(e) => {
             // println!("Error: {}", e);
             assert!(e
@@ -1229,12 +1611,7 @@ fn test_features_disabled() {
     }
 
     let module = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/wasm/threads.wasm"));
-    match RuntimeModule::new(
-        module,
-        gas_costs.clone(),
-        Compiler::SP,
-        condom_limits.clone(),
-    ) {
+    match RuntimeModule::new(module, Compiler::SP, &config) {
         Err(e) => {
             // println!("Error: {}", e);
             assert!(e.to_string().starts_with(
@@ -1255,20 +1632,14 @@ fn test_class_id() {
         env!("CARGO_MANIFEST_DIR"),
         "/wasm/return_basic.wasm"
     ));
-    let module = ASModule::new(
-        bytecode,
-        100_000,
-        GasCosts::default(),
-        Compiler::SP,
-        CondomLimits::default(),
-    )
-    .unwrap();
+    let module =
+        ASModule::new(bytecode, 100_000, Compiler::SP, &ExecutionConfig::default()).unwrap();
     let mut store = Store::new(module._engine);
     let mut context = ASContext::new(
         &*interface,
         module.binary_module,
-        GasCosts::default(),
-        CondomLimits::default(),
+        ExecutionConfig::default(),
+        None,
     );
     let (instance, _function_env, _) = context.create_vm_instance_and_init_env(&mut store).unwrap();
 
@@ -1356,20 +1727,14 @@ fn test_abort_does_not_panic() {
         "/wasm/abort_crash.wasm"
     ));
 
-    let module = ASModule::new(
-        bytecode,
-        100_000,
-        GasCosts::default(),
-        Compiler::SP,
-        CondomLimits::default(),
-    )
-    .unwrap();
+    let module =
+        ASModule::new(bytecode, 100_000, Compiler::SP, &ExecutionConfig::default()).unwrap();
     let mut store = Store::new(module._engine);
     let mut context = ASContext::new(
         &*interface,
         module.binary_module,
-        GasCosts::default(),
-        CondomLimits::default(),
+        ExecutionConfig::default(),
+        None,
     );
     let create_vm_instance_and_init_env = context.create_vm_instance_and_init_env(&mut store);
     match create_vm_instance_and_init_env {
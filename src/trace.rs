@@ -0,0 +1,499 @@
+//! Execution traces, built with the `execution-trace` feature.
+//!
+//! `AbiTrace` derives `Serialize` and `Deserialize`, `serde_json` gives its
+//! JSON form. This module adds a compact binary form, meant to store the
+//! traces of many operations:
+//!
+//! * a version byte, then the number of traces and the traces
+//! * integers are LEB128 varints, signed ones zigzag encoded first
+//! * strings and byte arrays are prefixed with their length
+//! * options are prefixed with a `0` or `1` byte
+//! * values are prefixed with the tag of their `AbiTraceType` variant
+//!
+//! It also records the gas, duration and outcome of the ABI calls.
+
+use crate::types::{AbiTrace, AbiTraceType, AbiTraceValue};
+use displaydoc::Display;
+use std::time::Instant;
+use thiserror::Error;
+
+/// Version of the binary form of the traces
+const TRACE_FORMAT_VERSION: u8 = 1;
+
+/// Maximum nesting of the sub-calls of the decoded traces, far above the
+/// depth of the nested calls allowed by the node, so that corrupted traces
+/// cannot overflow the stack of the decoder
+const MAX_TRACE_DEPTH: usize = 64;
+
+/// Minimum encoded size of a trace: the name and params lengths, the return
+/// value tag, the sub-calls and error flags and the four integers
+const MIN_TRACE_SIZE: usize = 9;
+
+/// Minimum encoded size of a param: its name length and value tag
+const MIN_PARAM_SIZE: usize = 2;
+
+/// Error decoding the binary form of the traces
+#[derive(Clone, Debug, Display, Error, PartialEq, Eq)]
+pub enum TraceDecodeError {
+    /// unsupported trace format version: {0}
+    UnsupportedVersion(u8),
+    /// unexpected end of the trace data
+    UnexpectedEnd,
+    /// invalid varint in the trace data
+    InvalidVarint,
+    /// invalid UTF-8 string in the trace data
+    InvalidString,
+    /// unknown value tag: {0}
+    UnknownTag(u8),
+    /// {0} trailing bytes after the traces
+    TrailingBytes(usize),
+    /// sub-calls nested deeper than {0} levels
+    TooDeep(usize),
+}
+
+/// ABI call in progress, completing the trace entries it produced
+pub(crate) struct AbiCall {
+    name: String,
+    gas_before: u64,
+    started: Instant,
+}
+
+impl AbiCall {
+    pub(crate) fn start(name: &str, gas_before: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            gas_before,
+            started: Instant::now(),
+        }
+    }
+
+    /// Record the gas, duration and outcome of the call in `trace`
+    pub(crate) fn finish(
+        &self,
+        trace: &mut AbiTrace,
+        gas_after: u64,
+        depth: u32,
+        error: Option<String>,
+    ) {
+        trace.gas_before = self.gas_before;
+        trace.gas_after = gas_after;
+        trace.duration_ns = self.started.elapsed().as_nanos() as u64;
+        trace.error = error;
+        trace.depth = depth;
+    }
+
+    /// Trace of a call that failed before recording one
+    pub(crate) fn failed(&self, gas_after: u64, depth: u32, error: String) -> AbiTrace {
+        let mut trace = AbiTrace {
+            name: self.name.clone(),
+            ..Default::default()
+        };
+        self.finish(&mut trace, gas_after, depth, Some(error));
+        trace
+    }
+}
+
+impl AbiTrace {
+    /// Binary form of a single trace, see `encode_traces`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode_traces(std::slice::from_ref(self))
+    }
+
+    /// Decode a single trace encoded with `AbiTrace::to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TraceDecodeError> {
+        let mut traces = decode_traces(bytes)?;
+        match traces.len() {
+            1 => Ok(traces.remove(0)),
+            _ => Err(TraceDecodeError::UnexpectedEnd),
+        }
+    }
+}
+
+/// Compact binary form of the traces of an execution
+pub fn encode_traces(traces: &[AbiTrace]) -> Vec<u8> {
+    let mut buffer = vec![TRACE_FORMAT_VERSION];
+    write_traces(&mut buffer, traces);
+    buffer
+}
+
+/// Decode traces encoded with `encode_traces`
+pub fn decode_traces(bytes: &[u8]) -> Result<Vec<AbiTrace>, TraceDecodeError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    match reader.byte()? {
+        TRACE_FORMAT_VERSION => (),
+        version => return Err(TraceDecodeError::UnsupportedVersion(version)),
+    }
+    let traces = reader.traces(0)?;
+    match bytes.len() - reader.offset {
+        0 => Ok(traces),
+        trailing => Err(TraceDecodeError::TrailingBytes(trailing)),
+    }
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_signed(buffer: &mut Vec<u8>, value: i64) {
+    write_varint(buffer, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+fn write_traces(buffer: &mut Vec<u8>, traces: &[AbiTrace]) {
+    write_varint(buffer, traces.len() as u64);
+    for trace in traces {
+        write_bytes(buffer, trace.name.as_bytes());
+        write_varint(buffer, trace.params.len() as u64);
+        for param in &trace.params {
+            write_bytes(buffer, param.name.as_bytes());
+            write_value(buffer, &param.value);
+        }
+        write_value(buffer, &trace.return_value);
+        match &trace.sub_calls {
+            Some(sub_calls) => {
+                buffer.push(1);
+                write_traces(buffer, sub_calls);
+            }
+            None => buffer.push(0),
+        }
+        write_varint(buffer, trace.gas_before);
+        write_varint(buffer, trace.gas_after);
+        write_varint(buffer, trace.duration_ns);
+        match &trace.error {
+            Some(error) => {
+                buffer.push(1);
+                write_bytes(buffer, error.as_bytes());
+            }
+            None => buffer.push(0),
+        }
+        write_varint(buffer, trace.depth as u64);
+    }
+}
+
+fn write_value(buffer: &mut Vec<u8>, value: &AbiTraceType) {
+    match value {
+        AbiTraceType::None => buffer.push(0),
+        AbiTraceType::Bool(v) => buffer.extend_from_slice(&[1, *v as u8]),
+        AbiTraceType::U8(v) => buffer.extend_from_slice(&[2, *v]),
+        AbiTraceType::I32(v) => {
+            buffer.push(3);
+            write_signed(buffer, *v as i64);
+        }
+        AbiTraceType::U32(v) => {
+            buffer.push(4);
+            write_varint(buffer, *v as u64);
+        }
+        AbiTraceType::I64(v) => {
+            buffer.push(5);
+            write_signed(buffer, *v);
+        }
+        AbiTraceType::U64(v) => {
+            buffer.push(6);
+            write_varint(buffer, *v);
+        }
+        AbiTraceType::F64(v) => {
+            buffer.push(7);
+            buffer.extend_from_slice(&v.to_le_bytes());
+        }
+        AbiTraceType::ByteArray(v) => {
+            buffer.push(8);
+            write_bytes(buffer, v);
+        }
+        AbiTraceType::ByteArrays(v) => {
+            buffer.push(9);
+            write_varint(buffer, v.len() as u64);
+            for bytes in v {
+                write_bytes(buffer, bytes);
+            }
+        }
+        AbiTraceType::String(v) => {
+            buffer.push(10);
+            write_bytes(buffer, v.as_bytes());
+        }
+        AbiTraceType::Strings(v) => {
+            buffer.push(11);
+            write_varint(buffer, v.len() as u64);
+            for string in v {
+                write_bytes(buffer, string.as_bytes());
+            }
+        }
+        AbiTraceType::Slot((period, thread)) => {
+            buffer.push(12);
+            write_varint(buffer, *period);
+            buffer.push(*thread);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], TraceDecodeError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(TraceDecodeError::UnexpectedEnd)?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, TraceDecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, TraceDecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(TraceDecodeError::InvalidVarint);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(TraceDecodeError::InvalidVarint)
+    }
+
+    fn signed(&mut self) -> Result<i64, TraceDecodeError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// Length of a sequence of items encoded on at least `min_size` bytes,
+    /// bounded by the remaining data so that a corrupted length cannot
+    /// trigger a huge allocation
+    fn len(&mut self, min_size: usize) -> Result<usize, TraceDecodeError> {
+        let len = self.varint()?;
+        if len > ((self.bytes.len() - self.offset) / min_size) as u64 {
+            return Err(TraceDecodeError::UnexpectedEnd);
+        }
+        Ok(len as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, TraceDecodeError> {
+        let len = self.len(1)?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, TraceDecodeError> {
+        String::from_utf8(self.bytes()?).map_err(|_| TraceDecodeError::InvalidString)
+    }
+
+    fn flag(&mut self) -> Result<bool, TraceDecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(TraceDecodeError::UnknownTag(tag)),
+        }
+    }
+
+    /// Traces nested in `depth` levels of sub-calls
+    fn traces(&mut self, depth: usize) -> Result<Vec<AbiTrace>, TraceDecodeError> {
+        if depth > MAX_TRACE_DEPTH {
+            return Err(TraceDecodeError::TooDeep(MAX_TRACE_DEPTH));
+        }
+        let len = self.len(MIN_TRACE_SIZE)?;
+        let mut traces = Vec::with_capacity(len);
+        for _ in 0..len {
+            let name = self.string()?;
+            let params_len = self.len(MIN_PARAM_SIZE)?;
+            let mut params = Vec::with_capacity(params_len);
+            for _ in 0..params_len {
+                params.push(AbiTraceValue {
+                    name: self.string()?,
+                    value: self.value()?,
+                });
+            }
+            let return_value = self.value()?;
+            let sub_calls = match self.flag()? {
+                true => Some(self.traces(depth + 1)?),
+                false => None,
+            };
+            let gas_before = self.varint()?;
+            let gas_after = self.varint()?;
+            let duration_ns = self.varint()?;
+            let error = match self.flag()? {
+                true => Some(self.string()?),
+                false => None,
+            };
+            let depth =
+                u32::try_from(self.varint()?).map_err(|_| TraceDecodeError::InvalidVarint)?;
+            traces.push(AbiTrace {
+                name,
+                params,
+                return_value,
+                sub_calls,
+                gas_before,
+                gas_after,
+                duration_ns,
+                error,
+                depth,
+            });
+        }
+        Ok(traces)
+    }
+
+    fn value(&mut self) -> Result<AbiTraceType, TraceDecodeError> {
+        Ok(match self.byte()? {
+            0 => AbiTraceType::None,
+            1 => AbiTraceType::Bool(self.flag()?),
+            2 => AbiTraceType::U8(self.byte()?),
+            3 => AbiTraceType::I32(
+                i32::try_from(self.signed()?).map_err(|_| TraceDecodeError::InvalidVarint)?,
+            ),
+            4 => AbiTraceType::U32(
+                u32::try_from(self.varint()?).map_err(|_| TraceDecodeError::InvalidVarint)?,
+            ),
+            5 => AbiTraceType::I64(self.signed()?),
+            6 => AbiTraceType::U64(self.varint()?),
+            7 => AbiTraceType::F64(f64::from_le_bytes(
+                self.take(8)?.try_into().expect("8 bytes were taken"),
+            )),
+            8 => AbiTraceType::ByteArray(self.bytes()?),
+            9 => {
+                let len = self.len(1)?;
+                let mut arrays = Vec::with_capacity(len);
+                for _ in 0..len {
+                    arrays.push(self.bytes()?);
+                }
+                AbiTraceType::ByteArrays(arrays)
+            }
+            10 => AbiTraceType::String(self.string()?),
+            11 => {
+                let len = self.len(1)?;
+                let mut strings = Vec::with_capacity(len);
+                for _ in 0..len {
+                    strings.push(self.string()?);
+                }
+                AbiTraceType::Strings(strings)
+            }
+            12 => AbiTraceType::Slot((self.varint()?, self.byte()?)),
+            tag => return Err(TraceDecodeError::UnknownTag(tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_traces() -> Vec<AbiTrace> {
+        vec![
+            AbiTrace {
+                name: "assembly_script_call".to_string(),
+                params: vec![
+                    ("address", "AS12".to_string()).into(),
+                    ("param", vec![0u8, 255]).into(),
+                    ("coins", u64::MAX).into(),
+                    ("offset", -42i32).into(),
+                    ("value", i64::MIN).into(),
+                    ("ratio", 0.5f64).into(),
+                    ("slot", (12u64, 3u8)).into(),
+                    ("keys", vec![vec![1u8], vec![]]).into(),
+                    ("names", vec!["a".to_string()]).into(),
+                    ("flag", true).into(),
+                    ("byte", 7u8).into(),
+                    ("count", 300u32).into(),
+                ],
+                return_value: AbiTraceType::None,
+                sub_calls: Some(vec![AbiTrace {
+                    name: "assembly_script_generate_event".to_string(),
+                    gas_before: 900,
+                    gas_after: 800,
+                    depth: 2,
+                    ..Default::default()
+                }]),
+                gas_before: 1_000,
+                gas_after: 700,
+                duration_ns: 12_345,
+                error: None,
+                depth: 1,
+            },
+            AbiTrace {
+                name: "abi_transfer_coins".to_string(),
+                gas_before: 700,
+                gas_after: 650,
+                error: Some("Not enough coins".to_string()),
+                depth: 1,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let traces = sample_traces();
+        let bytes = encode_traces(&traces);
+        assert_eq!(decode_traces(&bytes).unwrap(), traces);
+        assert!(!traces[1].is_success());
+
+        let single = traces[1].to_bytes();
+        assert_eq!(AbiTrace::from_bytes(&single).unwrap(), traces[1]);
+
+        assert_eq!(
+            decode_traces(&bytes[..bytes.len() - 1]),
+            Err(TraceDecodeError::UnexpectedEnd)
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_traces(&trailing),
+            Err(TraceDecodeError::TrailingBytes(1))
+        );
+        assert_eq!(
+            decode_traces(&[2]),
+            Err(TraceDecodeError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        // a trace whose only sub-call is nested `depth` levels deep
+        let nested = |depth: usize| {
+            let mut trace = AbiTrace::default();
+            for _ in 0..depth {
+                trace = AbiTrace {
+                    sub_calls: Some(vec![trace]),
+                    ..Default::default()
+                };
+            }
+            encode_traces(&[trace])
+        };
+        assert!(decode_traces(&nested(MAX_TRACE_DEPTH)).is_ok());
+        assert_eq!(
+            decode_traces(&nested(MAX_TRACE_DEPTH + 1)),
+            Err(TraceDecodeError::TooDeep(MAX_TRACE_DEPTH))
+        );
+
+        // announcing more traces than the remaining bytes can hold
+        let mut bytes = encode_traces(&[AbiTrace::default()]);
+        bytes[1] = 2;
+        assert_eq!(decode_traces(&bytes), Err(TraceDecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let traces = sample_traces();
+        let json = serde_json::to_string(&traces).unwrap();
+        assert!(json.contains("\"gasBefore\":1000"));
+        assert!(json.contains("\"error\":\"Not enough coins\""));
+        let decoded: Vec<AbiTrace> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, traces);
+    }
+}
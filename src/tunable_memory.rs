@@ -4,23 +4,29 @@ use wasmer::{
     MemoryType, Pages, TableType, Tunables,
 };
 
-/// Limitation of the memory
-/// A custom tunables that allows you to set a memory limit.
+/// Limitation of the memory and tables
+/// A custom tunables that allows you to set a memory and a table limit.
 ///
-/// After adjusting the memory limits, it delegates all other logic
+/// After adjusting the memory and table limits, it delegates all other logic
 /// to the base tunables.
 pub struct LimitingTunables<T: Tunables> {
     /// The maximum a linear memory is allowed to be (in Wasm pages, 64 KiB
     /// each). Since Wasmer ensures there is only none or one memory, this
     /// is practically an upper limit for the guest memory.
     limit: Pages,
+    /// The maximum number of elements of a table
+    table_limit: u32,
     /// The base implementation we delegate all the logic to
     base: T,
 }
 
 impl<T: Tunables> LimitingTunables<T> {
-    pub fn new(base: T, limit: Pages) -> Self {
-        Self { limit, base }
+    pub fn new(base: T, limit: Pages, table_limit: u32) -> Self {
+        Self {
+            limit,
+            table_limit,
+            base,
+        }
     }
 
     /// Takes an input memory type as requested by the guest and sets
@@ -56,6 +62,34 @@ impl<T: Tunables> LimitingTunables<T> {
 
         Ok(())
     }
+
+    /// Takes an input table type as requested by the guest and sets
+    /// a maximum if missing, like `adjust_memory`.
+    fn adjust_table(&self, requested: &TableType) -> TableType {
+        let mut adjusted = *requested;
+        if requested.maximum.is_none() {
+            adjusted.maximum = Some(self.table_limit);
+        }
+        adjusted
+    }
+
+    /// Ensures the a given table type does not exceed the table limit.
+    /// Call this after adjusting the table.
+    fn validate_table(&self, ty: &TableType) -> Result<(), String> {
+        if ty.minimum > self.table_limit {
+            return Err("Minimum exceeds the allowed table limit".to_string());
+        }
+
+        if let Some(max) = ty.maximum {
+            if max > self.table_limit {
+                return Err("Maximum exceeds the allowed table limit".to_string());
+            }
+        } else {
+            return Err("Maximum unset".to_string());
+        }
+
+        Ok(())
+    }
 }
 
 impl<T: Tunables> Tunables for LimitingTunables<T> {
@@ -71,7 +105,8 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
     ///
     /// Delegated to base.
     fn table_style(&self, table: &TableType) -> TableStyle {
-        self.base.table_style(table)
+        let adjusted = self.adjust_table(table);
+        self.base.table_style(&adjusted)
     }
 
     /// Create a memory owned by the host given a [`MemoryType`] and a
@@ -108,9 +143,12 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
     /// Create a table owned by the host given a [`TableType`] and a
     /// [`TableStyle`].
     ///
-    /// Delegated to base.
+    /// The requested table type is validated, adjusted to the limited and then
+    /// passed to base.
     fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<vm::VMTable, String> {
-        self.base.create_host_table(ty, style)
+        let adjusted = self.adjust_table(ty);
+        self.validate_table(&adjusted)?;
+        self.base.create_host_table(&adjusted, style)
     }
 
     /// Create a table owned by the VM given a [`TableType`] and a
@@ -123,6 +161,9 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
         style: &TableStyle,
         vm_definition_location: NonNull<VMTableDefinition>,
     ) -> Result<vm::VMTable, String> {
-        self.base.create_vm_table(ty, style, vm_definition_location)
+        let adjusted = self.adjust_table(ty);
+        self.validate_table(&adjusted)?;
+        self.base
+            .create_vm_table(&adjusted, style, vm_definition_location)
     }
 }
@@ -1,13 +1,16 @@
 use massa_proto_rs::massa::model::v1::{
     AddressCategory, ComparisonResult, NativeAmount, NativeTime, Slot,
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     path::PathBuf,
 };
 
 use crate::execution::RuntimeModule;
+use crate::middlewares::operator::{operator_field_str, OPERATOR_VARIANTS};
+use wasmer::wasmparser::Operator;
 
 use displaydoc::Display;
 use thiserror::Error;
@@ -52,9 +55,10 @@ macro_rules! bail {
 }
 
 #[cfg(feature = "execution-trace")]
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value", rename_all = "camelCase")]
 pub enum AbiTraceType {
+    #[default]
     None,
     Bool(bool),
     U8(u8),
@@ -147,7 +151,7 @@ impl From<(u64, u8)> for AbiTraceType {
 }
 
 #[cfg(feature = "execution-trace")]
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AbiTraceValue {
     pub name: String,
     #[serde(flatten)]
@@ -175,13 +179,35 @@ macro_rules! into_trace_value {
     }};
 }
 
+/// An ABI call of an execution, serializable with serde or in a compact binary
+/// form with `encode_traces`
 #[cfg(feature = "execution-trace")]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AbiTrace {
     pub name: String,
     pub params: Vec<AbiTraceValue>,
     pub return_value: AbiTraceType,
     pub sub_calls: Option<Vec<AbiTrace>>,
+    /// Remaining gas when the ABI was called
+    pub gas_before: u64,
+    /// Remaining gas when the ABI returned
+    pub gas_after: u64,
+    /// Wall-clock time spent in the ABI, in nanoseconds
+    pub duration_ns: u64,
+    /// Error raised by the ABI, `None` if it succeeded
+    pub error: Option<String>,
+    /// Depth of the call the ABI was called from, 1 for the call started by
+    /// the embedder
+    pub depth: u32,
+}
+
+#[cfg(feature = "execution-trace")]
+impl AbiTrace {
+    /// Whether the ABI call succeeded
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
 }
 
 /// That's what is returned when a module is executed correctly since the end
@@ -193,8 +219,15 @@ pub struct Response {
     pub remaining_gas: u64,
     /// number of gas required for the instance creation
     pub init_gas_cost: u64,
+    /// memory limit of the execution, in pages of 64 KiB
+    pub max_memory_pages: u32,
+    /// highest memory size reached by the instance, in pages of 64 KiB
+    pub peak_memory_pages: u32,
     #[cfg(feature = "execution-trace")]
     pub trace: Vec<AbiTrace>,
+    /// gas consumed by each function and ABI call, sub-executions included
+    #[cfg(feature = "profiling")]
+    pub profile: crate::ProfileNode,
 }
 
 pub trait InterfaceClone {
@@ -224,6 +257,48 @@ pub struct CondomLimits {
     pub max_globals_len: Option<usize>,
     pub max_custom_sections_len: Option<usize>,
     pub max_custom_sections_data_len: Option<usize>,
+    /// Maximum initial number of elements of a table
+    pub max_table_size: Option<usize>,
+    /// Maximum size in bytes of the values of all the globals
+    pub max_globals_data_len: Option<usize>,
+    /// Report every violated limit when rejecting a module instead of only
+    /// the first one
+    pub collect_all_violations: bool,
+}
+
+impl CondomLimits {
+    /// Feed every limit into `hasher`, used to fingerprint compiled modules.
+    /// `collect_all_violations` is left out as it only changes how a rejected
+    /// module is reported.
+    pub(crate) fn hash_into(&self, hasher: &mut Sha256) {
+        for limit in [
+            self.max_exports,
+            self.max_functions,
+            self.max_signature_len,
+            self.max_name_len,
+            self.max_imports_len,
+            self.max_table_initializers_len,
+            self.max_passive_elements_len,
+            self.max_passive_data_len,
+            self.max_global_initializers_len,
+            self.max_function_names_len,
+            self.max_tables_count,
+            self.max_memories_len,
+            self.max_globals_len,
+            self.max_custom_sections_len,
+            self.max_custom_sections_data_len,
+            self.max_table_size,
+            self.max_globals_data_len,
+        ] {
+            match limit {
+                Some(limit) => {
+                    hasher.update([1u8]);
+                    hasher.update((limit as u64).to_le_bytes());
+                }
+                None => hasher.update([0u8]),
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -231,6 +306,28 @@ pub struct GasCosts {
     pub(crate) abi_costs: HashMap<String, u64>,
     pub(crate) launch_cost: u64,
     pub(crate) operator_cost: u64,
+    /// Per-operator cost overrides, keyed by operator name (see
+    /// `operator_field_str`). Operators missing from this table are charged
+    /// `operator_cost`.
+    pub(crate) operator_costs: HashMap<String, u64>,
+    /// Cost per page of `memory.grow`
+    pub(crate) memory_grow_page_cost: u64,
+    /// Cost per byte of `memory.copy`
+    pub(crate) memory_copy_byte_cost: u64,
+    /// Cost per byte of `memory.fill`
+    pub(crate) memory_fill_byte_cost: u64,
+    /// Cost per byte of `memory.init`
+    pub(crate) memory_init_byte_cost: u64,
+    /// Per-byte cost of the argument buffer read by each WasmV1 ABI
+    pub(crate) abi_read_byte_costs: HashMap<String, u64>,
+    /// Per-byte cost of the return buffer written by each WasmV1 ABI
+    pub(crate) abi_write_byte_costs: HashMap<String, u64>,
+    /// Per-byte cost of an ABI argument buffer when the ABI has no specific
+    /// entry
+    pub(crate) default_abi_read_byte_cost: u64,
+    /// Per-byte cost of an ABI return buffer when the ABI has no specific
+    /// entry
+    pub(crate) default_abi_write_byte_cost: u64,
     pub cl_compilation_cost: u64,
     pub sp_compilation_cost: u64,
     pub max_instance_cost: u64,
@@ -240,6 +337,31 @@ impl GasCosts {
     pub fn new(abi_cost_file: PathBuf) -> Result<Self> {
         let abi_cost_file = std::fs::read_to_string(abi_cost_file)?;
         let mut abi_costs: HashMap<String, u64> = serde_json::from_str(&abi_cost_file)?;
+        // Memory operator costs are per page or per byte and must not be
+        // rounded like the other costs. They are optional, a missing entry
+        // disables the dynamic charging of the corresponding operator.
+        let memory_grow_page_cost = abi_costs.remove("memory_grow_page").unwrap_or(0);
+        let memory_copy_byte_cost = abi_costs.remove("memory_copy_byte").unwrap_or(0);
+        let memory_fill_byte_cost = abi_costs.remove("memory_fill_byte").unwrap_or(0);
+        let memory_init_byte_cost = abi_costs.remove("memory_init_byte").unwrap_or(0);
+        // Same for the per-byte costs of ABI buffers, given either as a
+        // default (`abi_read_byte`, `abi_write_byte`) or per ABI
+        // (`<abi_name>:read_byte`, `<abi_name>:write_byte`)
+        let default_abi_read_byte_cost = abi_costs.remove("abi_read_byte").unwrap_or(0);
+        let default_abi_write_byte_cost = abi_costs.remove("abi_write_byte").unwrap_or(0);
+        let mut abi_read_byte_costs = HashMap::new();
+        let mut abi_write_byte_costs = HashMap::new();
+        abi_costs.retain(|name, cost| {
+            if let Some(abi_name) = name.strip_suffix(":read_byte") {
+                abi_read_byte_costs.insert(abi_name.to_string(), *cost);
+                false
+            } else if let Some(abi_name) = name.strip_suffix(":write_byte") {
+                abi_write_byte_costs.insert(abi_name.to_string(), *cost);
+                false
+            } else {
+                true
+            }
+        });
         abi_costs.iter_mut().for_each(|(_, v)| {
             let unit_digit = *v % 10;
             if unit_digit > 5 {
@@ -274,9 +396,117 @@ impl GasCosts {
                 )
             })?,
             abi_costs,
+            operator_costs: HashMap::new(),
+            memory_grow_page_cost,
+            memory_copy_byte_cost,
+            memory_fill_byte_cost,
+            memory_init_byte_cost,
+            abi_read_byte_costs,
+            abi_write_byte_costs,
+            default_abi_read_byte_cost,
+            default_abi_write_byte_cost,
         })
     }
 
+    /// Same as `GasCosts::new` but also loads a per-operator cost table.
+    ///
+    /// The operator cost file is a JSON map from operator name (e.g.
+    /// `"I64DivU"`, `"CallIndirect"`) to its cost. The `Wasm:` prefix used in
+    /// gas calibration results is accepted so that calibration output can be
+    /// fed back directly. Operators absent from the file keep the default
+    /// operator cost.
+    pub fn new_with_operator_costs(
+        abi_cost_file: PathBuf,
+        operator_cost_file: PathBuf,
+    ) -> Result<Self> {
+        let mut gas_costs = Self::new(abi_cost_file)?;
+        let operator_cost_file = std::fs::read_to_string(operator_cost_file)?;
+        let operator_costs: HashMap<String, u64> = serde_json::from_str(&operator_cost_file)?;
+        for (name, cost) in operator_costs {
+            let name = name.strip_prefix("Wasm:").unwrap_or(&name);
+            if !OPERATOR_VARIANTS.contains(&name) {
+                return Err(InterfaceError::GasCalibrationError(format!(
+                    "unknown operator {} in operator gas cost file.",
+                    name
+                )));
+            }
+            gas_costs.operator_costs.insert(name.to_string(), cost);
+        }
+        Ok(gas_costs)
+    }
+
+    /// Whether some memory operators have a runtime cost depending on their
+    /// operand
+    pub(crate) fn has_memory_operator_costs(&self) -> bool {
+        self.memory_grow_page_cost > 0
+            || self.memory_copy_byte_cost > 0
+            || self.memory_fill_byte_cost > 0
+            || self.memory_init_byte_cost > 0
+    }
+
+    /// Feed every cost into `hasher` in a deterministic order, used to
+    /// fingerprint compiled modules
+    pub(crate) fn hash_into(&self, hasher: &mut Sha256) {
+        fn hash_map(hasher: &mut Sha256, map: &HashMap<String, u64>) {
+            let sorted: BTreeMap<&String, &u64> = map.iter().collect();
+            hasher.update((sorted.len() as u64).to_le_bytes());
+            for (name, cost) in sorted {
+                hasher.update((name.len() as u64).to_le_bytes());
+                hasher.update(name.as_bytes());
+                hasher.update(cost.to_le_bytes());
+            }
+        }
+
+        hash_map(hasher, &self.abi_costs);
+        hash_map(hasher, &self.operator_costs);
+        hash_map(hasher, &self.abi_read_byte_costs);
+        hash_map(hasher, &self.abi_write_byte_costs);
+        for cost in [
+            self.launch_cost,
+            self.operator_cost,
+            self.memory_grow_page_cost,
+            self.memory_copy_byte_cost,
+            self.memory_fill_byte_cost,
+            self.memory_init_byte_cost,
+            self.default_abi_read_byte_cost,
+            self.default_abi_write_byte_cost,
+            self.cl_compilation_cost,
+            self.sp_compilation_cost,
+            self.max_instance_cost,
+        ] {
+            hasher.update(cost.to_le_bytes());
+        }
+    }
+
+    /// Get the gas cost of reading an argument buffer of `len` bytes in the
+    /// given ABI
+    pub(crate) fn get_abi_read_cost(&self, abi_name: &str, len: usize) -> u64 {
+        self.abi_read_byte_costs
+            .get(abi_name)
+            .copied()
+            .unwrap_or(self.default_abi_read_byte_cost)
+            .saturating_mul(len as u64)
+    }
+
+    /// Get the gas cost of writing a return buffer of `len` bytes in the
+    /// given ABI
+    pub(crate) fn get_abi_write_cost(&self, abi_name: &str, len: usize) -> u64 {
+        self.abi_write_byte_costs
+            .get(abi_name)
+            .copied()
+            .unwrap_or(self.default_abi_write_byte_cost)
+            .saturating_mul(len as u64)
+    }
+
+    /// Get the gas cost of a wasm operator, falling back to the default
+    /// operator cost if it has no specific entry
+    pub(crate) fn get_operator_cost(&self, operator: &Operator) -> u64 {
+        self.operator_costs
+            .get(operator_field_str(operator))
+            .copied()
+            .unwrap_or(self.operator_cost)
+    }
+
     #[cfg(any(test, feature = "gas_calibration", feature = "testing"))]
     pub fn get_abi_costs(&self) -> &HashMap<String, u64> {
         &self.abi_costs
@@ -380,6 +610,7 @@ impl Default for GasCosts {
         );
         GasCosts::try_insert(&mut h, String::from("assembly_script_unsafe_random"), 11);
         GasCosts::try_insert(&mut h, String::from("assembly_script_call"), 11);
+        GasCosts::try_insert(&mut h, String::from("assembly_script_try_call"), 11);
         GasCosts::try_insert(&mut h, String::from("assembly_script_local_call"), 11);
         GasCosts::try_insert(&mut h, String::from("assembly_script_local_execution"), 11);
         GasCosts::try_insert(&mut h, String::from("assembly_script_get_bytecode"), 11);
@@ -434,6 +665,7 @@ impl Default for GasCosts {
         // abi v1
         GasCosts::try_insert(&mut h, String::from("abi_get_address_version"), 30);
         GasCosts::try_insert(&mut h, String::from("abi_call"), 30);
+        GasCosts::try_insert(&mut h, String::from("abi_try_call"), 30);
         GasCosts::try_insert(
             &mut h,
             String::from("abi_checked_scalar_div_native_time"),
@@ -515,6 +747,15 @@ impl Default for GasCosts {
         Self {
             abi_costs: h,
             operator_cost: 1,
+            operator_costs: HashMap::new(),
+            memory_grow_page_cost: 0,
+            memory_copy_byte_cost: 0,
+            memory_fill_byte_cost: 0,
+            memory_init_byte_cost: 0,
+            abi_read_byte_costs: HashMap::new(),
+            abi_write_byte_costs: HashMap::new(),
+            default_abi_read_byte_cost: 0,
+            default_abi_write_byte_cost: 0,
             launch_cost: 10_000,
             sp_compilation_cost: 314_000_000,
             cl_compilation_cost: 745_000_000,
@@ -934,6 +1175,23 @@ pub trait Interface: Send + Sync + InterfaceClone {
     fn compare_pub_key_wasmv1(&self, left: &str, right: &str) -> Result<ComparisonResult>;
 
     fn save_gas_remaining_before_subexecution(&self, gas_used_until: u64);
+
+    /// Save the state before a sub-execution started by a call ABI and return
+    /// the id of the checkpoint. Checkpoints nest like the sub-executions.
+    fn checkpoint(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    /// Revert the state to the checkpoint `id`: the sub-execution failed
+    fn rollback(&self, id: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// Keep the changes made since the checkpoint `id`: the sub-execution
+    /// succeeded
+    fn commit(&self, id: u64) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl dyn Interface {
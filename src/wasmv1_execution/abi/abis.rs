@@ -1,6 +1,7 @@
 use super::{
     super::{env::ABIEnv, WasmV1Error},
     handler::{handle_abi, handle_abi_raw},
+    messages::{TryCallError, TryCallRequest, TryCallResult},
 };
 use function_name::named;
 use massa_proto_rs::massa::{
@@ -9,9 +10,9 @@ use massa_proto_rs::massa::{
 };
 use wasmer::{imports, AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports};
 
-use crate::Interface;
 #[cfg(feature = "execution-trace")]
 use crate::{into_trace_value, AbiTrace, AbiTraceType};
+use crate::{Interface, VMError};
 #[cfg(feature = "execution-trace")]
 use rust_decimal::prelude::ToPrimitive;
 #[cfg(feature = "execution-trace")]
@@ -117,6 +118,7 @@ pub fn register_abis(store: &mut impl AsStoreMut, shared_abi_env: ABIEnv) -> Imp
         "abi_set_ds_value" => abi_set_ds_value,
         "abi_sub_native_amount" => abi_sub_native_amount,
         "abi_transfer_coins" => abi_transfer_coins,
+        "abi_try_call" => abi_try_call,
         "abi_unsafe_random" => abi_unsafe_random,
         "abi_verify_signature" => abi_verify_signature,
         "abi_evm_verify_signature" => abi_evm_verify_signature,
@@ -144,6 +146,10 @@ fn abi_call(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<i32, W
             let amount = req
                 .call_coins
                 .ok_or_else(|| WasmV1Error::RuntimeError("No coins provided".into()))?;
+            // sending coins modifies the ledger
+            if amount.mantissa != 0 {
+                handler.check_writable(function_name!())?;
+            }
 
             #[cfg(feature = "execution-trace")]
             let amount_ = Decimal::try_from_i128_with_scale(amount.mantissa as i128, amount.scale)
@@ -161,20 +167,29 @@ fn abi_call(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<i32, W
             interface.increment_recursion_counter().map_err(|e| {
                 WasmV1Error::RuntimeError(format!("Could not increment recursion counter: {}", e))
             })?;
-            let response = crate::execution::run_function(
-                interface,
-                module,
-                &req.target_function_name,
-                &req.function_arg,
-                remaining_gas,
-                handler.get_gas_costs().clone(),
-                handler.get_condom_limits().clone(),
-            )
-            .map_err(|err| WasmV1Error::RuntimeError(format!("Could not run function: {}", err)))?;
+            let response = match handler.with_checkpoint(|| {
+                crate::execution::run_sub_function(
+                    interface,
+                    module,
+                    &req.target_function_name,
+                    &req.function_arg,
+                    remaining_gas,
+                    handler.get_config(),
+                    handler.get_deadline(),
+                )
+            })? {
+                Ok(response) => response,
+                Err(failure) => return Err(handler.sub_execution_failed(failure)),
+            };
             interface.decrement_recursion_counter().map_err(|e| {
                 WasmV1Error::RuntimeError(format!("Could not decrement recursion counter: {}", e))
             })?;
             handler.set_remaining_gas(response.remaining_gas);
+            #[cfg(feature = "profiling")]
+            handler
+                .exec_env
+                .profiler
+                .add_sub_execution(response.profile.clone());
             let interface = handler.exec_env.get_interface();
             interface.finish_call().map_err(|err| {
                 WasmV1Error::RuntimeError(format!("Could not finish call: {}", err))
@@ -182,21 +197,116 @@ fn abi_call(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<i32, W
 
             #[cfg(feature = "execution-trace")]
             {
-                if let Some(exec_env) = handler.store_env.data_mut().lock().as_mut() {
-                    exec_env.trace.push(AbiTrace {
+                handler.exec_env.trace.push(AbiTrace {
+                    name: function_name!().to_string(),
+                    params: vec![
+                        into_trace_value!(req.target_sc_address),
+                        into_trace_value!(req.target_function_name),
+                        into_trace_value!(req.function_arg),
+                        into_trace_value!(amount_.to_i64().unwrap()),
+                    ],
+                    return_value: AbiTraceType::ByteArray(response.ret.clone()),
+                    sub_calls: Some(response.trace),
+                    ..Default::default()
+                });
+            }
+            Ok(CallResponse { data: response.ret })
+        },
+    )
+}
+
+/// Call another smart contract, returning its failure instead of failing
+/// with it. The changes made by a failed callee are rolled back. A timeout
+/// still fails the caller, as the deadline is the one of the whole execution.
+#[named]
+fn abi_try_call(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<i32, WasmV1Error> {
+    handle_abi(
+        function_name!(),
+        store_env,
+        arg_offset,
+        |handler, req: TryCallRequest| {
+            let amount = req
+                .call_coins
+                .ok_or_else(|| WasmV1Error::RuntimeError("No coins provided".into()))?;
+            // sending coins modifies the ledger
+            if amount.mantissa != 0 {
+                handler.check_writable(function_name!())?;
+            }
+            let remaining_gas = handler.get_remaining_gas();
+            let limit = req.max_gas.min(remaining_gas);
+
+            let outcome = handler.with_checkpoint(|| {
+                crate::execution::try_call(
+                    handler.exec_env.get_interface(),
+                    |interface| interface.init_call_wasmv1(&req.target_sc_address, amount),
+                    &req.target_function_name,
+                    &req.function_arg,
+                    limit,
+                    handler.get_config(),
+                    handler.get_deadline(),
+                )
+            })?;
+            let gas_used = match outcome {
+                Err(failure) if matches!(failure.error, VMError::Timeout { .. }) => {
+                    return Err(handler.sub_execution_failed(failure))
+                }
+                Ok(ref response) => limit.saturating_sub(response.remaining_gas),
+                Err(ref failure) => failure.gas_usage().gas_used,
+            };
+            handler.set_remaining_gas(remaining_gas.saturating_sub(gas_used));
+            #[cfg(feature = "profiling")]
+            if let Ok(response) = &outcome {
+                handler
+                    .exec_env
+                    .profiler
+                    .add_sub_execution(response.profile.clone());
+            }
+
+            let result = match outcome {
+                Ok(response) => {
+                    #[cfg(feature = "execution-trace")]
+                    handler.exec_env.trace.push(AbiTrace {
                         name: function_name!().to_string(),
                         params: vec![
                             into_trace_value!(req.target_sc_address),
                             into_trace_value!(req.target_function_name),
                             into_trace_value!(req.function_arg),
-                            into_trace_value!(amount_.to_i64().unwrap()),
+                            into_trace_value!(req.max_gas),
                         ],
                         return_value: AbiTraceType::ByteArray(response.ret.clone()),
                         sub_calls: Some(response.trace),
+                        ..Default::default()
                     });
+                    TryCallResult {
+                        data: response.ret,
+                        error: None,
+                    }
                 }
-            }
-            Ok(CallResponse { data: response.ret })
+                Err(failure) => {
+                    #[cfg(feature = "execution-trace")]
+                    handler.exec_env.trace.push(AbiTrace {
+                        name: function_name!().to_string(),
+                        params: vec![
+                            into_trace_value!(req.target_sc_address),
+                            into_trace_value!(req.target_function_name),
+                            into_trace_value!(req.function_arg),
+                            into_trace_value!(req.max_gas),
+                        ],
+                        return_value: AbiTraceType::ByteArray(Vec::new()),
+                        sub_calls: Some(failure.trace),
+                        ..Default::default()
+                    });
+                    TryCallResult {
+                        data: Vec::new(),
+                        error: Some(TryCallError {
+                            kind: failure.error.kind().to_string(),
+                            message: failure.error.to_string(),
+                            gas_used,
+                        }),
+                    }
+                }
+            };
+            Ok(result)
         },
     )
 }
@@ -217,35 +327,43 @@ fn abi_local_call(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<
             interface.increment_recursion_counter().map_err(|e| {
                 WasmV1Error::RuntimeError(format!("Could not increment recursion counter: {}", e))
             })?;
-            let response = crate::execution::run_function(
-                interface,
-                module,
-                &req.target_function_name,
-                &req.function_arg,
-                remaining_gas,
-                handler.get_gas_costs().clone(),
-                handler.get_condom_limits().clone(),
-            )
-            .map_err(|err| WasmV1Error::RuntimeError(format!("Could not run function: {}", err)))?;
+            let response = match handler.with_checkpoint(|| {
+                crate::execution::run_sub_function(
+                    interface,
+                    module,
+                    &req.target_function_name,
+                    &req.function_arg,
+                    remaining_gas,
+                    handler.get_config(),
+                    handler.get_deadline(),
+                )
+            })? {
+                Ok(response) => response,
+                Err(failure) => return Err(handler.sub_execution_failed(failure)),
+            };
             interface.decrement_recursion_counter().map_err(|e| {
                 WasmV1Error::RuntimeError(format!("Could not decrement recursion counter: {}", e))
             })?;
             handler.set_remaining_gas(response.remaining_gas);
+            #[cfg(feature = "profiling")]
+            handler
+                .exec_env
+                .profiler
+                .add_sub_execution(response.profile.clone());
 
             #[cfg(feature = "execution-trace")]
             {
-                if let Some(exec_env) = handler.store_env.data_mut().lock().as_mut() {
-                    exec_env.trace.push(AbiTrace {
-                        name: function_name!().to_string(),
-                        params: vec![
-                            into_trace_value!(bytecode),
-                            into_trace_value!(req.target_function_name),
-                            into_trace_value!(req.function_arg),
-                        ],
-                        return_value: AbiTraceType::ByteArray(response.ret.clone()),
-                        sub_calls: Some(response.trace),
-                    });
-                }
+                handler.exec_env.trace.push(AbiTrace {
+                    name: function_name!().to_string(),
+                    params: vec![
+                        into_trace_value!(bytecode),
+                        into_trace_value!(req.target_function_name),
+                        into_trace_value!(req.function_arg),
+                    ],
+                    return_value: AbiTraceType::ByteArray(response.ret.clone()),
+                    sub_calls: Some(response.trace),
+                    ..Default::default()
+                });
             }
 
             Ok(CallResponse { data: response.ret })
@@ -261,6 +379,7 @@ fn abi_create_sc(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<i
         store_env,
         arg_offset,
         |handler, req: CreateScRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let interface = handler.exec_env.get_interface();
             match interface.create_module(&req.bytecode) {
                 Ok(sc_address) => {
@@ -385,6 +504,7 @@ fn abi_transfer_coins(
         store_env,
         arg_offset,
         |handler, req: TransferCoinsRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let Some(amount) = req.amount_to_transfer else {
                 return resp_err!("No coins provided");
             };
@@ -428,12 +548,12 @@ fn abi_transfer_coins(
                         };
                         params.push(into_trace_value!(sender_address));
 
-                        // let mut guard = handler.store_env.data_mut().lock();
                         handler.exec_env.trace.push(AbiTrace {
                             name: function_name!().to_string(),
                             params,
                             return_value: AbiTraceType::None,
                             sub_calls: None,
+                            ..Default::default()
                         });
                     }
                     resp_ok!(TransferCoinsResult, {})
@@ -472,9 +592,9 @@ fn abi_abort(store_env: FunctionEnvMut<ABIEnv>, arg_offset: i32) -> Result<i32,
         store_env,
         arg_offset,
         |_handler, req: Vec<u8>| -> Result<Vec<u8>, WasmV1Error> {
-            let msg = format!("Guest program abort: {}", String::from_utf8_lossy(&req));
-
-            Err(WasmV1Error::RuntimeError(msg))
+            Err(WasmV1Error::GuestAbort(
+                String::from_utf8_lossy(&req).into_owned(),
+            ))
         },
     )
 }
@@ -489,6 +609,7 @@ fn abi_set_ds_value(
         store_env,
         arg_offset,
         |handler, req: SetDsValueRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let interface = handler.exec_env.get_interface();
             if let Err(e) = interface.set_ds_value_wasmv1(&req.key, &req.value, None) {
                 return resp_err!(e);
@@ -527,6 +648,7 @@ fn abi_delete_ds_entry(
         store_env,
         arg_offset,
         |handler, req: DeleteDsEntryRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let interface = handler.exec_env.get_interface();
             if let Err(e) = interface.delete_ds_entry_wasmv1(&req.key, req.address) {
                 return resp_err!(e);
@@ -546,6 +668,7 @@ fn abi_append_ds_value(
         store_env,
         arg_offset,
         |handler, req: AppendDsValueRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let interface = handler.exec_env.get_interface();
             if let Err(e) = interface.append_ds_value_wasmv1(&req.key, &req.value, req.address) {
                 return resp_err!(e);
@@ -619,6 +742,7 @@ fn abi_set_bytecode(
         store_env,
         arg_offset,
         |handler, req: SetBytecodeRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let interface = handler.exec_env.get_interface();
             match interface.set_bytecode_wasmv1(&req.bytecode, req.address) {
                 Ok(_) => resp_ok!(SetBytecodeResult, {}),
@@ -937,6 +1061,7 @@ fn abi_deferred_call_cancel(
         store_env,
         arg_offset,
         |handler, req: DeferredCallCancelRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let Some(call_id) = req.call_id else {
                 return resp_err!("Call ID is required");
             };
@@ -947,14 +1072,13 @@ fn abi_deferred_call_cancel(
                     #[cfg(feature = "execution-trace")]
                     {
                         let params = vec![into_trace_value!(call_id)];
-                        if let Some(exec_env) = handler.store_env.data_mut().lock().as_mut() {
-                            exec_env.trace.push(AbiTrace {
-                                name: function_name!().to_string(),
-                                params,
-                                return_value: AbiTraceType::None,
-                                sub_calls: None,
-                            });
-                        }
+                        handler.exec_env.trace.push(AbiTrace {
+                            name: function_name!().to_string(),
+                            params,
+                            return_value: AbiTraceType::None,
+                            sub_calls: None,
+                            ..Default::default()
+                        });
                     }
 
                     resp_ok!(DeferredCallCancelResult, {})
@@ -975,6 +1099,7 @@ fn abi_deferred_call_register(
         store_env,
         arg_offset,
         |handler, req: DeferredCallRegisterRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let Some(target_slot) = req.target_slot else {
                 return resp_err!("Target slot is required");
             };
@@ -1004,14 +1129,13 @@ fn abi_deferred_call_register(
                             into_trace_value!(req.coins),
                             into_trace_value!(req.params),
                         ];
-                        if let Some(exec_env) = handler.store_env.data_mut().lock().as_mut() {
-                            exec_env.trace.push(AbiTrace {
-                                name: function_name!().to_string(),
-                                params,
-                                return_value: AbiTraceType::String(call_id.clone()),
-                                sub_calls: None,
-                            });
-                        }
+                        handler.exec_env.trace.push(AbiTrace {
+                            name: function_name!().to_string(),
+                            params,
+                            return_value: AbiTraceType::String(call_id.clone()),
+                            sub_calls: None,
+                            ..Default::default()
+                        });
                     }
 
                     resp_ok!(DeferredCallRegisterResult, {call_id: Some(call_id)})
@@ -1090,6 +1214,7 @@ fn abi_send_async_message(
         store_env,
         arg_offset,
         |handler, req: SendAsyncMessageRequest| -> Result<AbiResponse, WasmV1Error> {
+            handler.check_writable(function_name!())?;
             let Some(start) = req.validity_start else {
                 return resp_err!("Validity start slot is required");
             };
@@ -1141,14 +1266,13 @@ fn abi_send_async_message(
                             // filter key
                             into_trace_value!(filter_key),
                         ];
-                        if let Some(exec_env) = handler.store_env.data_mut().lock().as_mut() {
-                            exec_env.trace.push(AbiTrace {
-                                name: function_name!().to_string(),
-                                params,
-                                return_value: AbiTraceType::None,
-                                sub_calls: None,
-                            });
-                        }
+                        handler.exec_env.trace.push(AbiTrace {
+                            name: function_name!().to_string(),
+                            params,
+                            return_value: AbiTraceType::None,
+                            sub_calls: None,
+                            ..Default::default()
+                        });
                     }
 
                     resp_ok!(SendAsyncMessageResult, {})
@@ -1197,15 +1321,17 @@ fn abi_local_execution(
             interface.increment_recursion_counter().map_err(|e| {
                 WasmV1Error::RuntimeError(format!("Could not increment recursion counter: {}", e))
             })?;
-            match crate::execution::run_function(
-                interface,
-                module,
-                &req.target_function_name,
-                &req.function_arg,
-                remaining_gas,
-                handler.get_gas_costs().clone(),
-                handler.get_condom_limits().clone(),
-            ) {
+            match handler.with_checkpoint(|| {
+                crate::execution::run_sub_function(
+                    interface,
+                    module,
+                    &req.target_function_name,
+                    &req.function_arg,
+                    remaining_gas,
+                    handler.get_config(),
+                    handler.get_deadline(),
+                )
+            })? {
                 Ok(response) => {
                     interface.decrement_recursion_counter().map_err(|e| {
                         WasmV1Error::RuntimeError(format!(
@@ -1214,21 +1340,25 @@ fn abi_local_execution(
                         ))
                     })?;
                     handler.set_remaining_gas(response.remaining_gas);
+                    #[cfg(feature = "profiling")]
+                    handler
+                        .exec_env
+                        .profiler
+                        .add_sub_execution(response.profile.clone());
 
                     #[cfg(feature = "execution-trace")]
                     {
-                        if let Some(exec_env) = handler.store_env.data_mut().lock().as_mut() {
-                            exec_env.trace.push(AbiTrace {
-                                name: function_name!().to_string(),
-                                params: vec![
-                                    into_trace_value!(req.bytecode),
-                                    into_trace_value!(req.target_function_name),
-                                    into_trace_value!(req.function_arg),
-                                ],
-                                return_value: AbiTraceType::ByteArray(response.ret.clone()),
-                                sub_calls: Some(response.trace),
-                            });
-                        }
+                        handler.exec_env.trace.push(AbiTrace {
+                            name: function_name!().to_string(),
+                            params: vec![
+                                into_trace_value!(req.bytecode),
+                                into_trace_value!(req.target_function_name),
+                                into_trace_value!(req.function_arg),
+                            ],
+                            return_value: AbiTraceType::ByteArray(response.ret.clone()),
+                            sub_calls: Some(response.trace),
+                            ..Default::default()
+                        });
                     }
 
                     resp_ok!(LocalExecutionResponse, { data: response.ret })
@@ -1,8 +1,15 @@
 use super::super::env::{ABIEnv, ExecutionEnv};
-use crate::{wasmv1_execution::WasmV1Error, CondomLimits, GasCosts};
+use crate::middlewares::watchdog::Deadline;
+use crate::{
+    wasmv1_execution::WasmV1Error, ExecutionConfig, ExecutionFailure, GasCosts, GasUsage, VMError,
+};
 use std::io::Cursor;
+use std::sync::Arc;
 use wasmer::FunctionEnvMut;
 
+#[cfg(feature = "execution-trace")]
+use crate::{trace::AbiCall, AbiTrace, AbiTraceType};
+
 /// Handle an ABI call by providing helpers to read arguments, return values,
 /// and so on
 pub fn handle_abi<F, Req, Resp>(
@@ -29,19 +36,69 @@ where
         exec_env,
     };
 
+    #[cfg(feature = "execution-trace")]
+    let abi_call = handler.start_abi_call(abi_name);
+
+    let result = run_abi(&mut handler, abi_name, arg_offset, func);
+
+    #[cfg(feature = "execution-trace")]
+    if let Some((abi_call, trace_len)) = abi_call {
+        handler.finish_abi_call(abi_call, trace_len, &result);
+    }
+
+    result
+}
+
+/// Run an ABI call for `handle_abi`
+fn run_abi<F, Req, Resp>(
+    handler: &mut ABIHandler,
+    abi_name: &str,
+    arg_offset: i32,
+    func: F,
+) -> Result<i32, WasmV1Error>
+where
+    F: FnOnce(&mut ABIHandler, Req) -> Result<Resp, WasmV1Error>,
+    Req: prost::Message + Default,
+    Resp: prost::Message,
+{
+    // interrupt the call once its time budget is exhausted
+    handler.check_deadline()?;
+
+    #[cfg(feature = "profiling")]
+    handler.profile_abi_entry(abi_name);
+
     // apply gas cost
     let gas_cost = handler.get_gas_cost(abi_name);
     if gas_cost > 0 {
         handler.try_subtract_gas(gas_cost)?;
     }
 
-    // read argument
-    let arg: Req = handler.read_arg(arg_offset)?;
+    // read argument and charge its size
+    let arg_bytes = handler.read_arg_raw(arg_offset)?;
+    let arg: Req = ABIHandler::decode_arg(&arg_bytes)?;
+    handler.charge_arg_size(abi_name, arg_bytes.len())?;
 
     // call function
-    let response = func(&mut handler, arg)?;
+    #[cfg(feature = "execution-trace")]
+    let traced_arg = handler
+        .get_config()
+        .abi_trace()
+        .then(|| (handler.exec_env.trace.len(), arg_bytes));
+    let response = func(handler, arg)?;
+    handler.check_deadline()?;
+
+    #[cfg(feature = "execution-trace")]
+    if let Some((trace_len, arg_bytes)) = traced_arg {
+        handler.trace_abi(
+            abi_name,
+            trace_len,
+            arg_bytes.into(),
+            response.encode_to_vec().into(),
+        );
+    }
 
-    // return value
+    // charge the response size and return value
+    handler.charge_return_size(abi_name, response.encoded_len())?;
     handler.return_value(response)
 }
 
@@ -63,7 +120,6 @@ where
     let exec_env = env_lock.as_mut().ok_or_else(|| {
         WasmV1Error::InstanciationError("ABIs cannot be called at initialization time.".into())
     })?;
-    // let interface = exec_env.get_interface_mut();
 
     // create handler
     let mut handler = ABIHandler {
@@ -71,19 +127,61 @@ where
         exec_env,
     };
 
+    #[cfg(feature = "execution-trace")]
+    let abi_call = handler.start_abi_call(abi_name);
+
+    let result = run_abi_raw(&mut handler, abi_name, arg_offset, func);
+
+    #[cfg(feature = "execution-trace")]
+    if let Some((abi_call, trace_len)) = abi_call {
+        handler.finish_abi_call(abi_call, trace_len, &result);
+    }
+
+    result
+}
+
+/// Run an ABI call for `handle_abi_raw`
+fn run_abi_raw<F>(
+    handler: &mut ABIHandler,
+    abi_name: &str,
+    arg_offset: i32,
+    func: F,
+) -> Result<i32, WasmV1Error>
+where
+    F: FnOnce(&mut ABIHandler, Vec<u8>) -> Result<Vec<u8>, WasmV1Error>,
+{
+    // interrupt the call once its time budget is exhausted
+    handler.check_deadline()?;
+
+    #[cfg(feature = "profiling")]
+    handler.profile_abi_entry(abi_name);
+
     // apply gas cost
     let gas_cost = handler.get_gas_cost(abi_name);
     if gas_cost > 0 {
         handler.try_subtract_gas(gas_cost)?;
     }
 
-    // read argument
+    // read argument and charge its size
     let arg: Vec<u8> = handler.read_arg_raw(arg_offset)?;
+    handler.charge_arg_size(abi_name, arg.len())?;
 
     // call function
-    let response = func(&mut handler, arg)?;
+    #[cfg(feature = "execution-trace")]
+    let traced_arg = handler
+        .get_config()
+        .abi_trace()
+        .then(|| (handler.exec_env.trace.len(), arg.clone()));
+    let response = func(handler, arg)?;
+    handler.check_deadline()?;
 
-    // return value
+    #[cfg(feature = "execution-trace")]
+    if let Some((trace_len, arg)) = traced_arg {
+        handler.trace_abi(abi_name, trace_len, arg.into(), response.clone().into());
+    }
+
+    // charge the response size and return value
+    handler.charge_return_size(abi_name, response.len())?;
     handler.return_value_raw(&response)
 }
 
@@ -94,18 +192,12 @@ pub struct ABIHandler<'a, 'b> {
 }
 
 impl<'a, 'b> ABIHandler<'a, 'b> {
-    /// Read argument
-    pub fn read_arg<M>(&mut self, arg_offset: i32) -> Result<M, WasmV1Error>
+    /// Decode an argument read with `read_arg_raw`
+    pub fn decode_arg<M>(byte_vec: &[u8]) -> Result<M, WasmV1Error>
     where
         M: prost::Message + Default,
     {
-        let byte_vec = self
-            .exec_env
-            .take_buffer(&mut self.store_env, arg_offset)
-            .map_err(|err| {
-                WasmV1Error::RuntimeError(format!("Could not read ABI argument: {}", err))
-            })?;
-        M::decode(&mut Cursor::new(&byte_vec)).map_err(|err| {
+        M::decode(&mut Cursor::new(byte_vec)).map_err(|err| {
             WasmV1Error::RuntimeError(format!("Could not deserialize ABI argument: {}", err))
         })
     }
@@ -155,6 +247,140 @@ impl<'a, 'b> ABIHandler<'a, 'b> {
             .map_err(|err| WasmV1Error::RuntimeError(format!("ABI gas error: {}", err)))
     }
 
+    /// Trace an ABI call with its request and response, the encoded protobuf
+    /// messages for the ABIs exchanging them. Nothing is traced if the ABI
+    /// recorded a trace of its own since the trace had `trace_len` entries.
+    #[cfg(feature = "execution-trace")]
+    fn trace_abi(
+        &mut self,
+        abi_name: &str,
+        trace_len: usize,
+        request: AbiTraceType,
+        response: AbiTraceType,
+    ) {
+        if self.exec_env.trace.len() > trace_len {
+            return;
+        }
+        self.exec_env.trace.push(AbiTrace {
+            name: abi_name.to_string(),
+            params: vec![("request", request).into()],
+            return_value: response,
+            sub_calls: None,
+            ..Default::default()
+        });
+    }
+
+    /// Start recording an ABI call, along with the length of the trace
+    /// before it
+    #[cfg(feature = "execution-trace")]
+    fn start_abi_call(&mut self, abi_name: &str) -> Option<(AbiCall, usize)> {
+        if !self.get_config().abi_trace() {
+            return None;
+        }
+        let abi_call = AbiCall::start(abi_name, self.get_remaining_gas());
+        Some((abi_call, self.exec_env.trace.len()))
+    }
+
+    /// Record the gas, duration and outcome of an ABI call in the traces it
+    /// produced, or in a trace of its own if it failed before producing any
+    #[cfg(feature = "execution-trace")]
+    fn finish_abi_call(
+        &mut self,
+        abi_call: AbiCall,
+        trace_len: usize,
+        result: &Result<i32, WasmV1Error>,
+    ) {
+        let gas_after = self.get_remaining_gas();
+        let depth = self.get_config().call_depth();
+        let error = result.as_ref().err().map(|err| err.to_string());
+        match error {
+            Some(error) if self.exec_env.trace.len() == trace_len => {
+                let mut trace = abi_call.failed(gas_after, depth, error);
+                // the trace of the sub-execution that made the call fail
+                trace.sub_calls = self
+                    .exec_env
+                    .sub_failure
+                    .as_ref()
+                    .map(|failure| failure.trace.clone());
+                self.exec_env.trace.push(trace);
+            }
+            error => {
+                for trace in &mut self.exec_env.trace[trace_len..] {
+                    abi_call.finish(trace, gas_after, depth, error.clone());
+                }
+            }
+        }
+    }
+
+    /// Run a sub-execution between a checkpoint of the interface state, see
+    /// `execution::with_checkpoint`
+    pub fn with_checkpoint<T>(
+        &self,
+        sub_execution: impl FnOnce() -> Result<T, ExecutionFailure>,
+    ) -> Result<Result<T, ExecutionFailure>, WasmV1Error> {
+        crate::execution::with_checkpoint(self.exec_env.get_interface(), sub_execution).map_err(
+            |error| {
+                WasmV1Error::Execution(Box::new(VMError::Interface {
+                    error: Arc::new(error),
+                    gas: GasUsage::default(),
+                }))
+            },
+        )
+    }
+
+    /// Keep the failure of a sub-execution to report it along with the
+    /// failure of the current execution
+    pub fn sub_execution_failed(&mut self, failure: ExecutionFailure) -> WasmV1Error {
+        let error = failure.error.clone();
+        self.exec_env.sub_failure = Some(failure);
+        WasmV1Error::Execution(Box::new(error))
+    }
+
+    /// Record the entry in an ABI for the gas profile
+    #[cfg(feature = "profiling")]
+    pub fn profile_abi_entry(&mut self, abi_name: &str) {
+        let remaining_gas = self.get_remaining_gas();
+        self.exec_env
+            .profiler
+            .enter_abi(&mut self.store_env, abi_name, remaining_gas);
+    }
+
+    /// Fail once the time budget of the call is exhausted
+    pub fn check_deadline(&self) -> Result<(), WasmV1Error> {
+        match self.get_deadline() {
+            Some(deadline) => deadline
+                .check()
+                .map_err(|err| WasmV1Error::Execution(Box::new(err))),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if the state-mutating ABI `abi_name` is called in a read-only
+    /// call
+    pub fn check_writable(&self, abi_name: &str) -> Result<(), WasmV1Error> {
+        self.get_config()
+            .check_writable(abi_name)
+            .map_err(|err| WasmV1Error::Execution(Box::new(err)))
+    }
+
+    /// Charge the per-byte cost of an ABI argument of `len` bytes
+    pub fn charge_arg_size(&mut self, abi_name: &str, len: usize) -> Result<(), WasmV1Error> {
+        let gas_cost = self.get_gas_costs().get_abi_read_cost(abi_name, len);
+        if gas_cost > 0 {
+            self.try_subtract_gas(gas_cost)?;
+        }
+        Ok(())
+    }
+
+    /// Charge the per-byte cost of an ABI return value of `len` bytes
+    pub fn charge_return_size(&mut self, abi_name: &str, len: usize) -> Result<(), WasmV1Error> {
+        let gas_cost = self.get_gas_costs().get_abi_write_cost(abi_name, len);
+        if gas_cost > 0 {
+            self.try_subtract_gas(gas_cost)?;
+        }
+        Ok(())
+    }
+
     /// Get remaining gas
     pub fn get_remaining_gas(&mut self) -> u64 {
         self.exec_env.get_remaining_gas(&mut self.store_env)
@@ -180,9 +406,14 @@ impl<'a, 'b> ABIHandler<'a, 'b> {
             .unwrap_or(&0)
     }
 
-    /// Get condom limits
-    pub fn get_condom_limits(&self) -> &CondomLimits {
-        self.exec_env.get_condom_limits()
+    /// Get the runtime settings
+    pub fn get_config(&self) -> &ExecutionConfig {
+        self.exec_env.get_config()
+    }
+
+    /// Get the deadline of the call, inherited by the sub-executions
+    pub(crate) fn get_deadline(&self) -> Option<Deadline> {
+        self.exec_env.get_deadline()
     }
 
     /// Get the memory maximum size in bytes
@@ -0,0 +1,42 @@
+//! Messages of the ABIs that are not part of massa-proto-rs yet, encoded
+//! with the same protobuf wire format.
+
+use massa_proto_rs::massa::model::v1::NativeAmount;
+
+/// Request of `abi_try_call`: `CallRequest` with a gas limit for the callee
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TryCallRequest {
+    #[prost(string, tag = "1")]
+    pub target_sc_address: String,
+    #[prost(string, tag = "2")]
+    pub target_function_name: String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub function_arg: Vec<u8>,
+    #[prost(message, optional, tag = "4")]
+    pub call_coins: Option<NativeAmount>,
+    /// capped at the remaining gas of the caller
+    #[prost(uint64, tag = "5")]
+    pub max_gas: u64,
+}
+
+/// Failure of the callee of `abi_try_call`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TryCallError {
+    /// name of the `VMError` variant, e.g. `OutOfGas` or `Trap`
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(uint64, tag = "3")]
+    pub gas_used: u64,
+}
+
+/// Response of `abi_try_call`: the data returned by the callee, or its
+/// failure
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TryCallResult {
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub error: Option<TryCallError>,
+}
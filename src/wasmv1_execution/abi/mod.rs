@@ -1,4 +1,7 @@
 mod abis;
 mod handler;
+mod messages;
 
 pub use abis::register_abis;
+#[cfg(all(test, feature = "testing"))]
+pub(crate) use messages::{TryCallRequest, TryCallResult};
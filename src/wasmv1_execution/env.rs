@@ -1,13 +1,19 @@
 use std::sync::Arc;
 
 use super::{ffi::Ffi, WasmV1Error};
-use crate::GasCosts;
-use crate::{types::Interface, CondomLimits};
+use crate::linker::check_imports;
+use crate::middlewares::watchdog::{self, Deadline};
+use crate::types::Interface;
+use crate::{ExecutionConfig, ExecutionFailure, GasCosts, ModuleKind, VMError};
 use parking_lot::Mutex;
-use wasmer::{AsStoreMut, AsStoreRef, Imports, Instance, InstantiationError, TypedFunction};
+use wasmer::{
+    AsStoreMut, AsStoreRef, ExportError, Imports, Instance, InstantiationError, TypedFunction,
+};
 use wasmer_middlewares::metering::{self, MeteringPoints};
 use wasmer_types::TrapCode;
 
+#[cfg(feature = "profiling")]
+use crate::profiling::Profiler;
 #[cfg(feature = "execution-trace")]
 use crate::AbiTrace;
 
@@ -19,19 +25,23 @@ pub struct ExecutionEnv {
     /// Exposed interface functions used by the ABIs and implemented
     /// externally. In `massa/massa-execution-worker` for example.
     interface: Box<dyn Interface>,
-    /// Gas costs of different execution operations.
-    gas_costs: GasCosts,
+    /// Runtime settings, including the gas costs of the operations.
+    config: ExecutionConfig,
+    /// Deadline of the call, inherited by its nested calls.
+    deadline: Option<Deadline>,
     /// Instance to execute
     pub(crate) instance: Instance,
     /// Memory interface
     ffi: Ffi,
     /// Gas cost of instance creation
     init_gas_cost: u64,
-    /// Maximum number of exports
-    condom_limits: CondomLimits,
 
     #[cfg(feature = "execution-trace")]
     pub trace: Vec<AbiTrace>,
+    /// Failure of the sub-execution that made an ABI call fail
+    pub(crate) sub_failure: Option<ExecutionFailure>,
+    #[cfg(feature = "profiling")]
+    pub(crate) profiler: Profiler,
 }
 
 /// ABI environment giving ABIs access to the interface, gas costs and memory.
@@ -41,10 +51,19 @@ impl ExecutionEnv {
         store: &mut impl AsStoreMut,
         module: &super::WasmV1Module,
         interface: &dyn Interface,
-        gas_costs: GasCosts,
+        config: ExecutionConfig,
+        deadline: Option<Deadline>,
         import_object: &Imports,
-        condom_limits: CondomLimits,
     ) -> Result<Self, WasmV1Error> {
+        // Report every import the ABIs do not provide before instantiating
+        check_imports(
+            &module.binary_module,
+            import_object,
+            &*store,
+            ModuleKind::WasmV1,
+        )
+        .map_err(WasmV1Error::UnresolvedImports)?;
+
         // Create the instance
         let instance = match Instance::new(store, &module.binary_module, import_object) {
             Ok(instance) => instance,
@@ -54,8 +73,8 @@ impl ExecutionEnv {
                 if let InstantiationError::Start(ref e) = err {
                     if let Some(trap) = e.clone().to_trap() {
                         if trap == TrapCode::UnreachableCodeReached && e.trace().is_empty() {
-                            return Err(WasmV1Error::InstanciationError(
-                                "Not enough gas, limit reached at instance creation".to_string(),
+                            return Err(WasmV1Error::OutOfGas(
+                                "limit reached at instance creation".to_string(),
                             ));
                         }
                     }
@@ -67,6 +86,14 @@ impl ExecutionEnv {
             }
         };
 
+        // Interrupt the code that does not call any ABI once the deadline
+        // is passed
+        if let Some(deadline) = deadline {
+            watchdog::arm(store, &instance, deadline).map_err(|err| {
+                WasmV1Error::InstanciationError(format!("Could not arm the watchdog: {}", err))
+            })?;
+        }
+
         // Create FFI for memory access
         let ffi = Ffi::try_new(&instance, store)
             .map_err(|err| WasmV1Error::RuntimeError(format!("Could not create FFI: {}", err)))?;
@@ -82,26 +109,45 @@ impl ExecutionEnv {
                         "Remaining gas after instance creation is higher than the gas limit at compilation",
                     ),
                 MeteringPoints::Exhausted => {
-                    return Err(WasmV1Error::InstanciationError(
-                        "Not enough gas, gas exhausted after instance creation".to_string(),
+                    return Err(WasmV1Error::OutOfGas(
+                        "gas exhausted after instance creation".to_string(),
                     ));
                 }
             };
         }
 
+        #[cfg(feature = "profiling")]
+        let mut profiler = Profiler::default();
+        #[cfg(feature = "profiling")]
+        profiler.init_with_instance(&instance);
+
         // Return the environment
         Ok(Self {
-            gas_costs,
+            config,
+            deadline,
             interface: interface.clone_box(),
             instance,
             ffi,
             init_gas_cost,
-            condom_limits,
             #[cfg(feature = "execution-trace")]
             trace: Default::default(),
+            sub_failure: None,
+            #[cfg(feature = "profiling")]
+            profiler,
         })
     }
 
+    /// Failure of the execution, with the trace recorded until then
+    pub(crate) fn failure(&mut self, error: VMError) -> ExecutionFailure {
+        #[allow(unused_mut)]
+        let mut failure = ExecutionFailure::from(error).with_sub_failure(self.sub_failure.take());
+        #[cfg(feature = "execution-trace")]
+        if self.config.abi_trace() {
+            failure.trace = std::mem::take(&mut self.trace);
+        }
+        failure
+    }
+
     /// Get gas cost of instance creation
     pub fn get_init_gas_cost(&self) -> u64 {
         self.init_gas_cost
@@ -121,11 +167,12 @@ impl ExecutionEnv {
         self.instance
             .exports
             .get_typed_function::<i32, i32>(&store, function_name)
-            .map_err(|err| {
-                WasmV1Error::RuntimeError(format!(
+            .map_err(|err| match err {
+                ExportError::Missing(_) => WasmV1Error::FunctionNotFound(function_name.to_string()),
+                err => WasmV1Error::RuntimeError(format!(
                     "Error getting typed guest function {}: {}",
                     function_name, err
-                ))
+                )),
             })
     }
 
@@ -142,18 +189,14 @@ impl ExecutionEnv {
         let remaining = match metering::get_remaining_points(store, &self.instance) {
             metering::MeteringPoints::Remaining(remaining) => remaining,
             metering::MeteringPoints::Exhausted => {
-                return Err(WasmV1Error::RuntimeError(
-                    "Gas exhausted before ABI call".into(),
+                return Err(WasmV1Error::OutOfGas(
+                    "gas exhausted before ABI call".into(),
                 ))
             }
         };
         let new_remaining = match remaining.checked_sub(gas) {
             Some(v) => v,
-            None => {
-                return Err(WasmV1Error::RuntimeError(
-                    "Gas exhausted after ABI call".into(),
-                ))
-            }
+            None => return Err(WasmV1Error::OutOfGas("gas exhausted after ABI call".into())),
         };
         metering::set_remaining_points(store, &self.instance, new_remaining);
         Ok(())
@@ -171,6 +214,15 @@ impl ExecutionEnv {
         }
     }
 
+    /// Whether the metering points are exhausted.
+    pub fn is_gas_exhausted(&self, store: &mut impl AsStoreMut) -> bool {
+        cfg!(not(feature = "gas_calibration"))
+            && matches!(
+                metering::get_remaining_points(store, &self.instance),
+                metering::MeteringPoints::Exhausted
+            )
+    }
+
     /// Set remaining gas.
     pub fn set_remaining_gas(&self, store: &mut impl AsStoreMut, remaining_gas: u64) {
         if cfg!(not(feature = "gas_calibration")) {
@@ -200,16 +252,26 @@ impl ExecutionEnv {
 
     /// Get gas costs.
     pub fn get_gas_costs(&self) -> &GasCosts {
-        &self.gas_costs
+        self.config.gas_costs()
     }
 
-    /// Get the condom limits.
-    pub fn get_condom_limits(&self) -> &CondomLimits {
-        &self.condom_limits
+    /// Get the runtime settings.
+    pub fn get_config(&self) -> &ExecutionConfig {
+        &self.config
+    }
+
+    /// Get the deadline of the call.
+    pub(crate) fn get_deadline(&self) -> Option<Deadline> {
+        self.deadline
     }
 
     /// Get the memory maximum size in bytes
     pub fn get_max_mem_size(&self, store: &mut impl AsStoreMut) -> u64 {
         self.ffi.get_max_mem_size(&store)
     }
+
+    /// Get the memory size in pages
+    pub fn get_mem_pages(&self, store: &impl AsStoreRef) -> u32 {
+        self.ffi.get_mem_pages(store)
+    }
 }
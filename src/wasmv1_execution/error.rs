@@ -1,3 +1,5 @@
+use crate::linker::ImportReport;
+use crate::VMError;
 use displaydoc::Display;
 use thiserror::Error;
 
@@ -7,4 +9,14 @@ pub enum WasmV1Error {
     RuntimeError(String),
     /// Instanciation error: {0}
     InstanciationError(String),
+    /// Not enough gas: {0}
+    OutOfGas(String),
+    /// Guest program abort: {0}
+    GuestAbort(String),
+    /// Function not found: {0}
+    FunctionNotFound(String),
+    /// Unresolved imports: {0}
+    UnresolvedImports(ImportReport),
+    /// Could not run function: {0}
+    Execution(Box<VMError>),
 }
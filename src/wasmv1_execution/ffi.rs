@@ -58,6 +58,12 @@ impl Ffi {
         self.guest_memory.view(store).data_size()
     }
 
+    /// Get the memory size in pages. Memories never shrink so it is also the
+    /// peak size reached during the execution.
+    pub fn get_mem_pages(&self, store: &impl AsStoreRef) -> u32 {
+        self.guest_memory.view(store).size().0
+    }
+
     /// Reads a buffer and tries to deallocate it guest-side.
     /// Assumes memory layout is: [len: i32 little-endian][data: u8*]
     pub fn take_buffer(
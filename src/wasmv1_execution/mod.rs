@@ -4,16 +4,23 @@ mod error;
 mod ffi;
 
 use self::env::{ABIEnv, ExecutionEnv};
-use crate::error::VMResult;
+use crate::artifact::{settings_fingerprint, ArtifactError, Fingerprint};
+use crate::backtrace::{DebugLines, WasmBacktrace};
+use crate::error::{AbortLocation, ExecutionFailure, GasUsage, VMResult};
 use crate::execution::Compiler;
 use crate::middlewares::condom::CondomMiddleware;
+use crate::middlewares::dynamic_metering::DynamicMetering;
 use crate::middlewares::gas_calibration::{
     get_gas_calibration_result, GasCalibration, GasCalibrationResult,
 };
-use crate::settings::max_number_of_pages;
+#[cfg(feature = "profiling")]
+use crate::middlewares::profiling::Profiling;
+use crate::middlewares::watchdog::{Deadline, Watchdog};
 use crate::tunable_memory::LimitingTunables;
-use crate::{CondomLimits, GasCosts, Interface, Response, VMError};
+use crate::{ExecutionConfig, Interface, ModuleKind, Response, VMError};
 use abi::*;
+#[cfg(all(test, feature = "testing"))]
+pub(crate) use abi::{TryCallRequest, TryCallResult};
 pub(crate) use error::*;
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -33,6 +40,10 @@ pub struct WasmV1Module {
     pub(crate) binary_module: Module,
     pub(crate) gas_limit_at_compilation: u64,
     pub compiler: Compiler,
+    // DWARF line info of the bytecode, not kept in serialized artifacts
+    pub(crate) debug_lines: Option<Arc<DebugLines>>,
+    // Compilation settings baked into the module
+    pub(crate) fingerprint: Fingerprint,
     // Compilation engine can not be dropped
     pub(crate) _engine: Engine,
 }
@@ -41,66 +52,67 @@ impl WasmV1Module {
     pub(crate) fn compile(
         bytecode: &[u8],
         limit: u64,
-        gas_costs: GasCosts,
         compiler: Compiler,
-        condom_limits: CondomLimits,
-    ) -> Result<Self, WasmV1Error> {
+        config: &ExecutionConfig,
+    ) -> VMResult<Self> {
+        let fingerprint = settings_fingerprint(limit, config, ModuleKind::WasmV1);
+        let condom_middleware = Arc::new(CondomMiddleware::new(config.condom_limits().clone()));
         let engine = match compiler {
-            Compiler::CL => init_cl_engine(limit, gas_costs, condom_limits),
-            Compiler::SP => init_sp_engine(limit, gas_costs, condom_limits),
-        };
-        let binary_module = match Module::new(&engine, bytecode) {
-            Ok(module) => module,
-            Err(e) => {
-                return Err(WasmV1Error::InstanciationError(format!(
-                    "Could not compile bytecode: {}",
-                    e
-                )))
-            }
+            Compiler::CL => init_cl_engine(limit, config, condom_middleware.clone()),
+            Compiler::SP => init_sp_engine(limit, config, condom_middleware.clone()),
         };
+        let binary_module =
+            Module::new(&engine, bytecode).map_err(|e| match condom_middleware.take_report() {
+                Some(report) => VMError::ModuleRejected {
+                    report,
+                    gas: GasUsage::default(),
+                },
+                None => VMError::instance_error(format!("Could not compile bytecode: {}", e)),
+            })?;
         Ok(Self {
             binary_module,
             gas_limit_at_compilation: limit,
             compiler,
+            debug_lines: DebugLines::parse(bytecode).map(Arc::new),
+            fingerprint,
             _engine: engine,
         })
     }
 
     /// Serialize a module
-    pub fn serialize(&self) -> Vec<u8> {
-        match self.compiler {
-            Compiler::CL => self
-                .binary_module
-                .serialize()
-                .expect("Could not serialize module")
-                .to_vec(),
-            Compiler::SP => {
-                panic!("cannot serialize a module compiled with Singlepass")
-            }
-        }
+    pub fn serialize(&self) -> VMResult<Vec<u8>> {
+        Ok(self
+            .binary_module
+            .serialize()
+            .map_err(VMError::instance_error)?
+            .to_vec())
     }
 
-    /// Deserialize a module
-    pub fn deserialize(
+    /// Deserialize the payload of a verified artifact
+    pub(crate) fn deserialize(
         ser_module: &[u8],
+        compiler: Compiler,
         limit: u64,
-        gas_costs: GasCosts,
-        condom_limits: CondomLimits,
-    ) -> VMResult<Self> {
-        // Deserialization is only meant for Cranelift modules
-        let engine = init_cl_engine(limit, gas_costs, condom_limits);
+        config: &ExecutionConfig,
+        fingerprint: Fingerprint,
+    ) -> Result<Self, ArtifactError> {
+        let condom_middleware = Arc::new(CondomMiddleware::new(config.condom_limits().clone()));
+        let engine = match compiler {
+            Compiler::CL => init_cl_engine(limit, config, condom_middleware),
+            Compiler::SP => init_sp_engine(limit, config, condom_middleware),
+        };
         let store = Store::new(engine.clone());
         // Unsafe because code injection is possible
-        // That's not an issue because we only deserialize modules we have
-        // serialized by ourselves before. This also means that we
-        // expect the module to be valid and the deserialization to
-        // succeed.
+        // That's not an issue because we only deserialize artifacts whose
+        // frame, checksum and fingerprint have been verified beforehand
         let binary_module = unsafe { Module::deserialize(&store, ser_module) }
-            .expect("Could not deserialize module");
+            .map_err(|e| ArtifactError::Deserialization(e.to_string()))?;
         Ok(WasmV1Module {
             binary_module,
             gas_limit_at_compilation: limit,
-            compiler: Compiler::CL,
+            compiler,
+            debug_lines: None,
+            fingerprint,
             _engine: engine,
         })
     }
@@ -124,7 +136,8 @@ impl WasmV1Module {
 // * https://github.com/WebAssembly/proposals
 //
 // TLDR: Turn off every feature except for `bulk_memory`.
-const FEATURES: Features = Features {
+// Default of `ExecutionConfig`.
+pub(crate) const FEATURES: Features = Features {
     threads: false,         // non-deterministic
     reference_types: false, // could be enabled but we have no need for it atm
     simd: false,            // non-deterministic
@@ -141,8 +154,8 @@ const FEATURES: Features = Features {
 
 pub(crate) fn init_sp_engine(
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
+    condom_middleware: Arc<CondomMiddleware>,
 ) -> Engine {
     // Singlepass is used to compile arbitrary bytecode.
     //
@@ -152,14 +165,18 @@ pub(crate) fn init_sp_engine(
 
     // Canonicalize NaN
     compiler_config.canonicalize_nans(true);
-    add_middleware(&mut compiler_config, limit, gas_costs, condom_limits);
+    add_middleware(&mut compiler_config, limit, config, condom_middleware);
 
     let base = BaseTunables::for_target(&Target::default());
-    let tunables = LimitingTunables::new(base, Pages(max_number_of_pages()));
+    let tunables = LimitingTunables::new(
+        base,
+        Pages(config.max_memory_pages()),
+        config.max_table_elements(),
+    );
 
     let mut engine = Engine::from(
         EngineBuilder::new(compiler_config)
-            .set_features(Some(FEATURES))
+            .set_features(Some(config.features(ModuleKind::WasmV1).clone()))
             .engine(),
     );
     engine.set_tunables(tunables);
@@ -168,8 +185,8 @@ pub(crate) fn init_sp_engine(
 
 pub(crate) fn init_cl_engine(
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
+    condom_middleware: Arc<CondomMiddleware>,
 ) -> Engine {
     // Cranelift is used to compile bytecode that will be cached.
     //
@@ -179,14 +196,18 @@ pub(crate) fn init_cl_engine(
 
     // Canonicalize NaN
     compiler_config.canonicalize_nans(true);
-    add_middleware(&mut compiler_config, limit, gas_costs, condom_limits);
+    add_middleware(&mut compiler_config, limit, config, condom_middleware);
 
     let base = BaseTunables::for_target(&Target::default());
-    let tunables = LimitingTunables::new(base, Pages(max_number_of_pages()));
+    let tunables = LimitingTunables::new(
+        base,
+        Pages(config.max_memory_pages()),
+        config.max_table_elements(),
+    );
 
     let mut engine = Engine::from(
         EngineBuilder::new(compiler_config)
-            .set_features(Some(FEATURES))
+            .set_features(Some(config.features(ModuleKind::WasmV1).clone()))
             .engine(),
     );
     engine.set_tunables(tunables);
@@ -196,13 +217,12 @@ pub(crate) fn init_cl_engine(
 fn add_middleware<T>(
     compiler_config: &mut T,
     limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
+    config: &ExecutionConfig,
+    condom_middleware: Arc<CondomMiddleware>,
 ) where
     T: CompilerConfig,
 {
     // Add condom middleware
-    let condom_middleware = Arc::new(CondomMiddleware::new(condom_limits));
     compiler_config.push_middleware(condom_middleware);
 
     if cfg!(feature = "gas_calibration") {
@@ -211,10 +231,28 @@ fn add_middleware<T>(
         compiler_config.push_middleware(gas_calibration);
     } else {
         // Add metering middleware
-        let metering = Arc::new(Metering::new(limit, move |_: &Operator| -> u64 {
-            gas_costs.operator_cost
+        let gas_costs = config.gas_costs().clone();
+        let dynamic_metering = gas_costs
+            .has_memory_operator_costs()
+            .then(|| Arc::new(DynamicMetering::new(&gas_costs)));
+        let metering = Arc::new(Metering::new(limit, move |operator: &Operator| -> u64 {
+            gas_costs.get_operator_cost(operator)
         }));
         compiler_config.push_middleware(metering);
+        // Must come after the metering middleware, see `DynamicMetering`
+        if let Some(dynamic_metering) = dynamic_metering {
+            compiler_config.push_middleware(dynamic_metering);
+        }
+        // Must come after the metering middlewares so that its code is not
+        // metered, see `Profiling`
+        #[cfg(feature = "profiling")]
+        compiler_config.push_middleware(Arc::new(Profiling::new()));
+    }
+
+    // Only compiled in when a timeout is set, must come after the metering
+    // middlewares, see `Watchdog`
+    if config.timeout().is_some() {
+        compiler_config.push_middleware(Arc::new(Watchdog::new()));
     }
 }
 
@@ -224,13 +262,14 @@ pub(crate) fn exec_wasmv1_module(
     function: &str,
     param: &[u8],
     gas_limit: u64,
-    gas_costs: GasCosts,
-    condom_limits: CondomLimits,
-) -> VMResult<(Response, Option<GasCalibrationResult>)> {
+    config: &ExecutionConfig,
+    deadline: Option<Deadline>,
+) -> Result<(Response, Option<GasCalibrationResult>), ExecutionFailure> {
     // Init store
+    let condom_middleware = Arc::new(CondomMiddleware::new(config.condom_limits().clone()));
     let engine = match module.compiler {
-        Compiler::CL => init_cl_engine(gas_limit, gas_costs.clone(), condom_limits.clone()),
-        Compiler::SP => init_sp_engine(gas_limit, gas_costs.clone(), condom_limits.clone()),
+        Compiler::CL => init_cl_engine(gas_limit, config, condom_middleware),
+        Compiler::SP => init_sp_engine(gas_limit, config, condom_middleware),
     };
     let mut store = Store::new(engine);
 
@@ -247,15 +286,34 @@ pub(crate) fn exec_wasmv1_module(
         &mut store,
         &module,
         interface,
-        gas_costs,
+        config.clone(),
+        deadline,
         &import_object,
-        condom_limits,
     )
-    .map_err(|err| {
-        VMError::InstanceError(format!(
-            "Failed to create instance of execution environment: {}",
-            err
-        ))
+    .map_err(|err| match err {
+        // the whole limit was consumed by the instance creation
+        WasmV1Error::OutOfGas(_) => VMError::OutOfGas {
+            function: function.to_string(),
+            gas: GasUsage {
+                init_gas_cost: gas_limit,
+                gas_used: gas_limit,
+            },
+        },
+        WasmV1Error::UnresolvedImports(report) => VMError::UnresolvedImports {
+            report,
+            gas: GasUsage::default(),
+        },
+        // the gas consumed by a failed instance creation is unknown
+        err => VMError::InstanceError {
+            error: format!(
+                "Failed to create instance of execution environment: {}",
+                err
+            ),
+            gas: GasUsage {
+                init_gas_cost: gas_limit,
+                gas_used: gas_limit,
+            },
+        },
     })?;
 
     // Get gas cost of instance creation
@@ -266,37 +324,59 @@ pub(crate) fn exec_wasmv1_module(
     let available_gas = match gas_limit.checked_sub(init_gas_cost) {
         Some(remaining_gas) => remaining_gas,
         None => {
-            return Err(VMError::ExecutionError {
-                error: "Available gas does not cover instance creation".to_string(),
-                init_gas_cost,
-            })
+            return Err(VMError::OutOfGas {
+                function: function.to_string(),
+                gas: GasUsage {
+                    init_gas_cost,
+                    gas_used: gas_limit,
+                },
+            }
+            .into())
         }
     };
     execution_env.set_remaining_gas(&mut store, available_gas);
 
     // Get function to execute. Must follow the following prototype: param_addr:
     // i32 -> return_addr: i32
-    let wasm_func =
-        execution_env
-            .get_func(&store, function)
-            .map_err(|err| VMError::ExecutionError {
+    let wasm_func = execution_env
+        .get_func(&store, function)
+        .map_err(|err| match err {
+            WasmV1Error::FunctionNotFound(_) => VMError::FunctionNotFound {
+                function: function.to_string(),
+                gas: GasUsage {
+                    init_gas_cost,
+                    gas_used: init_gas_cost,
+                },
+            },
+            err => VMError::ExecutionError {
                 error: format!(
                     "Could not find guest function {} for call: {}",
                     function, err
                 ),
-                init_gas_cost,
-            })?;
+                gas: GasUsage {
+                    init_gas_cost,
+                    gas_used: init_gas_cost,
+                },
+            },
+        })?;
 
     // Allocate and write function argument to guest memory
-    let param_offset = execution_env
-        .create_buffer(&mut store, param)
-        .map_err(|err| VMError::ExecutionError {
-            error: format!(
-                "Could not write argument for guest call {}: {}",
-                function, err
-            ),
-            init_gas_cost,
-        })?;
+    let param_offset = match execution_env.create_buffer(&mut store, param) {
+        Ok(param_offset) => param_offset,
+        Err(err) => {
+            return Err(VMError::ExecutionError {
+                error: format!(
+                    "Could not write argument for guest call {}: {}",
+                    function, err
+                ),
+                gas: GasUsage {
+                    init_gas_cost,
+                    gas_used: gas_limit.saturating_sub(execution_env.get_remaining_gas(&mut store)),
+                },
+            }
+            .into())
+        }
+    };
 
     // Now that we have an instance, we can make the execution environment
     // available to the ABIs. We avoided setting it before instance creation
@@ -305,30 +385,48 @@ pub(crate) fn exec_wasmv1_module(
     shared_abi_env.lock().replace(execution_env);
 
     // Call func
-    let returned_offset =
-        wasm_func
-            .call(&mut store, param_offset)
-            .map_err(|err| VMError::ExecutionError {
-                error: format!("Error while calling guest function {}: {}", function, err),
-                init_gas_cost,
-            })?;
+    let call_result = wasm_func.call(&mut store, param_offset);
 
     // Take back the execution environment
-    let execution_env = shared_abi_env
+    let mut execution_env = shared_abi_env
         .lock()
         .take()
         .expect("Execution environment unavailable after execution");
 
+    let returned_offset = match call_result {
+        Ok(returned_offset) => returned_offset,
+        Err(err) => {
+            let error = call_error(
+                err,
+                function,
+                GasUsage {
+                    init_gas_cost,
+                    gas_used: gas_limit.saturating_sub(execution_env.get_remaining_gas(&mut store)),
+                },
+                execution_env.is_gas_exhausted(&mut store),
+                module.debug_lines.as_deref(),
+            );
+            return Err(execution_env.failure(error));
+        }
+    };
+
     // Read returned value from guest memory and deallocate it
-    let ret = execution_env
-        .take_buffer(&mut store, returned_offset)
-        .map_err(|err| VMError::ExecutionError {
-            error: format!(
-                "Could not read return value from guest call {}: {}",
-                function, err
-            ),
-            init_gas_cost,
-        })?;
+    let ret = match execution_env.take_buffer(&mut store, returned_offset) {
+        Ok(ret) => ret,
+        Err(err) => {
+            let error = VMError::ExecutionError {
+                error: format!(
+                    "Could not read return value from guest call {}: {}",
+                    function, err
+                ),
+                gas: GasUsage {
+                    init_gas_cost,
+                    gas_used: gas_limit.saturating_sub(execution_env.get_remaining_gas(&mut store)),
+                },
+            };
+            return Err(execution_env.failure(error));
+        }
+    };
 
     // Get remaining gas
     let remaining_gas = execution_env.get_remaining_gas(&mut store);
@@ -348,9 +446,79 @@ pub(crate) fn exec_wasmv1_module(
             ret,
             remaining_gas,
             init_gas_cost,
+            max_memory_pages: config.max_memory_pages(),
+            peak_memory_pages: execution_env.get_mem_pages(&store),
             #[cfg(feature = "execution-trace")]
-            trace: execution_env.trace.clone(),
+            trace: if config.abi_trace() {
+                execution_env.trace.clone()
+            } else {
+                Vec::new()
+            },
+            #[cfg(feature = "profiling")]
+            profile: execution_env
+                .profiler
+                .report(&mut store, &execution_env.instance, function),
         },
         gc_result,
     ))
 }
+
+/// Convert an error raised by a guest function call into a `VMError`
+fn call_error(
+    err: wasmer::RuntimeError,
+    function: &str,
+    gas: GasUsage,
+    gas_exhausted: bool,
+    debug_lines: Option<&DebugLines>,
+) -> VMError {
+    // The metering middleware traps when the points are exhausted
+    if gas_exhausted {
+        return VMError::OutOfGas {
+            function: function.to_string(),
+            gas,
+        };
+    }
+    // raised by the watchdog
+    if let Some(err) = err.downcast_ref::<VMError>() {
+        return err.clone().with_gas(function, gas);
+    }
+    match err.downcast_ref::<WasmV1Error>() {
+        Some(WasmV1Error::OutOfGas(_)) => {
+            return VMError::OutOfGas {
+                function: function.to_string(),
+                gas,
+            }
+        }
+        // keep the typed errors of sub-executions
+        Some(WasmV1Error::Execution(err))
+            if !matches!(
+                **err,
+                VMError::InstanceError { .. }
+                    | VMError::ExecutionError { .. }
+                    | VMError::DepthError { .. }
+            ) =>
+        {
+            return (**err).clone().with_gas(function, gas)
+        }
+        Some(WasmV1Error::GuestAbort(message)) => {
+            return VMError::GuestAbort {
+                message: message.clone(),
+                location: AbortLocation::default(),
+                gas,
+            }
+        }
+        _ => (),
+    }
+    if let Some(code) = err.clone().to_trap() {
+        return VMError::Trap {
+            function: function.to_string(),
+            code,
+            backtrace: WasmBacktrace::new(err.trace(), debug_lines),
+            gas,
+        };
+    }
+    VMError::ExecutionError {
+        error: format!("Error while calling guest function {}: {}", function, err),
+        gas,
+    }
+}